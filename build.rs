@@ -0,0 +1,35 @@
+//! Builds the 1KP sample table in from `snapshot/`, see src/snapshot.rs
+
+use std::path::Path;
+
+const SNAPSHOT_FILES: [&str; 3] = [
+    "DATE",
+    "Sample-List-with-Taxonomy.tsv.gz",
+    "assemblies.html.gz",
+];
+
+/// Set to build without the offline fallback when `snapshot/` is missing
+const NO_SNAPSHOT: &str = "ONEKP_NO_SNAPSHOT";
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(snapshot)");
+    println!("cargo:rerun-if-changed=snapshot");
+    println!("cargo:rerun-if-env-changed={}", NO_SNAPSHOT);
+    let dir =
+        Path::new(&std::env::var("CARGO_MANIFEST_DIR").expect("set by cargo")).join("snapshot");
+    let missing: Vec<&str> = SNAPSHOT_FILES
+        .into_iter()
+        .filter(|f| !dir.join(f).is_file())
+        .collect();
+    if missing.is_empty() {
+        println!("cargo:rustc-cfg=snapshot");
+    } else if std::env::var_os(NO_SNAPSHOT).is_none() {
+        panic!(
+            "{} is missing {}. Download the sample table as shown in src/snapshot.rs, \
+             or set {}=1 to build without the offline fallback.",
+            dir.display(),
+            missing.join(", "),
+            NO_SNAPSHOT
+        );
+    }
+}
@@ -0,0 +1,93 @@
+/// Clades of the 1KP sample list
+pub const CLADES: &[&str] = &[
+    "Basal Eudicots",
+    "Basalmost angiosperms",
+    "Chloranthales",
+    "Chromista (Algae)",
+    "Conifers",
+    "Core Eudicots",
+    "Core Eudicots/Asterids",
+    "Core Eudicots/Rosids",
+    "Cycadales",
+    "Dinophyceae",
+    "Euglenozoa",
+    "Eusporangiate Monilophytes",
+    "Ginkgoales",
+    "Glaucophyta (Algae)",
+    "Gnetales",
+    "Green Algae",
+    "Hornworts",
+    "Leptosporangiate Monilophytes",
+    "Liverworts",
+    "Lycophytes",
+    "Magnoliids",
+    "Monocots",
+    "Monocots/Commelinids",
+    "Mosses",
+    "Red Algae",
+];
+
+/// Common names of plant groups and the 1KP clades they span
+pub const CLADE_ALIASES: &[(&str, &[&str])] = &[
+    ("mosses", &["Mosses"]),
+    ("liverworts", &["Liverworts"]),
+    ("hornworts", &["Hornworts"]),
+    ("bryophytes", &["Mosses", "Liverworts", "Hornworts"]),
+    ("lycophytes", &["Lycophytes"]),
+    (
+        "ferns",
+        &[
+            "Eusporangiate Monilophytes",
+            "Leptosporangiate Monilophytes",
+        ],
+    ),
+    ("conifers", &["Conifers"]),
+    ("cycads", &["Cycadales"]),
+    ("ginkgo", &["Ginkgoales"]),
+    ("gymnosperms", GYMNOSPERMS),
+    ("angiosperms", ANGIOSPERMS),
+    ("flowering plants", ANGIOSPERMS),
+    ("monocots", &["Monocots", "Monocots/Commelinids"]),
+    (
+        "eudicots",
+        &[
+            "Basal Eudicots",
+            "Core Eudicots",
+            "Core Eudicots/Asterids",
+            "Core Eudicots/Rosids",
+        ],
+    ),
+    ("asterids", &["Core Eudicots/Asterids"]),
+    ("rosids", &["Core Eudicots/Rosids"]),
+    ("green algae", &["Green Algae"]),
+    ("red algae", &["Red Algae"]),
+];
+
+const GYMNOSPERMS: &[&str] = &["Conifers", "Cycadales", "Ginkgoales", "Gnetales"];
+
+const ANGIOSPERMS: &[&str] = &[
+    "Basalmost angiosperms",
+    "Chloranthales",
+    "Magnoliids",
+    "Monocots",
+    "Monocots/Commelinids",
+    "Basal Eudicots",
+    "Core Eudicots",
+    "Core Eudicots/Asterids",
+    "Core Eudicots/Rosids",
+];
+
+/// The clades `name` stands for, ignoring case
+///
+/// ```
+/// use onekp::alias::clade_alias;
+///
+/// assert_eq!(clade_alias("Ferns"), Some(&["Eusporangiate Monilophytes", "Leptosporangiate Monilophytes"][..]));
+/// assert_eq!(clade_alias("Magnoliids"), None);
+/// ```
+pub fn clade_alias(name: &str) -> Option<&'static [&'static str]> {
+    CLADE_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(name.trim()))
+        .map(|(_, clades)| *clades)
+}
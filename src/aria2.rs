@@ -0,0 +1,157 @@
+use std::{
+    fs::{remove_file, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    client::{ClientOptions, DEFAULT_USER_AGENT},
+    error::{OneKpError, Result},
+};
+
+/// A file for aria2c: the URLs it can be fetched from and where to write it
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Primary URL followed by its mirrors
+    pub urls: Vec<String>,
+    pub out: PathBuf,
+}
+
+/// aria2c `--input-file` listing every entry,
+/// with its mirrors as tab-separated URIs and `dir`/`out` options
+pub fn input_file(entries: &[Entry]) -> String {
+    let mut text = String::new();
+    for entry in entries.iter() {
+        text.push_str(&entry.urls.join("\t"));
+        text.push('\n');
+        if let Some(dir) = entry.out.parent().filter(|d| !d.as_os_str().is_empty()) {
+            text.push_str(&format!("  dir={}\n", dir.display()));
+        }
+        if let Some(out) = entry.out.file_name() {
+            text.push_str(&format!("  out={}\n", out.to_string_lossy()));
+        }
+    }
+    text
+}
+
+/// Control file aria2c keeps next to a file until it is complete
+pub fn control_path(out: &Path) -> PathBuf {
+    let mut control = out.as_os_str().to_owned();
+    control.push(".aria2");
+    PathBuf::from(control)
+}
+
+/// Whether aria2c finished writing `out`
+pub fn is_complete(out: &Path) -> bool {
+    out.exists() && !control_path(out).exists()
+}
+
+/// Remove a file aria2c left incomplete. Its pieces may not be contiguous,
+/// so it cannot be resumed by the built-in downloader.
+pub fn discard_incomplete(out: &Path) -> Result<()> {
+    let control = control_path(out);
+    if control.exists() {
+        if out.exists() {
+            remove_file(out)?;
+        }
+        remove_file(control)?;
+    }
+    Ok(())
+}
+
+/// Input file only the current user can read, since it may hold the proxy password
+fn create_private(path: &Path) -> std::io::Result<File> {
+    // a leftover file keeps its permissions
+    if path.exists() {
+        remove_file(path)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Proxy options of every entry of the input file, kept off the command line
+/// where other users could read the password
+fn proxy_options(options: &ClientOptions) -> String {
+    let mut text = String::new();
+    if let Some(proxy) = &options.proxy {
+        text.push_str(&format!("  all-proxy={}\n", proxy));
+        if let Some((user, password)) = &options.proxy_auth {
+            text.push_str(&format!("  all-proxy-user={}\n", user));
+            text.push_str(&format!("  all-proxy-passwd={}\n", password));
+        }
+    }
+    text
+}
+
+/// Download `entries` with aria2c, `jobs` files at a time.
+/// Files aria2c fails to download are left to the caller.
+/// aria2c has no interval between requests to a host, `options.interval` is only its retry wait.
+pub async fn run(
+    entries: &[Entry],
+    workdir: &Path,
+    jobs: usize,
+    options: &ClientOptions,
+    limit_rate: Option<u64>,
+) -> Result<()> {
+    let input = workdir.join(".onekp_aria2_input.txt");
+    let proxy = proxy_options(options);
+    let mut bw = BufWriter::new(create_private(&input)?);
+    for entry in entries.iter() {
+        bw.write_all(input_file(std::slice::from_ref(entry)).as_bytes())?;
+        bw.write_all(proxy.as_bytes())?;
+    }
+    bw.flush()?;
+    drop(bw);
+
+    let mut cmd = tokio::process::Command::new("aria2c");
+    cmd.arg(format!("--input-file={}", input.display()))
+        .arg(format!("--max-concurrent-downloads={}", jobs.max(1)))
+        .arg(format!("--max-tries={}", options.max_retry))
+        .arg(format!("--retry-wait={}", options.interval.as_secs()))
+        .arg(format!("--timeout={}", options.timeout.as_secs()))
+        .arg(format!("--connect-timeout={}", options.timeout.as_secs()))
+        .arg("--continue=true")
+        .arg("--allow-overwrite=true")
+        .arg("--auto-file-renaming=false")
+        // keep the size of partial files equal to the bytes received
+        .arg("--file-allocation=none")
+        .arg("--console-log-level=warn")
+        .arg("--summary-interval=0");
+    if let Some(rate) = limit_rate {
+        cmd.arg(format!("--max-overall-download-limit={}", rate));
+    }
+    cmd.arg(format!(
+        "--user-agent={}",
+        options.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT)
+    ));
+    for (name, value) in options.headers.iter() {
+        cmd.arg(format!("--header={}: {}", name, value));
+    }
+    let status = cmd
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .map_err(|err| {
+            OneKpError::Other(
+                anyhow::Error::new(err).context("Failed to run aria2c, is it installed?"),
+            )
+        });
+    remove_file(&input)?;
+
+    let status = status?;
+    if !status.success() {
+        // aria2c exits with an error when any download failed
+        return Err(OneKpError::Other(anyhow!("aria2c exited with {}", status)));
+    }
+    Ok(())
+}
@@ -0,0 +1,353 @@
+use std::{
+    env::{self, current_dir},
+    fmt,
+    fs::{
+        create_dir_all, metadata, read_dir, read_to_string, remove_dir, remove_dir_all,
+        remove_file, File,
+    },
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use crate::{
+    client::Client, error::OneKpError, metadata_version::VERSIONS_DIR, taxonomy::TAXONOMY_FILENAME,
+    transport::Validators, write_atomic,
+};
+
+/// Subdirectory of the cache directory keeping the [`Validators`] of the cached copies
+const VALIDATORS_DIR: &str = "validators";
+
+/// How long cached copies are reused by default
+pub const CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// Whether the cached copy at `path` is older than [`CACHE_TTL`]
+pub fn is_cache_update_required(path: &Path) -> Result<bool, OneKpError> {
+    Ok(is_expired(path, Some(CACHE_TTL))?)
+}
+
+/// Whether the cached copy at `path` is older than `ttl`, `None` never expiring
+fn is_expired(path: &Path, ttl: Option<Duration>) -> Result<bool> {
+    let meta = metadata(path)?;
+    Ok(match ttl {
+        Some(ttl) => SystemTime::now().duration_since(meta.modified()?)? >= ttl,
+        None => false,
+    })
+}
+
+/// How long cached copies are reused: a number of seconds, optionally followed by `s`, `m`, `h`
+/// or `d`, or `never` for copies that never expire
+///
+/// ```
+/// use std::time::Duration;
+/// use onekp::CacheTtl;
+///
+/// assert_eq!("90".parse::<CacheTtl>().unwrap().0, Some(Duration::from_secs(90)));
+/// assert_eq!("2h".parse::<CacheTtl>().unwrap().0, Some(Duration::from_secs(7200)));
+/// assert_eq!("never".parse::<CacheTtl>().unwrap().0, None);
+/// assert_eq!(CacheTtl(Some(Duration::from_secs(60))).to_string(), "60s");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawCacheTtl")]
+pub struct CacheTtl(pub Option<Duration>);
+
+impl Default for CacheTtl {
+    fn default() -> Self {
+        Self(Some(CACHE_TTL))
+    }
+}
+
+impl FromStr for CacheTtl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("never") {
+            return Ok(Self(None));
+        }
+        let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => s.split_at(i),
+            None => (s, "s"),
+        };
+        let seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86400,
+            _ => {
+                return Err(format!(
+                    "expected seconds, e.g. 600, 10m, 2h or 7d, or never, got {:?}",
+                    s
+                ))
+            }
+        };
+        let number: u64 = number.parse().map_err(|_| {
+            format!(
+                "expected seconds, e.g. 600, 10m, 2h or 7d, or never, got {:?}",
+                s
+            )
+        })?;
+        Ok(Self(Some(Duration::from_secs(number * seconds))))
+    }
+}
+
+impl fmt::Display for CacheTtl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(ttl) => write!(f, "{}s", ttl.as_secs()),
+            None => write!(f, "never"),
+        }
+    }
+}
+
+/// [`CacheTtl`] in the config file: seconds or text
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawCacheTtl {
+    Seconds(u64),
+    Text(String),
+}
+
+impl TryFrom<RawCacheTtl> for CacheTtl {
+    type Error = String;
+
+    fn try_from(raw: RawCacheTtl) -> Result<Self, Self::Error> {
+        match raw {
+            RawCacheTtl::Seconds(seconds) => Ok(Self(Some(Duration::from_secs(seconds)))),
+            RawCacheTtl::Text(text) => text.parse(),
+        }
+    }
+}
+
+/// `onekp` in the cache directory of the platform, shared by every working directory:
+/// `$XDG_CACHE_HOME/onekp` or `~/.cache/onekp` on Linux, `~/Library/Caches/onekp` on macOS and
+/// `%LOCALAPPDATA%\onekp\cache` on Windows. `.onekp_cache` in the current directory without a home directory.
+pub fn default_cache_dir() -> Result<PathBuf, OneKpError> {
+    let home = env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from);
+    let platform_dir = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("onekp").join("cache"))
+    } else if cfg!(target_os = "macos") {
+        home.map(|home| home.join("Library").join("Caches").join("onekp"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".cache")))
+            .map(|dir| dir.join("onekp"))
+    };
+    match platform_dir {
+        Some(dir) => Ok(dir),
+        None => Ok(current_dir()?.join(".onekp_cache")),
+    }
+}
+
+/// How [`Cache::get`] uses the cached copies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Reuse the copies younger than the TTL
+    #[default]
+    Reuse,
+    /// Download again, replacing the copies
+    Refresh,
+    /// Download without reading or writing the cache
+    Bypass,
+    /// Only use the copies, however old, failing with [`OneKpError::NotCached`] without one
+    Offline,
+}
+
+/// Directory the metadata and checksum lists are cached in, and how
+#[derive(Debug, Clone)]
+pub struct Cache {
+    pub dir: PathBuf,
+    pub mode: CacheMode,
+    /// How long the copies are reused, `None` for ever
+    pub ttl: Option<Duration>,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            mode: CacheMode::default(),
+            ttl: Some(CACHE_TTL),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: CacheMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// File of the cached copy of `url`
+    pub fn path(&self, url: &str) -> PathBuf {
+        let filename = match url.split('/').next_back() {
+            Some("") | None => "index.html",
+            Some(name) => name,
+        };
+        self.dir.join(filename)
+    }
+
+    /// Files in the cache directory, by name. Empty if the directory does not exist yet.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>, OneKpError> {
+        let dir = match read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let mut entries = vec![];
+        for entry in dir {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            if !meta.is_file() {
+                continue;
+            }
+            let age = SystemTime::now()
+                .duration_since(meta.modified()?)
+                .unwrap_or_default();
+            entries.push(CacheEntry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                size: meta.len(),
+                expired: self.ttl.is_some_and(|ttl| age >= ttl),
+                age,
+            });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    /// Delete what the cache wrote: the copies of `urls`, their validators, the stored metadata
+    /// versions and the lineages, returning the files deleted. Anything else is left alone,
+    /// and the directory is removed only if nothing is left in it.
+    pub fn clear<'a>(
+        &self,
+        urls: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<CacheEntry>, OneKpError> {
+        let mut names: Vec<String> = urls
+            .into_iter()
+            .filter_map(|url| Some(self.path(url).file_name()?.to_string_lossy().into_owned()))
+            .collect();
+        names.push(TAXONOMY_FILENAME.to_owned());
+        let entries: Vec<CacheEntry> = self
+            .entries()?
+            .into_iter()
+            .filter(|e| names.contains(&e.name))
+            .collect();
+        for entry in entries.iter() {
+            remove_file(self.dir.join(&entry.name))?;
+        }
+        for dir in [VALIDATORS_DIR, VERSIONS_DIR] {
+            match remove_dir_all(self.dir.join(dir)) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        // fails when the directory holds other files
+        let _ = remove_dir(&self.dir);
+        Ok(entries)
+    }
+
+    /// Validators of the cached copy at `path`, empty if it has none
+    fn validators(&self, path: &Path) -> Validators {
+        read_to_string(self.validators_path(path))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    fn validators_path(&self, path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_owned();
+        name.push(".json");
+        self.dir.join(VALIDATORS_DIR).join(name)
+    }
+
+    /// Get `url` as text, reusing the cached copy depending on the [`CacheMode`].
+    /// An expired copy is revalidated with its `ETag` or `Last-Modified` and only downloaded again
+    /// if the server has a newer one.
+    pub async fn get(&self, url: &str, client: &Client) -> Result<String, OneKpError> {
+        let path = self.path(url);
+        let validators = match (self.mode, is_expired(&path, self.ttl)) {
+            (CacheMode::Reuse, Ok(false)) => return Ok(read_to_string(path)?),
+            (CacheMode::Offline, _) => {
+                return match read_to_string(&path) {
+                    Err(err) if err.kind() == ErrorKind::NotFound => {
+                        Err(OneKpError::NotCached(url.to_owned()))
+                    }
+                    text => Ok(text?),
+                }
+            }
+            (CacheMode::Reuse, Ok(true)) => self.validators(&path),
+            _ => Validators::default(),
+        };
+
+        let resp = if validators.is_empty() {
+            client.get(url).await?
+        } else {
+            match client.get_if_modified(url, &validators).await? {
+                Some(resp) => resp,
+                None => {
+                    // unchanged, good for another TTL
+                    File::options()
+                        .write(true)
+                        .open(&path)?
+                        .set_modified(SystemTime::now())?;
+                    return Ok(read_to_string(path)?);
+                }
+            }
+        };
+        let validators = Validators::from_headers(&resp.headers);
+        let text = tokio::time::timeout(client.timeout(), resp.text())
+            .await
+            .map_err(|_| OneKpError::Network(anyhow!("Timed out receiving {}", url)))??;
+        if self.mode != CacheMode::Bypass {
+            create_dir_all(self.dir.join(VALIDATORS_DIR))?;
+            write_atomic(&path, text.as_bytes())?;
+            let validators_path = self.validators_path(&path);
+            if validators.is_empty() {
+                match remove_file(validators_path) {
+                    Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                    _ => {}
+                }
+            } else {
+                write_atomic(&validators_path, &serde_json::to_vec(&validators)?)?;
+            }
+        }
+        Ok(text)
+    }
+}
+
+/// File of the cache directory, see [`Cache::entries`]
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub name: String,
+    /// In bytes
+    pub size: u64,
+    /// Time since it was downloaded
+    pub age: Duration,
+    /// Whether it is older than the TTL and will be downloaded again
+    pub expired: bool,
+}
+
+/// Get `url` as text through the [default cache directory](default_cache_dir)
+pub async fn use_cache(url: &str, client: &Client) -> Result<String, OneKpError> {
+    use_cache_in(&default_cache_dir()?, url, client).await
+}
+
+/// Get `url` as text, reusing the copy in `cache_path` for [`CACHE_TTL`]
+pub async fn use_cache_in(
+    cache_path: &Path,
+    url: &str,
+    client: &Client,
+) -> Result<String, OneKpError> {
+    Cache::new(cache_path).get(url, client).await
+}
@@ -0,0 +1,92 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use crate::error::Result;
+use md5::{Digest, Md5};
+use sha2::Sha256;
+
+/// MD5 checksums published by GigaDB, keyed by the file path relative to the dataset root.
+#[derive(Debug, Clone, Default)]
+pub struct Checksums {
+    md5: HashMap<String, String>,
+}
+
+impl Checksums {
+    /// Parse a `md5sum`-style list (`<hash>  <path>` per line).
+    pub fn parse(text: &str) -> Self {
+        let md5 = text
+            .lines()
+            .filter_map(|line| {
+                let (hash, path) = line.trim().split_once(char::is_whitespace)?;
+                // `*` marks binary mode in md5sum output
+                let path = path.trim_start().trim_start_matches('*');
+                Some((normalize(path).to_owned(), hash.to_ascii_lowercase()))
+            })
+            .collect();
+
+        Self { md5 }
+    }
+
+    pub fn insert(&mut self, path: &str, md5: &str) {
+        self.md5
+            .insert(normalize(path).to_owned(), md5.to_ascii_lowercase());
+    }
+
+    pub fn get(&self, path: &str) -> Option<&str> {
+        self.md5.get(normalize(path)).map(|h| h.as_str())
+    }
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+pub fn md5_file(path: &Path) -> Result<String> {
+    hash_file::<Md5>(path)
+}
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+    hash_file::<Sha256>(path)
+}
+
+fn hash_file<D: Digest>(path: &Path) -> Result<String> {
+    let mut br = BufReader::new(File::open(path)?);
+    let mut hasher = D::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = br.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    pub path: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MD5 mismatch for {}: expected {}, got {}",
+            self.path, self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
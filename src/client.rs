@@ -0,0 +1,465 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER},
+    Method, Proxy, StatusCode,
+};
+
+use crate::{
+    error::OneKpError,
+    ftp,
+    rate_limit::{BandwidthLimiter, RateLimiter},
+    transport::{HttpTransport, Transport, TransportResponse, Validators},
+};
+
+/// Settings used to build a [`Client`]
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// Interval between requests to the same host
+    pub interval: Duration,
+    /// Number of attempts for each request, at least 1
+    pub max_retry: usize,
+    pub timeout: Duration,
+    /// `http://`, `https://` or `socks5://` proxy for every request.
+    /// When unset, `HTTP_PROXY` / `HTTPS_PROXY` environment variables are used.
+    pub proxy: Option<String>,
+    /// Basic auth credentials for the proxy as (user, password)
+    pub proxy_auth: Option<(String, String)>,
+    /// Defaults to [`DEFAULT_USER_AGENT`]
+    pub user_agent: Option<String>,
+    /// Extra headers sent with every request
+    pub headers: Vec<(String, String)>,
+}
+
+/// Default seconds between requests to the same host
+pub const INTERVAL: u64 = 3;
+/// Default number of attempts for each request
+pub const MAX_RETRY: usize = 5;
+/// Default seconds to wait for a connection or data
+pub const TIMEOUT: u64 = 60;
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(INTERVAL),
+            max_retry: MAX_RETRY,
+            timeout: Duration::from_secs(TIMEOUT),
+            proxy: None,
+            proxy_auth: None,
+            user_agent: None,
+            headers: vec![],
+        }
+    }
+}
+
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/illumination-k/onekp)"
+);
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    // shared connection pool, cheap to clone
+    transport: Arc<dyn Transport>,
+    // shared between tasks
+    limiter: RateLimiter,
+    bandwidth: Option<BandwidthLimiter>,
+    max_retry: usize,
+    timeout: Duration,
+    // kept for external downloaders
+    options: Arc<ClientOptions>,
+    notices: Option<Notices>,
+}
+
+/// Receiver of the retries and other notices of a [`Client`], see [`Client::with_notices`]
+#[derive(Clone)]
+struct Notices(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl std::fmt::Debug for Notices {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Notices")
+    }
+}
+
+impl Client {
+    pub fn new(options: &ClientOptions) -> Result<Self, OneKpError> {
+        let invalid = |err: &dyn std::fmt::Display| OneKpError::InvalidOptions(err.to_string());
+        if options.max_retry == 0 {
+            return Err(invalid(&"max_retry must be at least 1"));
+        }
+        let mut headers = HeaderMap::new();
+        for (name, value) in options.headers.iter() {
+            headers.append(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(&e))?,
+                HeaderValue::from_str(value).map_err(|e| invalid(&e))?,
+            );
+        }
+
+        let mut builder = reqwest::Client::builder()
+            .user_agent(options.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT))
+            .default_headers(headers)
+            .connect_timeout(options.timeout)
+            .pool_idle_timeout(Duration::from_secs(90))
+            .tcp_keepalive(Duration::from_secs(60));
+
+        if let Some(proxy) = &options.proxy {
+            let mut proxy = Proxy::all(proxy).map_err(|e| invalid(&e))?;
+            if let Some((user, password)) = &options.proxy_auth {
+                proxy = proxy.basic_auth(user, password);
+            }
+            builder = builder.proxy(proxy);
+        }
+
+        Ok(Self {
+            transport: Arc::new(HttpTransport::new(builder.build()?)),
+            limiter: RateLimiter::new(options.interval),
+            bandwidth: None,
+            max_retry: options.max_retry,
+            timeout: options.timeout,
+            options: Arc::new(options.clone()),
+            notices: None,
+        })
+    }
+
+    /// Send the HTTP requests through `transport` instead of reqwest
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Pass retried requests and other notices to `notices` instead of dropping them.
+    /// The client never prints on the terminal itself.
+    pub fn with_notices(mut self, notices: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.notices = Some(Notices(Arc::new(notices)));
+        self
+    }
+
+    /// Report `msg` to the receiver of [`Client::with_notices`], if any
+    pub fn notify(&self, msg: &str) {
+        if let Some(Notices(notices)) = &self.notices {
+            notices(msg);
+        }
+    }
+
+    /// Cap the download speed of body streams read through [`Client::throttle`].
+    pub fn with_limit_rate(mut self, bytes_per_sec: u64) -> Self {
+        self.bandwidth = Some(BandwidthLimiter::new(bytes_per_sec));
+        self
+    }
+
+    /// Call for every received chunk to respect the bandwidth limit.
+    pub async fn throttle(&self, bytes: u64) {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.consume(bytes).await;
+        }
+    }
+
+    /// Same client without the interval between requests to a host and with a single attempt,
+    /// for quick probes such as size estimates. Callers bound the number of requests in flight.
+    pub fn unthrottled(&self) -> Self {
+        Self {
+            limiter: RateLimiter::new(Duration::ZERO),
+            max_retry: 1,
+            ..self.clone()
+        }
+    }
+
+    /// Settings the client was built with
+    pub fn options(&self) -> &ClientOptions {
+        &self.options
+    }
+
+    /// Number of attempts for each request
+    pub fn max_retry(&self) -> usize {
+        self.max_retry
+    }
+
+    /// How long to wait for a connection, the response, or the next chunk of the body
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    async fn _request(
+        &self,
+        method: Method,
+        url: &str,
+        offset: u64,
+        validators: Option<&Validators>,
+    ) -> Result<TransportResponse> {
+        self.limiter.acquire(&host_of(url)?).await;
+
+        let send = match (method, validators) {
+            (Method::HEAD, _) => self.transport.head(url),
+            (_, Some(validators)) => self.transport.get_if_modified(url, validators),
+            _ => self.transport.get(url, offset),
+        };
+        let resp = tokio::time::timeout(self.timeout, send)
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for the response of {}", url))??;
+
+        match resp.status {
+            StatusCode::OK => {}
+            // only meaningful when resuming, let the caller decide what to do
+            StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {}
+            StatusCode::NOT_MODIFIED if validators.is_some() => {}
+            StatusCode::TOO_MANY_REQUESTS => {
+                return Err(RateLimited {
+                    status: resp.status,
+                    retry_after: parse_retry_after(&resp.headers),
+                }
+                .into())
+            }
+            // an outage unless the server says when to come back
+            StatusCode::SERVICE_UNAVAILABLE => {
+                if let Some(retry_after) = parse_retry_after(&resp.headers) {
+                    return Err(RateLimited {
+                        status: resp.status,
+                        retry_after: Some(retry_after),
+                    }
+                    .into());
+                }
+                return Err(anyhow!("Error: {}", resp.status));
+            }
+            StatusCode::NOT_FOUND => {
+                return Err(NotFound {
+                    url: url.to_owned(),
+                }
+                .into())
+            }
+            status => return Err(anyhow!("Error: {}", status)),
+        }
+
+        Ok(resp)
+    }
+
+    /// Run `attempt` until it succeeds, returning its output and the number of retries
+    async fn retrying<T, F, Fut>(&self, url: &str, mut attempt: F) -> Result<(T, usize)>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempts = 0;
+        let mut rate_limited = 0;
+        let mut waited = Duration::ZERO;
+        while attempts < self.max_retry {
+            match attempt().await {
+                Ok(data) => return Ok((data, attempts)),
+                // retrying does not make a missing file appear
+                Err(err) if OneKpError::find::<NotFound>(&err).is_some() => return Err(err),
+                Err(err) => {
+                    let wait = OneKpError::find::<RateLimited>(&err).map(|limited| {
+                        limited
+                            .retry_after
+                            .unwrap_or_else(|| self.limiter.interval() * (1 << rate_limited))
+                            .min(MAX_RETRY_AFTER)
+                    });
+                    match wait {
+                        // waiting for the server does not consume the retry budget, up to a point
+                        Some(wait)
+                            if rate_limited < MAX_RATE_LIMITED
+                                && waited + wait <= MAX_RATE_LIMITED_WAIT =>
+                        {
+                            rate_limited += 1;
+                            waited += wait;
+                            self.notify(&format!("{}, retrying after {}s", err, wait.as_secs()));
+                            self.limiter.delay(&host_of(url)?, wait);
+                        }
+                        _ => {
+                            attempts += 1;
+                            self.notify(&format!("{}", err));
+                        }
+                    }
+                }
+            }
+        }
+
+        Err(RetriesExhausted {
+            url: url.to_owned(),
+            retries: self.max_retry,
+        }
+        .into())
+    }
+
+    /// Send a request with retries, returning the response and the number of retries
+    async fn request(
+        &self,
+        method: Method,
+        url: &str,
+        offset: u64,
+    ) -> Result<(TransportResponse, usize)> {
+        self.retrying(url, || self._request(method.clone(), url, offset, None))
+            .await
+    }
+
+    pub async fn get(&self, url: &str) -> Result<TransportResponse, OneKpError> {
+        Ok(self.get_from(url, 0).await?.0)
+    }
+
+    /// Fetch `url` starting at byte `offset` with a `Range` header,
+    /// returning the response and the number of retries it took.
+    /// Servers that ignore the header answer with `200 OK` and the whole body.
+    pub async fn get_from(
+        &self,
+        url: &str,
+        offset: u64,
+    ) -> Result<(TransportResponse, usize), OneKpError> {
+        Ok(self.request(Method::GET, url, offset).await?)
+    }
+
+    /// `GET url` unless it did not change since the copy `validators` came from, `None` then
+    pub async fn get_if_modified(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<Option<TransportResponse>, OneKpError> {
+        let (resp, _) = self
+            .retrying(url, || self._request(Method::GET, url, 0, Some(validators)))
+            .await?;
+        Ok((resp.status != StatusCode::NOT_MODIFIED).then_some(resp))
+    }
+
+    pub async fn head(&self, url: &str) -> Result<TransportResponse, OneKpError> {
+        Ok(self.request(Method::HEAD, url, 0).await?.0)
+    }
+
+    /// Open the body of an `http(s)://` or `ftp://` URL from byte `offset`,
+    /// returning it with the number of retries it took.
+    pub async fn open(&self, url: &str, offset: u64) -> Result<(Body, usize), OneKpError> {
+        if is_ftp(url) {
+            return Ok(self
+                .retrying(url, || async {
+                    self.limiter.acquire(&host_of(url)?).await;
+                    ftp::open(url, offset, self.timeout).await
+                })
+                .await?);
+        }
+
+        let (mut resp, mut retries) = self.get_from(url, offset).await?;
+        if resp.status == StatusCode::RANGE_NOT_SATISFIABLE {
+            // the partial file is broken or stale, start over
+            let (fresh, fresh_retries) = self.get_from(url, 0).await?;
+            resp = fresh;
+            retries += fresh_retries;
+        }
+        let offset = if resp.status == StatusCode::PARTIAL_CONTENT {
+            offset
+        } else {
+            0
+        };
+
+        Ok((
+            Body {
+                offset,
+                len: resp.content_length,
+                stream: resp.body,
+            },
+            retries,
+        ))
+    }
+
+    /// Size of the remote file, if the server tells it
+    pub async fn content_length(&self, url: &str) -> Result<Option<u64>, OneKpError> {
+        if is_ftp(url) {
+            return Ok(self
+                .retrying(url, || async {
+                    self.limiter.acquire(&host_of(url)?).await;
+                    ftp::size(url, self.timeout).await
+                })
+                .await?
+                .0);
+        }
+        Ok(self.head(url).await?.content_length())
+    }
+}
+
+/// Body of a download opened with [`Client::open`]
+pub struct Body {
+    /// Byte position the body starts at, 0 when the server sends the whole file
+    pub offset: u64,
+    /// Length of the body
+    pub len: Option<u64>,
+    pub stream: BoxStream<'static, Result<Bytes, OneKpError>>,
+}
+
+fn is_ftp(url: &str) -> bool {
+    url.starts_with("ftp://")
+}
+
+const MAX_RATE_LIMITED: u32 = 10;
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(600);
+/// Total time a request waits for the server to lift its rate limit before the waits count as
+/// failed attempts, so that mirrors are tried
+const MAX_RATE_LIMITED_WAIT: Duration = Duration::from_secs(900);
+
+fn host_of(url: &str) -> Result<String> {
+    Ok(reqwest::Url::parse(url)?
+        .host_str()
+        .unwrap_or_default()
+        .to_owned())
+}
+
+/// Every attempt of a request failed
+#[derive(Debug)]
+pub struct RetriesExhausted {
+    pub url: String,
+    pub retries: usize,
+}
+
+impl std::fmt::Display for RetriesExhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Error {} times when fetching {}", self.retries, self.url)
+    }
+}
+
+impl std::error::Error for RetriesExhausted {}
+
+/// The server does not have the file (404)
+#[derive(Debug)]
+pub struct NotFound {
+    pub url: String,
+}
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Not found: {}", self.url)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// The server asked us to slow down (429, or 503 with `Retry-After`)
+#[derive(Debug)]
+pub(crate) struct RateLimited {
+    status: StatusCode,
+    retry_after: Option<Duration>,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rate limited: {}", self.status)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// `Retry-After` is either delay seconds or an HTTP date
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
@@ -0,0 +1,222 @@
+//! Defaults of the command line options, read from `~/.config/onekp/config.toml`:
+//!
+//! ```toml
+//! rootdir = "/data/onekp"
+//! interval = 2
+//! jobs = 4
+//! cache_dir = "/data/onekp/.cache"
+//! cache_ttl = "1d"
+//! sequence_type = "protein"
+//! mirrors = ["https://mirror.example.org/gigadb/"]
+//!
+//! [profile.hpc]
+//! rootdir = "/scratch/onekp"
+//! jobs = 16
+//! ```
+//!
+//! The values of a profile, selected with `--profile`, replace those at the top.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::read_to_string,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use clap::{Arg, ArgAction, Command, ValueEnum};
+use serde::Deserialize;
+
+use crate::{error::OneKpError, CacheTtl, SequenceType};
+
+/// Contents of the config file. The options given on the command line take precedence.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// --rootdir of the commands downloading or reading a dataset
+    pub rootdir: Option<PathBuf>,
+    /// --interval, in seconds
+    pub interval: Option<u64>,
+    /// --jobs
+    pub jobs: Option<usize>,
+    /// --cache-dir
+    pub cache_dir: Option<PathBuf>,
+    /// --cache-ttl, in seconds or e.g. "2h" or "never"
+    pub cache_ttl: Option<CacheTtl>,
+    /// --sequence-type
+    pub sequence_type: Option<SequenceType>,
+    /// --mirror
+    pub mirrors: Vec<String>,
+    /// Named sets of values, overriding the ones above
+    pub profile: BTreeMap<String, Config>,
+}
+
+impl Config {
+    /// `$ONEKP_CONFIG`, else `onekp/config.toml` of `$XDG_CONFIG_HOME`, or of `~/.config`
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("ONEKP_CONFIG").filter(|path| !path.is_empty()) {
+            return Some(PathBuf::from(path));
+        }
+        let dir = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(dir.join("onekp").join("config.toml"))
+    }
+
+    /// Config file at `path`, the default config if there is none
+    ///
+    /// ```
+    /// use onekp::config::Config;
+    ///
+    /// let path = std::env::temp_dir().join("onekp-config-doctest.toml");
+    /// std::fs::write(
+    ///     &path,
+    ///     "jobs = 4 # comment\nmirrors = [\n  'https://a/',\n  \"https://b/\",\n]\n[profile.hpc]\nrootdir = \"/scratch\"\n",
+    /// )
+    /// .unwrap();
+    /// let config = Config::load(&path).unwrap();
+    /// assert_eq!(config.jobs, Some(4));
+    /// assert_eq!(config.mirrors, ["https://a/", "https://b/"]);
+    /// let hpc = config.with_profile("hpc").unwrap();
+    /// assert_eq!(hpc.rootdir.unwrap().to_str(), Some("/scratch"));
+    ///
+    /// std::fs::write(&path, "jobs = ").unwrap();
+    /// assert!(Config::load(&path).is_err());
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn load(path: &Path) -> Result<Self, OneKpError> {
+        let text = match read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        toml::from_str(&text)
+            .map_err(|err| OneKpError::InvalidOptions(format!("{}: {}", path.display(), err)))
+    }
+
+    /// The config with the values of the profile `name` in place of those at the top
+    pub fn with_profile(mut self, name: &str) -> Result<Self, OneKpError> {
+        let Some(profile) = self.profile.remove(name) else {
+            let known: Vec<&str> = self.profile.keys().map(|k| k.as_str()).collect();
+            return Err(OneKpError::InvalidOptions(format!(
+                "no profile {:?} in the config file, profiles: {}",
+                name,
+                if known.is_empty() {
+                    "none".to_owned()
+                } else {
+                    known.join(", ")
+                }
+            )));
+        };
+        Ok(Self {
+            rootdir: profile.rootdir.or(self.rootdir),
+            interval: profile.interval.or(self.interval),
+            jobs: profile.jobs.or(self.jobs),
+            cache_dir: profile.cache_dir.or(self.cache_dir),
+            cache_ttl: profile.cache_ttl.or(self.cache_ttl),
+            sequence_type: profile.sequence_type.or(self.sequence_type),
+            mirrors: if profile.mirrors.is_empty() {
+                self.mirrors
+            } else {
+                profile.mirrors
+            },
+            profile: BTreeMap::new(),
+        })
+    }
+
+    /// Values of the command line options set by the config, by argument ID
+    fn defaults(&self) -> Vec<(&'static str, Vec<String>)> {
+        let mut defaults = vec![];
+        if let Some(rootdir) = &self.rootdir {
+            defaults.push(("rootdir", vec![rootdir.display().to_string()]));
+        }
+        if let Some(interval) = self.interval {
+            defaults.push(("interval", vec![interval.to_string()]));
+        }
+        if let Some(jobs) = self.jobs {
+            defaults.push(("jobs", vec![jobs.to_string()]));
+        }
+        if let Some(ttl) = self.cache_ttl {
+            defaults.push(("cache_ttl", vec![ttl.to_string()]));
+        }
+        if let Some(value) = self.sequence_type.and_then(|s| s.to_possible_value()) {
+            defaults.push(("sequence_type", vec![value.get_name().to_owned()]));
+        }
+        if !self.mirrors.is_empty() {
+            defaults.push(("mirrors", self.mirrors.clone()));
+        }
+        defaults
+    }
+
+    /// `cmd` with the values of the config as defaults of its options and those of its subcommands,
+    /// and a global --profile option. Optional options without a default, e.g. the --rootdir
+    /// overriding that of a report, are left alone.
+    pub fn apply(&self, cmd: Command) -> Command {
+        let cmd = cmd.arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("PROFILE")
+                .global(true)
+                .env(PROFILE_ENV)
+                .help("Profile of the config file to use, see `[profile.<name>]`"),
+        );
+        self.defaults()
+            .into_iter()
+            .fold(cmd, |cmd, (id, values)| set_default(cmd, id, &values))
+    }
+}
+
+/// Environment variable selecting the profile when --profile is not given
+const PROFILE_ENV: &str = "ONEKP_PROFILE";
+
+/// Name of the profile given by --profile in `args`, else by `$ONEKP_PROFILE`.
+/// It is needed before the options are parsed with the values of the profile.
+///
+/// ```
+/// use onekp::config::profile_name;
+///
+/// let args = |line: &str| line.split(' ').map(|a| a.to_owned()).collect::<Vec<String>>();
+/// assert_eq!(profile_name(args("onekp fetch --profile hpc -s protein")), Some("hpc".to_owned()));
+/// assert_eq!(profile_name(args("onekp fetch --profile=laptop")), Some("laptop".to_owned()));
+/// assert_eq!(profile_name(args("onekp search -- --profile")), None);
+/// ```
+pub fn profile_name(args: impl IntoIterator<Item = String>) -> Option<String> {
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => break,
+            "--profile" => return args.next(),
+            _ => {
+                if let Some(name) = arg.strip_prefix("--profile=") {
+                    return Some(name.to_owned());
+                }
+            }
+        }
+    }
+    env::var(PROFILE_ENV).ok().filter(|name| !name.is_empty())
+}
+
+fn set_default(cmd: Command, id: &str, values: &[String]) -> Command {
+    let applies = cmd.get_arguments().any(|arg| {
+        arg.get_id() == id
+            && (arg.is_required_set()
+                || !arg.get_default_values().is_empty()
+                || matches!(arg.get_action(), ArgAction::Append))
+    });
+    let cmd = if applies {
+        cmd.mut_arg(id, |arg| {
+            arg.required(false)
+                .default_values(values.iter().map(|v| v.to_owned()))
+        })
+    } else {
+        cmd
+    };
+    let names: Vec<String> = cmd
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_owned())
+        .collect();
+    names.iter().fold(cmd, |cmd, name| {
+        cmd.mut_subcommand(name, |sub| set_default(sub, id, values))
+    })
+}
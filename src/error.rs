@@ -0,0 +1,147 @@
+use thiserror::Error;
+
+use crate::{
+    checksum::ChecksumMismatch,
+    client::{NotFound, RateLimited, RetriesExhausted},
+    fetch::Truncated,
+    strict::InvalidMetadata,
+    validate::Corrupt,
+};
+
+/// Result of the library API
+pub type Result<T, E = OneKpError> = std::result::Result<T, E>;
+
+/// Errors of the library API, to be matched by kind
+#[derive(Debug, Error)]
+pub enum OneKpError {
+    /// The server could not be reached, kept failing or sent an incomplete response
+    #[error(transparent)]
+    Network(anyhow::Error),
+    /// The server does not have the file
+    #[error(transparent)]
+    NotFound(#[from] NotFound),
+    /// The sample has no single directory on the server, see
+    /// [`OneKpRecord::is_available`](crate::OneKpRecord::is_available)
+    #[error("{0} has no single directory in the assemblies listing")]
+    Unavailable(String),
+    /// The metadata or a file from the server could not be understood
+    #[error("{0}")]
+    Parse(String),
+    /// The sample table failed the `--strict` checks
+    #[error(transparent)]
+    InvalidMetadata(#[from] InvalidMetadata),
+    /// A downloaded file does not match its published checksum
+    #[error(transparent)]
+    Checksum(#[from] ChecksumMismatch),
+    /// A downloaded file is not a valid gzip or FASTA file
+    #[error(transparent)]
+    Corrupt(#[from] Corrupt),
+    /// The options of a run contradict each other or do not fit the destination
+    #[error("{0}")]
+    InvalidOptions(String),
+    /// Working offline and the file is not in the cache
+    #[error("{0} is not cached, run once online to download it")]
+    NotCached(String),
+    /// The destination does not have enough free space
+    #[error("{0}")]
+    InsufficientSpace(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl OneKpError {
+    /// Whether trying again later may help
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Network(_))
+    }
+
+    /// The error of type `E` in `err`, whether `err` is one or a [`OneKpError`] holding one
+    pub(crate) fn find<E: std::error::Error + Send + Sync + 'static>(
+        err: &anyhow::Error,
+    ) -> Option<&E> {
+        if let Some(found) = err.downcast_ref::<E>() {
+            return Some(found);
+        }
+        let inner: &(dyn std::error::Error + 'static) = match err.downcast_ref::<Self>()? {
+            Self::NotFound(err) => err,
+            Self::InvalidMetadata(err) => err,
+            Self::Checksum(err) => err,
+            Self::Corrupt(err) => err,
+            Self::Io(err) => err,
+            Self::Network(err) | Self::Other(err) => return err.downcast_ref::<E>(),
+            _ => return None,
+        };
+        inner.downcast_ref::<E>()
+    }
+}
+
+impl From<serde_json::Error> for OneKpError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Parse(err.to_string())
+    }
+}
+
+impl From<tokio::task::JoinError> for OneKpError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        Self::Other(err.into())
+    }
+}
+
+/// Writing strings only fails on the underlying writer
+impl From<csv::Error> for OneKpError {
+    fn from(err: csv::Error) -> Self {
+        Self::Io(err.into())
+    }
+}
+
+impl From<parquet::errors::ParquetError> for OneKpError {
+    fn from(err: parquet::errors::ParquetError) -> Self {
+        Self::Other(err.into())
+    }
+}
+
+impl From<reqwest::Error> for OneKpError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Network(err.into())
+    }
+}
+
+/// Sort the errors of the internal code, which uses [`anyhow`], by kind
+impl From<anyhow::Error> for OneKpError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<Self>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<NotFound>() {
+            Ok(err) => return Self::NotFound(err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<ChecksumMismatch>() {
+            Ok(err) => return Self::Checksum(err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<Corrupt>() {
+            Ok(err) => return Self::Corrupt(err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<std::io::Error>() {
+            Ok(err) => return Self::Io(err),
+            Err(err) => err,
+        };
+        let is_network = err.is::<reqwest::Error>()
+            || err.is::<suppaftp::FtpError>()
+            || err.is::<object_store::Error>()
+            || err.is::<RetriesExhausted>()
+            || err.is::<RateLimited>()
+            || err.is::<Truncated>()
+            || err.is::<tokio::time::error::Elapsed>();
+        if is_network {
+            Self::Network(err)
+        } else {
+            Self::Other(err)
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use clap::ValueEnum;
+
+use crate::aria2::{self, Entry};
+
+/// Input file formats of external downloaders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UrlFormat {
+    /// `aria2c -i`, with mirrors and output names
+    Aria2,
+    /// `wget -i`, URLs only
+    Wget,
+    /// `curl -K`, with output names
+    Curl,
+}
+
+pub fn render(format: UrlFormat, entries: &[Entry]) -> String {
+    match format {
+        UrlFormat::Aria2 => aria2::input_file(entries),
+        UrlFormat::Wget => entries
+            .iter()
+            .filter_map(|e| e.urls.first())
+            .map(|url| format!("{}\n", url))
+            .collect(),
+        UrlFormat::Curl => {
+            let mut text = String::from("create-dirs\n");
+            for entry in entries.iter() {
+                if let Some(url) = entry.urls.first() {
+                    text.push_str(&format!(
+                        "url = \"{}\"\noutput = \"{}\"\n",
+                        escape(url),
+                        escape(&entry.out.to_string_lossy())
+                    ));
+                }
+            }
+            text
+        }
+    }
+}
+
+/// Quote for a curl config file
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,1343 @@
+use anyhow::{anyhow, Result};
+use clap::{Args, ValueEnum};
+use futures_util::{Stream, StreamExt};
+use indicatif::HumanBytes;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, metadata, remove_file, rename, File, OpenOptions},
+    io::{stderr, stdin, BufWriter, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+use tokio::{
+    sync::{mpsc, Semaphore},
+    task::JoinSet,
+};
+
+use colored::*;
+
+use crate::{
+    aria2,
+    checksum::{md5_file, ChecksumMismatch, Checksums},
+    client::{Client, RetriesExhausted},
+    error::OneKpError,
+    manifest::{self, ManifestAlgorithm},
+    mirror::Mirrors,
+    part_path,
+    path_template::PathTemplate,
+    progress::{DownloadEvent, Progress},
+    provenance::{sidecar_path, Provenance},
+    rate_limit::parse_byte_rate,
+    report::{FetchReport, FileReport, FileStatus, SampleReport, SampleStatus},
+    sink::Sink,
+    state::FetchState,
+    validate::{check_fasta, check_gzip, is_gzip, Alphabet, Corrupt},
+    write_atomic, Cache, NameBy, OneKpRecord, Product, SequenceType, METADATA_FILENAME,
+    METADATA_HEADER,
+};
+
+/// Download `url` into the partial file `part`.
+/// The caller renames it to the final name after verification.
+async fn download_file(
+    url: &str,
+    part: &Path,
+    client: &Client,
+    progress: &Progress,
+) -> Result<DownloadStats> {
+    // resume from the previous partial download if any
+    let offset = metadata(part).map(|m| m.len()).unwrap_or(0);
+    let (body, retries) = client.open(url, offset).await?;
+
+    // the server may start over, e.g. when the partial file is broken or stale
+    let position = body.offset;
+    let f = if position > 0 {
+        OpenOptions::new().append(true).open(part)?
+    } else {
+        File::create(part)?
+    };
+    let name = part
+        .file_stem()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut file_progress = progress.file(&name, position, body.len.map(|len| len + position));
+
+    let mut bytes = 0;
+    let mut bw = BufWriter::new(f);
+    let mut stream = body.stream;
+    while let Some(chunk) = tokio::time::timeout(client.timeout(), stream.next())
+        .await
+        .map_err(|_| anyhow!("Timed out receiving {}", url))?
+    {
+        let chunk = chunk?;
+        bw.write_all(&chunk)?;
+        file_progress.inc(chunk.len() as u64);
+        bytes += chunk.len() as u64;
+        client.throttle(chunk.len() as u64).await;
+    }
+    bw.flush()?;
+    bw.get_ref().sync_all()?;
+    drop(bw);
+
+    if let Some(expected) = body.len {
+        if bytes != expected {
+            if bytes > expected {
+                // the extra bytes cannot be resumed from
+                remove_file(part)?;
+            }
+            return Err(Truncated {
+                url: url.to_owned(),
+                expected,
+                received: bytes,
+            }
+            .into());
+        }
+    }
+    file_progress.finish();
+
+    Ok(DownloadStats {
+        bytes,
+        retries,
+        content_length: body.len.map(|len| len + position),
+        records: None,
+    })
+}
+
+/// The body did not have the length announced by the server
+#[derive(Debug)]
+pub(crate) struct Truncated {
+    url: String,
+    expected: u64,
+    received: u64,
+}
+
+impl std::fmt::Display for Truncated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Received {} bytes instead of {} from {}",
+            self.received, self.expected, self.url
+        )
+    }
+}
+
+impl std::error::Error for Truncated {}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct DownloadStats {
+    bytes: u64,
+    retries: usize,
+    /// Size of the whole file announced by the server
+    content_length: Option<u64>,
+    /// Number of FASTA records found by `--validate-fasta`
+    records: Option<u64>,
+}
+
+impl std::ops::AddAssign for DownloadStats {
+    fn add_assign(&mut self, other: Self) {
+        self.bytes += other.bytes;
+        self.retries += other.retries;
+        self.content_length = other.content_length.or(self.content_length);
+        self.records = other.records.or(self.records);
+    }
+}
+
+async fn compute_md5(path: &Path) -> Result<String> {
+    let path = path.to_owned();
+    Ok(tokio::task::spawn_blocking(move || md5_file(&path)).await??)
+}
+
+async fn verify_md5(path: &Path, expected: &str) -> Result<()> {
+    let actual = compute_md5(path).await?;
+
+    if actual != expected {
+        return Err(ChecksumMismatch {
+            path: path.display().to_string(),
+            expected: expected.to_owned(),
+            actual,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Check the downloaded `part` of `path` against its checksum, and its content with `--validate`.
+/// Returns the number of FASTA records with `--validate-fasta`.
+async fn verify_part(
+    part: &Path,
+    path: &Path,
+    expected_md5: Option<&str>,
+    alphabet: Alphabet,
+    options: &FetchOptions,
+) -> Result<Option<u64>> {
+    if let Some(expected) = expected_md5 {
+        verify_md5(part, expected).await?;
+    }
+    if !is_gzip(path) {
+        return Ok(None);
+    }
+    let part = part.to_owned();
+    if options.validate_fasta {
+        let records = tokio::task::spawn_blocking(move || check_fasta(&part, alphabet)).await??;
+        return Ok(Some(records));
+    }
+    if options.validate {
+        tokio::task::spawn_blocking(move || check_gzip(&part)).await??;
+    }
+    Ok(None)
+}
+
+/// Errors of a complete download whose content is wrong, which another download may fix
+fn is_bad_content(err: &anyhow::Error) -> bool {
+    OneKpError::find::<ChecksumMismatch>(err).is_some()
+        || OneKpError::find::<Corrupt>(err).is_some()
+}
+
+/// Check whether an existing local file matches the remote one,
+/// by checksum if available, otherwise by size.
+async fn is_up_to_date(
+    path: &Path,
+    urls: &[String],
+    expected_md5: Option<&str>,
+    client: &Client,
+) -> Result<bool> {
+    if let Some(expected) = expected_md5 {
+        return Ok(compute_md5(path).await? == expected);
+    }
+
+    let local_len = metadata(path)?.len();
+    let mut remote_len = Err(anyhow!("No URL to check {}", path.display()));
+    for url in urls.iter() {
+        remote_len = client
+            .content_length(url)
+            .await
+            .map_err(anyhow::Error::from);
+        if remote_len.as_ref().is_ok_and(|len| len.is_some()) {
+            break;
+        }
+    }
+    Ok(match remote_len? {
+        Some(remote_len) => remote_len == local_len,
+        // files are renamed only after completion, so trust the local one
+        None => true,
+    })
+}
+
+/// Transport used for the data files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Protocol {
+    #[default]
+    Https,
+    /// Plain FTP from ftp.cngb.org, falling back to HTTPS
+    Ftp,
+}
+
+/// Program that transfers the data files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Downloader {
+    #[default]
+    Builtin,
+    /// Hand the whole batch to aria2c, then verify the files as usual
+    Aria2c,
+}
+
+/// What to do when a target file already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverwritePolicy {
+    /// Keep the existing file
+    Never,
+    /// Always download again
+    Always,
+    /// Download again only if the remote checksum or size differs
+    #[default]
+    IfDifferent,
+}
+
+#[derive(Debug, Clone)]
+struct FetchOptions {
+    /// Files of each sample, see [`SequenceType::with_products`]
+    filenames: Vec<&'static str>,
+    name_by: NameBy,
+    overwrite: OverwritePolicy,
+    redownload: bool,
+    sidecar: bool,
+    downloader: Downloader,
+    validate: bool,
+    validate_fasta: bool,
+}
+
+impl FetchOptions {
+    /// Defaults of `fetch`
+    fn new(sequence_type: SequenceType) -> Self {
+        Self {
+            filenames: sequence_type.to_filenames(),
+            name_by: NameBy::default(),
+            overwrite: OverwritePolicy::IfDifferent,
+            redownload: false,
+            sidecar: true,
+            downloader: Downloader::Builtin,
+            validate: false,
+            validate_fasta: false,
+        }
+    }
+}
+
+/// Errors worth trying another mirror for, as opposed to local failures
+fn is_remote_error(err: &anyhow::Error) -> bool {
+    OneKpError::find::<std::io::Error>(err).is_none()
+}
+
+/// Download from the first of `urls` that works, falling back to the next on remote errors
+async fn download_any(
+    urls: &[String],
+    part: &Path,
+    client: &Client,
+    progress: &Progress,
+) -> Result<DownloadStats> {
+    let mut last_err = anyhow!("No URL to download {}", part.display());
+    for (i, url) in urls.iter().enumerate() {
+        if i > 0 {
+            progress.println(&format!("{}: trying {}", "Fallback".yellow(), url));
+        }
+        // incomplete transfers are resumed from the partial file
+        let mut stats = DownloadStats::default();
+        let mut result = download_file(url, part, client, progress).await;
+        while let Err(err) = &result {
+            if OneKpError::find::<Truncated>(err).is_none() || stats.retries >= client.max_retry() {
+                break;
+            }
+            progress.println(&format!("{}, retrying", err));
+            stats.retries += 1;
+            result = download_file(url, part, client, progress).await;
+        }
+        match result {
+            Ok(downloaded) => {
+                stats += downloaded;
+                return Ok(stats);
+            }
+            Err(err) if is_remote_error(&err) => {
+                progress.println(&format!("{}", err));
+                last_err = err;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Err(last_err)
+}
+
+/// Download one file unless it can be skipped, verify it, and move it into place.
+/// `urls` are the primary URL followed by its mirrors.
+async fn fetch_file(
+    urls: &[String],
+    path: &Path,
+    expected_md5: Option<&str>,
+    alphabet: Alphabet,
+    client: &Client,
+    progress: &Progress,
+    options: &FetchOptions,
+) -> Result<(FileStatus, DownloadStats)> {
+    let skip = path.exists()
+        && match options.overwrite {
+            OverwritePolicy::Never => true,
+            OverwritePolicy::Always => false,
+            OverwritePolicy::IfDifferent => is_up_to_date(path, urls, expected_md5, client).await?,
+        };
+    if skip {
+        progress.println(&format!(
+            "{}: {} already exists",
+            "Skipped".cyan(),
+            path.display()
+        ));
+        return Ok((FileStatus::Skipped, DownloadStats::default()));
+    }
+
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    let part = part_path(path);
+    let mut stats = if options.downloader == Downloader::Aria2c && aria2::is_complete(&part) {
+        DownloadStats {
+            bytes: metadata(&part)?.len(),
+            ..Default::default()
+        }
+    } else {
+        // files aria2c could not complete are downloaded by onekp itself
+        aria2::discard_incomplete(&part)?;
+        download_any(urls, &part, client, progress).await?
+    };
+
+    stats.records = match verify_part(&part, path, expected_md5, alphabet, options).await {
+        Ok(records) => records,
+        Err(err) => {
+            if !is_bad_content(&err) {
+                return Err(err);
+            }
+            // a corrupt partial file cannot be resumed
+            remove_file(&part)?;
+            if !options.redownload {
+                return Err(err);
+            }
+            progress.println(&format!("{}, downloading again", err));
+            stats += download_any(urls, &part, client, progress).await?;
+            match verify_part(&part, path, expected_md5, alphabet, options).await {
+                Ok(records) => records,
+                Err(err) => {
+                    remove_file(&part)?;
+                    return Err(err);
+                }
+            }
+        }
+    };
+    if let Some(records) = stats.records {
+        progress.println(&format!(
+            "{}: {} records in {}",
+            "Validated".green(),
+            records,
+            path.display()
+        ));
+    }
+
+    // only complete and verified files get the final name
+    rename(&part, path)?;
+
+    Ok((FileStatus::Downloaded, stats))
+}
+
+/// Shared by every download task of a run
+#[derive(Debug, Clone)]
+struct FetchContext {
+    client: Client,
+    progress: Progress,
+    checksums: Option<Arc<Checksums>>,
+    mirrors: Mirrors,
+    /// Local directory the files are downloaded into, the root directory unless uploading
+    workdir: PathBuf,
+    sink: Option<Arc<Sink>>,
+}
+
+/// Whether the object already uploaded to `sink` can be kept
+async fn is_uploaded(
+    sink: &Sink,
+    relative: &Path,
+    urls: &[String],
+    client: &Client,
+    overwrite: OverwritePolicy,
+) -> Result<bool> {
+    if overwrite == OverwritePolicy::Always {
+        return Ok(false);
+    }
+    let Some(size) = sink.size(relative).await? else {
+        return Ok(false);
+    };
+    if overwrite == OverwritePolicy::Never {
+        return Ok(true);
+    }
+    // ETags of multipart uploads are not MD5 checksums, so compare sizes
+    for url in urls.iter() {
+        if let Ok(Some(len)) = client.content_length(url).await {
+            return Ok(len == size);
+        }
+    }
+    Ok(true)
+}
+
+/// Upload the local file and its sidecar to `sink`, then remove them
+async fn upload(sink: &Sink, local: &Path, relative: &Path) -> Result<()> {
+    sink.upload(local, relative).await?;
+    remove_file(local)?;
+    let sidecar = sidecar_path(local);
+    if sidecar.exists() {
+        sink.upload(&sidecar, &sidecar_path(relative)).await?;
+        remove_file(sidecar)?;
+    }
+    Ok(())
+}
+
+/// Failed report of a sample without a directory on the server, see [`OneKpRecord::is_available`]
+fn unavailable_report(rec: &OneKpRecord) -> SampleReport {
+    SampleReport {
+        id: rec.id.clone(),
+        species: rec.species.clone(),
+        status: SampleStatus::Failed,
+        duration_secs: 0.0,
+        error: Some(OneKpError::Unavailable(rec.id.clone()).to_string()),
+        files: vec![],
+    }
+}
+
+async fn fetch_and_save(
+    rec: &OneKpRecord,
+    basedir: &Path,
+    template: &PathTemplate,
+    ctx: &FetchContext,
+    options: &FetchOptions,
+) -> SampleReport {
+    let FetchContext {
+        client,
+        progress,
+        checksums,
+        mirrors,
+        workdir,
+        sink,
+    } = ctx;
+    if !rec.is_available() {
+        return unavailable_report(rec);
+    }
+    let checksums = checksums.as_deref();
+    let started = Instant::now();
+    let mut files = vec![];
+    let mut error = None;
+
+    for filename in options.filenames.iter() {
+        let relative = template.render(rec, filename, options.name_by);
+        let path = basedir.join(&relative);
+        let local = workdir.join(&relative);
+        let urls = mirrors.urls(&rec.to_gigadb_path(filename));
+        let url = urls[0].clone();
+        let expected = checksums.and_then(|c| c.get(&rec.to_gigadb_path(filename)));
+        let alphabet = Alphabet::of(filename);
+        if checksums.is_some() && expected.is_none() {
+            progress.println(&format!(
+                "{}: no checksum for {}",
+                "Warning".yellow(),
+                path.display()
+            ));
+        }
+
+        let file_started = Instant::now();
+        let mut result = match sink {
+            Some(sink) => {
+                match is_uploaded(sink, &relative, &urls, client, options.overwrite).await {
+                    Ok(true) => {
+                        progress.println(&format!(
+                            "{}: {} already exists",
+                            "Skipped".cyan(),
+                            path.display()
+                        ));
+                        Ok((FileStatus::Skipped, DownloadStats::default()))
+                    }
+                    Ok(false) => {
+                        fetch_file(&urls, &local, expected, alphabet, client, progress, options)
+                            .await
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            None => fetch_file(&urls, &local, expected, alphabet, client, progress, options).await,
+        };
+        if options.sidecar {
+            if let Ok((FileStatus::Downloaded, _)) = &result {
+                if let Err(err) = write_sidecar(rec, &url, &local, expected).await {
+                    result = Err(err);
+                }
+            }
+        }
+        if let Some(sink) = sink {
+            // a local file left by a failed upload is uploaded as well
+            if result.is_ok() && local.exists() {
+                if let Err(err) = upload(sink, &local, &relative).await {
+                    result = Err(err);
+                }
+            }
+        }
+        let duration_secs = file_started.elapsed().as_secs_f64();
+
+        match result {
+            Ok((status, stats)) => files.push(FileReport {
+                url,
+                path,
+                status,
+                content_length: stats.content_length,
+                records: stats.records,
+                bytes: stats.bytes,
+                duration_secs,
+                retries: stats.retries,
+                error: None,
+            }),
+            Err(err) => {
+                let status = if OneKpError::find::<ChecksumMismatch>(&err).is_some() {
+                    FileStatus::ChecksumMismatch
+                } else if OneKpError::find::<Corrupt>(&err).is_some() {
+                    FileStatus::Corrupt
+                } else {
+                    FileStatus::Failed
+                };
+                files.push(FileReport {
+                    url,
+                    path,
+                    status,
+                    content_length: None,
+                    records: None,
+                    bytes: 0,
+                    duration_secs,
+                    retries: OneKpError::find::<RetriesExhausted>(&err).map_or(0, |e| e.retries),
+                    error: Some(err.to_string()),
+                });
+                error = Some(err.to_string());
+                break;
+            }
+        }
+    }
+
+    let status = if error.is_some() {
+        SampleStatus::Failed
+    } else if files.iter().all(|f| f.status == FileStatus::Skipped) {
+        SampleStatus::Skipped
+    } else {
+        SampleStatus::Success
+    };
+
+    SampleReport {
+        id: rec.id.clone(),
+        species: rec.species.clone(),
+        status,
+        duration_secs: started.elapsed().as_secs_f64(),
+        error,
+        files,
+    }
+}
+
+/// `client` reporting its retries through `progress`
+fn notifying(client: Client, progress: &Progress) -> Client {
+    let progress = progress.clone();
+    client.with_notices(move |msg| progress.println(msg))
+}
+
+/// Download the `sequence_type` files of one sample into `dest`, with the default `fetch` options.
+/// Files are verified against `checksums` when given, and existing identical files are kept.
+pub async fn fetch_sample(
+    rec: &OneKpRecord,
+    sequence_type: SequenceType,
+    dest: &Path,
+    client: &Client,
+    mirrors: &Mirrors,
+    checksums: Option<Arc<Checksums>>,
+) -> SampleReport {
+    let progress = Progress::new(1);
+    let ctx = FetchContext {
+        client: notifying(client.clone(), &progress),
+        progress,
+        checksums,
+        mirrors: mirrors.clone(),
+        workdir: dest.to_owned(),
+        sink: None,
+    };
+    let options = FetchOptions::new(sequence_type);
+    let report = fetch_and_save(rec, dest, &PathTemplate::default(), &ctx, &options).await;
+    ctx.progress.finish();
+    report
+}
+
+/// Download the `sequence_type` files of `records` into `dest`, `jobs` samples at a time,
+/// with the default `fetch` options. Progress and results are reported as [`DownloadEvent`]s
+/// instead of on the terminal. The stream ends when every sample is finished,
+/// and dropping it cancels the downloads.
+pub fn fetch_stream(
+    records: Vec<OneKpRecord>,
+    sequence_type: SequenceType,
+    dest: PathBuf,
+    client: Client,
+    mirrors: Mirrors,
+    checksums: Option<Arc<Checksums>>,
+    jobs: usize,
+) -> impl Stream<Item = DownloadEvent> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let progress = Progress::with_events(tx);
+    let ctx = FetchContext {
+        client: notifying(client, &progress),
+        progress,
+        checksums,
+        mirrors,
+        workdir: dest.clone(),
+        sink: None,
+    };
+    let options = FetchOptions::new(sequence_type);
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut tasks = JoinSet::new();
+    for rec in records.into_iter() {
+        let semaphore = semaphore.clone();
+        let dest = dest.clone();
+        let ctx = ctx.clone();
+        let options = options.clone();
+        tasks.spawn(async move {
+            let Ok(_permit) = semaphore.acquire_owned().await else {
+                return;
+            };
+            let report =
+                fetch_and_save(&rec, &dest, &PathTemplate::default(), &ctx, &options).await;
+            ctx.progress.send(DownloadEvent::SampleFinished(report));
+        });
+    }
+    // the channel closes when the last task drops its sender
+    drop(ctx);
+    futures_util::stream::unfold((rx, tasks), |(mut rx, tasks)| async move {
+        let event = rx.recv().await?;
+        Some((event, (rx, tasks)))
+    })
+}
+
+/// Record where the downloaded file `path` came from in `<file>.meta.json`
+async fn write_sidecar(
+    rec: &OneKpRecord,
+    url: &str,
+    path: &Path,
+    verified_md5: Option<&str>,
+) -> Result<()> {
+    let md5 = match verified_md5 {
+        Some(md5) => md5.to_owned(),
+        None => compute_md5(path).await?,
+    };
+    Ok(Provenance::new(url, path, &md5, rec)?.write(path)?)
+}
+
+/// Total size of the files to be downloaded
+#[derive(Debug, Clone, Copy, Default)]
+struct SizeEstimate {
+    files: usize,
+    bytes: u64,
+    // files whose size could not be determined
+    unknown: usize,
+}
+
+/// HEAD requests in flight while estimating the download size
+const ESTIMATE_CONCURRENCY: usize = 8;
+
+/// Sum the sizes of every file, asking the mirrors in the order the download uses them.
+/// The requests bypass the interval between requests, a few at a time.
+async fn estimate_size(
+    records: &[OneKpRecord],
+    filenames: &[&str],
+    mirrors: &Mirrors,
+    client: &Client,
+) -> SizeEstimate {
+    let client = client.unthrottled();
+    let semaphore = Arc::new(Semaphore::new(ESTIMATE_CONCURRENCY));
+    let mut tasks = JoinSet::new();
+    for rec in records.iter() {
+        for filename in filenames.iter() {
+            let urls = mirrors.urls(&rec.to_gigadb_path(filename));
+            let client = client.clone();
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.ok()?;
+                for url in urls.iter() {
+                    if let Ok(Some(len)) = client.content_length(url).await {
+                        return Some(len);
+                    }
+                }
+                None
+            });
+        }
+    }
+
+    let mut estimate = SizeEstimate::default();
+    while let Some(joined) = tasks.join_next().await {
+        estimate.files += 1;
+        match joined.ok().flatten() {
+            Some(len) => estimate.bytes += len,
+            None => estimate.unknown += 1,
+        }
+    }
+    estimate
+}
+
+/// Free space of the file system `dir` is or will be on
+fn available_space(dir: &Path) -> Result<u64> {
+    let existing = dir
+        .ancestors()
+        .find(|d| d.exists())
+        .unwrap_or_else(|| Path::new("."));
+    Ok(fs4::available_space(existing)?)
+}
+
+/// Ask on the terminal. Without a terminal there is nobody to ask, so go ahead.
+fn confirm(question: &str) -> Result<bool> {
+    if !stdin().is_terminal() {
+        return Ok(true);
+    }
+    eprint!("{} [y/N] ", question);
+    stderr().flush()?;
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Write `metadata.tsv` into `workdir` with a row for every file of `records` that `exists`,
+/// so that the directory describes itself. Returns whether it was written.
+fn write_metadata(
+    records: &[OneKpRecord],
+    rootdir: &Path,
+    workdir: &Path,
+    file_paths: impl Fn(&OneKpRecord) -> Vec<PathBuf>,
+    exists: impl Fn(&Path) -> bool,
+) -> Result<bool> {
+    let mut lines = vec![format!("{}\tFile", METADATA_HEADER)];
+    for rec in records.iter() {
+        for path in file_paths(rec).iter().filter(|p| exists(p)) {
+            lines.push(format!(
+                "{}\t{}",
+                rec.to_tsv(),
+                path.strip_prefix(rootdir).unwrap_or(path).display()
+            ));
+        }
+    }
+    if lines.len() == 1 {
+        return Ok(false);
+    }
+    lines.push(String::new());
+    write_atomic(
+        &workdir.join(METADATA_FILENAME),
+        lines.join("\n").as_bytes(),
+    )?;
+    Ok(true)
+}
+
+/// Download `records` and print the summary.
+/// `locked` checksums are used instead of the published list, got through `cache`, when given.
+/// Returns `None` when nothing was fetched (dry run or aborted).
+pub async fn run_fetch(
+    mut records: Vec<OneKpRecord>,
+    args: &FetchArgs,
+    client: Client,
+    mirrors: Mirrors,
+    cache: &Cache,
+    locked: Option<Checksums>,
+) -> Result<Option<FetchReport>, OneKpError> {
+    let FetchArgs {
+        rootdir,
+        sequence_type,
+        jobs,
+        no_verify,
+        no_sidecar,
+        redownload,
+        overwrite,
+        limit_rate,
+        path_template,
+        name_by,
+        protocol,
+        downloader,
+        validate,
+        validate_fasta,
+        manifest,
+        products,
+        ..
+    } = args.clone();
+    let filenames = sequence_type.with_products(&products);
+    // reported as failed without trying
+    let unavailable: Vec<OneKpRecord> = records
+        .iter()
+        .filter(|rec| !rec.is_available())
+        .cloned()
+        .collect();
+    records.retain(|rec| rec.is_available());
+
+    // the samples of a run come from one dataset
+    let source = records.first().map(|rec| rec.source());
+    let checksum_url = source.and_then(|s| s.checksum_url()).map(str::to_owned);
+    let ftp_base_url = source.and_then(|s| s.ftp_base_url()).map(str::to_owned);
+    if protocol == Protocol::Ftp && ftp_base_url.is_none() {
+        return Err(OneKpError::InvalidOptions(format!(
+            "{} is not served over FTP",
+            source.map(|s| s.name()).unwrap_or_default()
+        )));
+    }
+    let mirrors = match protocol {
+        Protocol::Https => mirrors,
+        Protocol::Ftp => match &ftp_base_url {
+            Some(base) => mirrors.with_primary(base),
+            None => mirrors,
+        },
+    };
+
+    // cloud storage is written through a local staging directory
+    let sink = Sink::parse(&rootdir)?.map(Arc::new);
+    if sink.is_some() && !manifest.is_empty() {
+        return Err(OneKpError::InvalidOptions(
+            "--manifest needs a local root directory".to_owned(),
+        ));
+    }
+    let workdir = match &sink {
+        Some(sink) => sink.staging_dir(),
+        None => rootdir.clone(),
+    };
+
+    let file_paths = |rec: &OneKpRecord| -> Vec<PathBuf> {
+        filenames
+            .iter()
+            .map(|filename| rootdir.join(path_template.render(rec, filename, name_by)))
+            .collect()
+    };
+    // including the samples skipped by --resume
+    let selected = records.clone();
+    let mut state = if args.resume {
+        let state = FetchState::load(&workdir)?;
+        let total = records.len();
+        records.retain(|rec| !state.is_done(&rec.id, &file_paths(rec)));
+        eprintln!(
+            "{}: {} of {} samples already done",
+            "Resuming".cyan(),
+            total - records.len(),
+            total
+        );
+        state
+    } else {
+        FetchState::default()
+    };
+
+    // two samples must not write to the same file, e.g. with duplicated sample IDs
+    let mut destinations = BTreeMap::new();
+    for rec in records.iter() {
+        for path in file_paths(rec) {
+            if let Some(other) = destinations.insert(path.clone(), rec.id.clone()) {
+                return Err(OneKpError::InvalidOptions(format!(
+                    "{} and {} would both be saved to {}, change --name-by or --path-template",
+                    other,
+                    rec.id,
+                    path.display()
+                )));
+            }
+        }
+    }
+
+    if args.dry_run {
+        // the URL tried first, the destination, then the mirrors in the order they are tried
+        for rec in records.iter() {
+            for filename in filenames.iter() {
+                let mut urls = mirrors.urls(&rec.to_gigadb_path(filename)).into_iter();
+                let dest = rootdir.join(path_template.render(rec, filename, name_by));
+                let mut line = format!("{}\t{}", urls.next().unwrap_or_default(), dest.display());
+                for url in urls {
+                    line.push('\t');
+                    line.push_str(&url);
+                }
+                println!("{}", line);
+            }
+        }
+        for rec in unavailable.iter() {
+            let report = unavailable_report(rec);
+            eprintln!(
+                "{}: {}",
+                "Unavailable".yellow(),
+                report.error.unwrap_or_default()
+            );
+        }
+        eprintln!(
+            "{}: {} samples, {} files, {} samples unavailable",
+            "Dry run".cyan(),
+            records.len(),
+            records.len() * filenames.len(),
+            unavailable.len()
+        );
+        return Ok(None);
+    }
+
+    let client = match limit_rate {
+        Some(rate) => client.with_limit_rate(rate),
+        None => client,
+    };
+    let options = FetchOptions {
+        filenames: filenames.clone(),
+        name_by,
+        overwrite,
+        redownload,
+        sidecar: !no_sidecar,
+        downloader,
+        validate,
+        validate_fasta,
+    };
+    let checksums = if no_verify {
+        None
+    } else if let Some(locked) = locked {
+        Some(Arc::new(locked))
+    } else if let Some(url) = checksum_url {
+        match cache.get(&url, &client).await {
+            Ok(text) => Some(Arc::new(Checksums::parse(&text))),
+            Err(err) => {
+                eprintln!(
+                    "{}: checksum list is not available, skip verification\n{}",
+                    "Warning".yellow(),
+                    err
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // the space check is pointless when files only pass through a staging directory
+    let check_space = sink.is_none();
+    // without a terminal there is nobody to ask
+    let ask = !args.yes && stdin().is_terminal();
+    let estimate = if ask || (check_space && !args.ignore_space_check) {
+        eprintln!("Estimating download size...");
+        Some(estimate_size(&records, &filenames, &mirrors, &client).await)
+    } else {
+        None
+    };
+
+    if let Some(estimate) = estimate.filter(|_| check_space) {
+        // files already on disk are skipped or resumed
+        let on_disk: u64 = records
+            .iter()
+            .flat_map(&file_paths)
+            .flat_map(|path| [part_path(&path), path])
+            .filter_map(|path| metadata(path).ok())
+            .map(|m| m.len())
+            .sum();
+        let needed = estimate.bytes.saturating_sub(on_disk);
+        let available = available_space(&workdir)?;
+        if needed > available {
+            let msg = format!(
+                "{} is needed in {} but only {} is available",
+                HumanBytes(needed),
+                workdir.display(),
+                HumanBytes(available)
+            );
+            if !args.ignore_space_check {
+                return Err(OneKpError::InsufficientSpace(format!(
+                    "{}. Use --ignore-space-check to fetch anyway",
+                    msg
+                )));
+            }
+            eprintln!("{}: {}", "Warning".yellow(), msg);
+        }
+    }
+
+    if let Some(estimate) = estimate.filter(|_| ask) {
+        let mut question = format!(
+            "About to download {} files, ~{}",
+            estimate.files,
+            HumanBytes(estimate.bytes)
+        );
+        if estimate.unknown > 0 {
+            question.push_str(&format!(" ({} files of unknown size)", estimate.unknown));
+        }
+        if !confirm(&format!("{} — continue?", question))? {
+            eprintln!("Aborted");
+            return Ok(None);
+        }
+    }
+
+    for rec in records.iter() {
+        state.enqueue(&rec.id, file_paths(rec));
+    }
+    if let Some(sink) = &sink {
+        create_dir_all(&workdir)?;
+        eprintln!("Uploading to {} through {}", sink.url(), workdir.display());
+    }
+    state.save(&workdir)?;
+
+    if downloader == Downloader::Aria2c {
+        // existing files are checked by the usual pipeline afterwards
+        let entries: Vec<aria2::Entry> = records
+            .iter()
+            .flat_map(|rec| {
+                filenames.iter().map(|filename| {
+                    let path = workdir.join(path_template.render(rec, filename, name_by));
+                    (path, mirrors.urls(&rec.to_gigadb_path(filename)))
+                })
+            })
+            .filter(|(path, _)| !path.exists() || overwrite == OverwritePolicy::Always)
+            .map(|(path, urls)| aria2::Entry {
+                urls,
+                out: part_path(&path),
+            })
+            .collect();
+        if !entries.is_empty() {
+            eprintln!("Downloading {} files with aria2c", entries.len());
+            if let Err(err) =
+                aria2::run(&entries, &workdir, jobs, client.options(), limit_rate).await
+            {
+                eprintln!("{}: {}", "Warning".yellow(), err);
+            }
+        }
+    }
+
+    let started = Instant::now();
+    let started_at = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(anyhow::Error::from)?
+        .as_secs();
+    let mut success_ids = vec![];
+    let mut err_ids = vec![];
+    let mut skipped_ids = vec![];
+    let mut mismatch_ids = vec![];
+    let mut pending_ids: Vec<String> = records.iter().map(|r| r.id.clone()).collect();
+    let progress = Progress::new(records.len() as u64);
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut tasks = JoinSet::new();
+    progress.println("--- Fetching start ---");
+    let ctx = FetchContext {
+        client: notifying(client, &progress),
+        progress: progress.clone(),
+        checksums,
+        mirrors,
+        workdir: workdir.clone(),
+        sink: sink.clone(),
+    };
+    for rec in records.into_iter() {
+        let semaphore = semaphore.clone();
+        let rootdir = rootdir.clone();
+        let path_template = path_template.clone();
+        let ctx = ctx.clone();
+        let options = options.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let report = fetch_and_save(&rec, &rootdir, &path_template, &ctx, &options).await;
+            Ok::<_, anyhow::Error>(report)
+        });
+    }
+    let mut samples = vec![];
+    let mut interrupted = false;
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        let joined = tokio::select! {
+            joined = tasks.join_next() => match joined {
+                Some(joined) => joined,
+                None => break,
+            },
+            _ = &mut ctrl_c, if !interrupted => {
+                interrupted = true;
+                progress.println(&format!(
+                    "{}: cancelling the remaining downloads, partial files are kept as *.part to resume later",
+                    "Interrupted".yellow()
+                ));
+                tasks.abort_all();
+                continue;
+            }
+        };
+        let sample = match joined {
+            Ok(sample) => sample?,
+            // aborted by --fail-fast or Ctrl-C
+            Err(err) if err.is_cancelled() => continue,
+            Err(err) => return Err(anyhow::Error::from(err).into()),
+        };
+        pending_ids.retain(|id| id != &sample.id);
+        state.update(&sample);
+        state.save(&workdir)?;
+        match sample.status {
+            SampleStatus::Success => {
+                progress.println(&format!("{}: {}", "Success".green(), sample.species));
+                success_ids.push(sample.id.clone());
+            }
+            SampleStatus::Skipped => {
+                progress.println(&format!("{}: {}", "Skipped".cyan(), sample.species));
+                skipped_ids.push(sample.id.clone());
+            }
+            SampleStatus::Failed => {
+                progress.println(&format!(
+                    "{}: {}\n{}",
+                    "Failed".red(),
+                    sample.species,
+                    sample.error.as_deref().unwrap_or_default()
+                ));
+                if sample.has_checksum_mismatch() {
+                    mismatch_ids.push(sample.id.clone());
+                }
+                err_ids.push(sample.id.clone());
+                if args.fail_fast && !tasks.is_empty() {
+                    progress.println("Aborting the remaining downloads (--fail-fast)");
+                    tasks.abort_all();
+                }
+            }
+        }
+        samples.push(sample);
+        progress.sample_done();
+    }
+    for rec in unavailable.iter() {
+        let sample = unavailable_report(rec);
+        progress.println(&format!(
+            "{}: {}\n{}",
+            "Failed".red(),
+            sample.species,
+            sample.error.as_deref().unwrap_or_default()
+        ));
+        err_ids.push(sample.id.clone());
+        samples.push(sample);
+    }
+    progress.finish();
+
+    eprintln!("--- Fetching end ---");
+    eprintln!("{}: {}", "Success IDs".green(), success_ids.join(","));
+    eprintln!("{}: {}", "Skipped IDs".cyan(), skipped_ids.join(","));
+    eprintln!("{}: {}", "Failed IDs".red(), err_ids.join(","));
+    if !mismatch_ids.is_empty() {
+        eprintln!(
+            "{}: {}",
+            "Checksum mismatch IDs".red(),
+            mismatch_ids.join(",")
+        );
+    }
+    if !pending_ids.is_empty() {
+        eprintln!("{}: {}", "Not fetched IDs".yellow(), pending_ids.join(","));
+    }
+    match &sink {
+        Some(sink) => {
+            let uploaded = |path: &Path| state.is_file_done(path);
+            if write_metadata(&selected, &rootdir, &workdir, file_paths, uploaded)? {
+                let local = workdir.join(METADATA_FILENAME);
+                sink.upload(&local, Path::new(METADATA_FILENAME)).await?;
+            }
+        }
+        None => {
+            write_metadata(&selected, &rootdir, &workdir, file_paths, Path::exists)?;
+            for algorithm in manifest.iter() {
+                let path = manifest::write(&rootdir, *algorithm)?;
+                eprintln!("Wrote {}", path.display());
+            }
+        }
+    }
+
+    let mut options = args.clone();
+    if let Some(sink) = &sink {
+        options.rootdir = PathBuf::from(sink.url());
+    }
+    Ok(Some(FetchReport {
+        onekp_version: env!("CARGO_PKG_VERSION").to_owned(),
+        started_at,
+        duration_secs: started.elapsed().as_secs_f64(),
+        interrupted,
+        options,
+        metadata: None,
+        samples,
+    }))
+}
+
+/// Default number of samples downloaded concurrently
+pub const JOBS: usize = 1;
+
+/// Options of `fetch`, recorded in the report so that runs can be replayed
+#[derive(Debug, Clone, Args, Serialize, Deserialize)]
+pub struct FetchArgs {
+    #[arg(long, short, env = "ONEKP_ROOTDIR")]
+    pub rootdir: PathBuf,
+    #[arg(long, short, env = "ONEKP_SEQUENCE_TYPE")]
+    pub sequence_type: SequenceType,
+    /// Number of samples downloaded concurrently
+    #[arg(long, short, env = "ONEKP_JOBS", default_value_t = JOBS)]
+    pub jobs: usize,
+    /// Skip MD5 verification of the downloaded files
+    #[arg(long)]
+    pub no_verify: bool,
+    /// Do not write the <file>.meta.json provenance sidecar of each downloaded file
+    #[arg(long)]
+    #[serde(default)]
+    pub no_sidecar: bool,
+    /// Decompress each downloaded .gz file to check that it is complete and not corrupt
+    #[arg(long)]
+    #[serde(default)]
+    pub validate: bool,
+    /// Also check that each decompressed file is well-formed FASTA and count its records. Implies --validate
+    #[arg(long)]
+    #[serde(default)]
+    pub validate_fasta: bool,
+    /// Download a file once more when its MD5 checksum does not match or it fails --validate
+    #[arg(long)]
+    pub redownload: bool,
+    /// Destination of each file relative to the root directory, e.g. {clade}/{order}/{species}/{file}.
+    /// Placeholders: {id}, {clade}, {order}, {family}, {species}, {tissue_type}, {prefix}, {file}
+    #[arg(long, default_value = PathTemplate::DEFAULT, value_parser = PathTemplate::parse)]
+    #[serde(default)]
+    pub path_template: PathTemplate,
+    /// How to name the downloaded files
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(default)]
+    pub name_by: NameBy,
+    /// Transport used for the data files. Proxies only apply to https
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(default)]
+    pub protocol: Protocol,
+    /// Program that transfers the data files. aria2c must be on PATH
+    #[arg(long, value_enum, default_value_t)]
+    #[serde(default)]
+    pub downloader: Downloader,
+    /// What to do when a file already exists in the root directory
+    #[arg(long, value_enum, default_value_t)]
+    pub overwrite: OverwritePolicy,
+    /// Cap the total download speed in bytes per second, e.g. 500K, 5M, 1G
+    #[arg(long, value_parser = parse_byte_rate)]
+    pub limit_rate: Option<u64>,
+    /// Stop at the first failed sample
+    #[arg(long)]
+    pub fail_fast: bool,
+    /// Skip the samples already done according to the state file of the root directory
+    #[arg(long)]
+    #[serde(skip)]
+    pub resume: bool,
+    /// Print the URLs and destination paths without downloading anything
+    #[arg(long)]
+    #[serde(skip)]
+    pub dry_run: bool,
+    /// Do not ask for confirmation before downloading
+    #[arg(long, short)]
+    #[serde(skip)]
+    pub yes: bool,
+    /// Only warn when the root directory does not have enough free space
+    #[arg(long)]
+    #[serde(default)]
+    pub ignore_space_check: bool,
+    /// Other files of each sample to download as well, e.g. assembly,stats
+    #[arg(long, value_enum, value_delimiter = ',')]
+    #[serde(default)]
+    pub products: Vec<Product>,
+    /// Write checksum manifests of every file in the root directory after fetching, e.g. sha256,md5
+    #[arg(long, value_enum, value_delimiter = ',')]
+    #[serde(default)]
+    pub manifest: Vec<ManifestAlgorithm>,
+    /// Write a JSON report of the run to this path
+    #[arg(long)]
+    #[serde(skip)]
+    pub report: Option<PathBuf>,
+}
+
+impl FetchArgs {
+    /// Options of `fetch` with the defaults of the command line for everything but the
+    /// root directory and the sequence type
+    ///
+    /// ```
+    /// use clap::{Args, Command, FromArgMatches};
+    /// use onekp::{FetchArgs, SequenceType};
+    ///
+    /// let cmd = FetchArgs::augment_args(Command::new("fetch"));
+    /// let matches = cmd.get_matches_from(["fetch", "-r", "out", "-s", "protein"]);
+    /// let parsed = FetchArgs::from_arg_matches(&matches).unwrap();
+    /// let built = FetchArgs::new("out".into(), SequenceType::Protein);
+    /// assert_eq!(
+    ///     serde_json::to_value(&parsed).unwrap(),
+    ///     serde_json::to_value(&built).unwrap()
+    /// );
+    /// ```
+    pub fn new(rootdir: PathBuf, sequence_type: SequenceType) -> Self {
+        Self {
+            rootdir,
+            sequence_type,
+            jobs: JOBS,
+            no_verify: false,
+            no_sidecar: false,
+            validate: false,
+            validate_fasta: false,
+            redownload: false,
+            path_template: PathTemplate::default(),
+            name_by: NameBy::default(),
+            protocol: Protocol::default(),
+            downloader: Downloader::default(),
+            overwrite: OverwritePolicy::default(),
+            limit_rate: None,
+            fail_fast: false,
+            resume: false,
+            dry_run: false,
+            yes: false,
+            ignore_space_check: false,
+            products: vec![],
+            manifest: vec![],
+            report: None,
+        }
+    }
+}
@@ -0,0 +1,558 @@
+use std::{
+    collections::BTreeSet,
+    fs::read_to_string,
+    io::{stdin, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::{ArgGroup, Args, ValueEnum};
+use colored::*;
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    alias::clade_alias, error::OneKpError, query::Query, synonym::Synonyms, OneKp, OneKpKey,
+    OneKpRecord,
+};
+
+/// How the subcommands select samples
+#[derive(Debug, Clone, Default, Args)]
+#[command(group(ArgGroup::new("filter_by").args(["filter_key", "filter_rank"])))]
+pub struct FilterArgs {
+    /// Metadata column to select samples by
+    #[arg(long)]
+    pub filter_key: Option<OneKpKey>,
+    /// Rank of the NCBI lineage to select samples by instead of a column, e.g. subclass or tribe.
+    /// Needs `onekp enrich`
+    #[arg(long)]
+    pub filter_rank: Option<String>,
+    /// Values of the column to select, comma-separated, with `*` and `?` wildcards.
+    /// `-` reads them from the standard input
+    #[arg(long, value_delimiter = ',', requires = "filter_by")]
+    pub filter_values: Vec<String>,
+    /// Also select the values listed in this file, one per line. `#` starts a comment, and `-` is the standard input
+    #[arg(long, requires = "filter_by")]
+    pub filter_values_file: Option<PathBuf>,
+    /// Also select the samples whose column matches this regular expression,
+    /// e.g. '^Selaginella' or '(?i)leaf|shoot'
+    #[arg(long, requires = "filter_by")]
+    pub filter_regex: Option<Regex>,
+    /// Also find the species listed under an older name of the --filter-values,
+    /// with the bundled synonym table
+    #[arg(long)]
+    pub synonyms: bool,
+    /// Extra synonyms, one `accepted name<TAB>name in the metadata` pair per line. Implies --synonyms
+    #[arg(long)]
+    pub synonyms_file: Option<PathBuf>,
+    /// How --filter-values and --exclude-values are compared with the column
+    #[arg(long = "match", value_enum, default_value = "exact")]
+    pub match_mode: MatchMode,
+    /// Only select the samples matching this expression,
+    /// e.g. 'clade == "Mosses" && tissue_type != "root" && species ~ "Sphagnum"'
+    #[arg(long)]
+    pub query: Option<Query>,
+    /// Metadata column to leave samples out by
+    #[arg(long, requires = "exclude_values")]
+    pub exclude_key: Option<OneKpKey>,
+    /// Values of --exclude-key whose samples are left out, comma-separated
+    #[arg(long, value_delimiter = ',', requires = "exclude_key")]
+    pub exclude_values: Vec<String>,
+    /// Ignore upper/lower case in --filter-values, --filter-regex and --exclude-values
+    #[arg(long, short = 'i')]
+    pub ignore_case: bool,
+    /// Keep a random subset of this many of the selected samples
+    #[arg(long)]
+    pub sample: Option<usize>,
+    /// Seed of --sample, to draw the same subset again [default: random]
+    #[arg(long, requires = "sample")]
+    pub seed: Option<u64>,
+    /// Skip this many of the selected samples, e.g. to process a large selection in chunks
+    #[arg(long, default_value_t = 0)]
+    pub offset: usize,
+    /// Process at most this many of the selected samples
+    #[arg(long)]
+    pub limit: Option<usize>,
+}
+
+/// Samples selected by [`FilterArgs::select`]
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub records: Vec<OneKpRecord>,
+    /// How the values were resolved, and the values matching no sample, to show the user
+    pub notices: Vec<String>,
+}
+
+/// How a filter value is compared with a metadata column
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum MatchMode {
+    /// The whole value
+    #[default]
+    Exact,
+    /// The beginning of the value
+    Prefix,
+    /// Any part of the value
+    Contains,
+}
+
+impl MatchMode {
+    /// Whether `value` matches `pattern`, where `*` stands for any text and `?` for one character
+    ///
+    /// ```
+    /// use onekp::filter::MatchMode;
+    ///
+    /// assert!(MatchMode::Exact.matches("Poa*", "Poa annua", false));
+    /// assert!(MatchMode::Exact.matches("?oa annua", "Poa annua", false));
+    /// assert!(!MatchMode::Exact.matches("Poa", "Poa annua", false));
+    /// assert!(MatchMode::Contains.matches("ANN?A", "Poa annua", true));
+    /// ```
+    pub fn matches(self, pattern: &str, value: &str, ignore_case: bool) -> bool {
+        if ignore_case {
+            return self.matches(&pattern.to_lowercase(), &value.to_lowercase(), false);
+        }
+        if pattern.contains(['*', '?']) {
+            return match self {
+                Self::Exact => glob_match(pattern, value),
+                Self::Prefix => glob_match(&format!("{}*", pattern), value),
+                Self::Contains => glob_match(&format!("*{}*", pattern), value),
+            };
+        }
+        match self {
+            Self::Exact => value == pattern,
+            Self::Prefix => value.starts_with(pattern),
+            Self::Contains => value.contains(pattern),
+        }
+    }
+}
+
+/// What --filter-values are compared with
+#[derive(Debug, Clone)]
+pub enum Column {
+    Key(OneKpKey),
+    /// Rank of the NCBI lineage of the species
+    Rank(String),
+}
+
+impl Column {
+    pub fn get<'a>(&self, rec: &'a OneKpRecord) -> &'a str {
+        match self {
+            Self::Key(key) => rec.get(*key),
+            Self::Rank(rank) => rec.rank(rank),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Key(key) => key
+                .to_possible_value()
+                .map(|v| v.get_name().to_owned())
+                .unwrap_or_default(),
+            Self::Rank(rank) => rank.clone(),
+        }
+    }
+
+    /// Whether the values come from the lineages looked up by `onekp enrich`
+    fn needs_lineage(&self) -> bool {
+        matches!(
+            self,
+            Self::Rank(_)
+                | Self::Key(OneKpKey::Taxid | OneKpKey::Phylum | OneKpKey::Class | OneKpKey::Genus)
+        )
+    }
+}
+
+/// `KEY=VALUE` of `onekp show --within`: the samples whose column KEY is VALUE, ignoring case.
+/// A common name of a clade stands for the clades it spans.
+///
+/// ```
+/// use onekp::filter::Within;
+///
+/// let within: Within = "clade=Bryophytes".parse().unwrap();
+/// assert!(within.contains("Liverworts") && !within.contains("Conifers"));
+/// assert!("Bryophytes".parse::<Within>().is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Within {
+    pub key: OneKpKey,
+    pub value: String,
+}
+
+impl Within {
+    /// Whether `value` of the column is within [`Within::value`]
+    pub fn contains(&self, value: &str) -> bool {
+        value.eq_ignore_ascii_case(&self.value)
+            || (self.key == OneKpKey::Clade
+                && clade_alias(&self.value).is_some_and(|clades| clades.contains(&value)))
+    }
+
+    pub fn matches(&self, rec: &OneKpRecord) -> bool {
+        self.contains(rec.get(self.key))
+    }
+}
+
+impl FromStr for Within {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, e.g. clade=Mosses, got {:?}", s))?;
+        Ok(Self {
+            key: OneKpKey::from_str(key.trim(), true)?,
+            value: value.trim().to_owned(),
+        })
+    }
+}
+
+impl FilterArgs {
+    /// Whether no filter was given
+    pub fn is_empty(&self) -> bool {
+        self.column().is_none() && self.query.is_none()
+    }
+
+    /// --filter-key or --filter-rank
+    fn column(&self) -> Option<Column> {
+        match (self.filter_key, &self.filter_rank) {
+            (Some(key), _) => Some(Column::Key(key)),
+            (None, Some(rank)) => Some(Column::Rank(rank.to_lowercase())),
+            (None, None) => None,
+        }
+    }
+
+    /// Samples selected by the filter, every sample without one, less the excluded ones
+    pub fn select(&self, onekp: &OneKp) -> Result<Selection, OneKpError> {
+        let mut notices = vec![];
+        let mut selected = match self.column() {
+            Some(column) => self.include(onekp, &column, &mut notices)?,
+            None => onekp.records().to_vec(),
+        };
+        if let Some(query) = &self.query {
+            selected.retain(|r| query.matches(r));
+        }
+        if let Some(key) = self.exclude_key {
+            selected.retain(|r| {
+                !self
+                    .exclude_values
+                    .iter()
+                    .any(|v| self.match_mode.matches(v, r.get(key), self.ignore_case))
+            });
+        }
+        if let Some(n) = self.sample {
+            let seed = self.seed.unwrap_or_else(random_seed);
+            let total = selected.len();
+            selected = sample(selected, n, seed);
+            notices.push(format!(
+                "Sampled {} of {} samples with --seed {}",
+                selected.len(),
+                total,
+                seed
+            ));
+        }
+        Ok(Selection {
+            records: selected
+                .into_iter()
+                .skip(self.offset)
+                .take(self.limit.unwrap_or(usize::MAX))
+                .collect(),
+            notices,
+        })
+    }
+
+    /// --filter-values followed by the values of --filter-values-file,
+    /// `-` standing for the values on the standard input
+    fn values(&self) -> Result<Vec<String>, OneKpError> {
+        let mut values = vec![];
+        for value in self.filter_values.iter() {
+            if value == "-" {
+                values.extend(parse_values(&read_list(None)?));
+            } else {
+                values.push(value.clone());
+            }
+        }
+        if let Some(path) = &self.filter_values_file {
+            let path = Some(path.as_path()).filter(|p| *p != Path::new("-"));
+            values.extend(parse_values(&read_list(path)?));
+        }
+        Ok(values)
+    }
+
+    /// The bundled synonym table with --synonyms-file, if synonyms are resolved
+    fn synonyms(&self) -> Result<Option<Synonyms>, OneKpError> {
+        if !self.synonyms && self.synonyms_file.is_none() {
+            return Ok(None);
+        }
+        let mut synonyms = Synonyms::bundled();
+        if let Some(path) = &self.synonyms_file {
+            synonyms.extend(Synonyms::parse(&read_list(Some(path))?)?);
+        }
+        Ok(Some(synonyms))
+    }
+
+    /// Replace the accepted species names among `values` with the names in the metadata
+    fn resolve_synonyms(
+        &self,
+        values: Vec<String>,
+        notices: &mut Vec<String>,
+    ) -> Result<Vec<String>, OneKpError> {
+        let Some(synonyms) = self.synonyms()? else {
+            return Ok(values);
+        };
+        let mut resolved = vec![];
+        for value in values {
+            match synonyms.resolve(&value) {
+                Some(names) => {
+                    notices.push(format!("{:?} is listed as {}", value, names.join(", ")));
+                    resolved.extend(names.iter().cloned());
+                }
+                None => resolved.push(value),
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Replace the common names among `values` matching no clade with the clades they span
+    fn resolve_clade_aliases(
+        &self,
+        onekp: &OneKp,
+        values: Vec<String>,
+        notices: &mut Vec<String>,
+    ) -> Vec<String> {
+        let mut resolved = vec![];
+        for value in values {
+            let known = onekp
+                .records()
+                .iter()
+                .any(|r| self.match_mode.matches(&value, &r.clade, self.ignore_case));
+            match clade_alias(&value).filter(|_| !known) {
+                Some(clades) => {
+                    notices.push(format!("{:?} stands for {}", value, clades.join(", ")));
+                    resolved.extend(clades.iter().map(|c| c.to_string()));
+                }
+                None => resolved.push(value),
+            }
+        }
+        resolved
+    }
+
+    fn include(
+        &self,
+        onekp: &OneKp,
+        column: &Column,
+        notices: &mut Vec<String>,
+    ) -> Result<Vec<OneKpRecord>, OneKpError> {
+        if column.needs_lineage() && onekp.records().iter().all(|r| r.lineage.is_none()) {
+            return Err(OneKpError::InvalidOptions(format!(
+                "{} comes from the NCBI lineages, look them up with `onekp enrich` first",
+                column.name()
+            )));
+        }
+        let mut values = self.values()?;
+        match column {
+            Column::Key(OneKpKey::Species) => values = self.resolve_synonyms(values, notices)?,
+            Column::Key(OneKpKey::Clade) => {
+                values = self.resolve_clade_aliases(onekp, values, notices)
+            }
+            _ => {}
+        }
+        if values.is_empty() && self.filter_regex.is_none() {
+            return Err(OneKpError::InvalidOptions(
+                "--filter-key and --filter-rank need --filter-values, --filter-values-file or --filter-regex"
+                    .to_owned(),
+            ));
+        }
+
+        let regex = match &self.filter_regex {
+            Some(regex) if self.ignore_case => Some(
+                RegexBuilder::new(regex.as_str())
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|err| OneKpError::InvalidOptions(err.to_string()))?,
+            ),
+            regex => regex.clone(),
+        };
+        let selected = onekp
+            .records()
+            .iter()
+            .filter(|r| {
+                let value = column.get(r);
+                values
+                    .iter()
+                    .any(|v| self.match_mode.matches(v, value, self.ignore_case))
+                    || regex.as_ref().is_some_and(|regex| regex.is_match(value))
+            })
+            .cloned()
+            .collect();
+        self.warn_unmatched(onekp, column, &values, notices);
+        Ok(selected)
+    }
+
+    /// Warn about the values matching no sample, suggesting the closest known values
+    fn warn_unmatched(
+        &self,
+        onekp: &OneKp,
+        column: &Column,
+        values: &[String],
+        notices: &mut Vec<String>,
+    ) {
+        let known: BTreeSet<&str> = onekp.records().iter().map(|r| column.get(r)).collect();
+        for value in values.iter() {
+            if known
+                .iter()
+                .any(|k| self.match_mode.matches(value, k, self.ignore_case))
+            {
+                continue;
+            }
+            let mut message = format!("no {} {:?}", column.name(), value);
+            let suggestions = suggest(value, known.iter().copied());
+            if !suggestions.is_empty() {
+                message.push_str(&format!("; did you mean {}?", suggestions.join(" or ")));
+            }
+            notices.push(format!("{}: {}", "Warning".yellow(), message));
+        }
+    }
+
+    /// Same as [`FilterArgs::select`], refusing to select every sample implicitly
+    pub fn select_required(&self, onekp: &OneKp) -> Result<Selection, OneKpError> {
+        if self.is_empty() {
+            return Err(OneKpError::InvalidOptions(
+                "select samples with --filter-key and --filter-values or --filter-regex, or --query"
+                    .to_owned(),
+            ));
+        }
+        self.select(onekp)
+    }
+}
+
+/// Text of the list file `path`, or of the standard input
+fn read_list(path: Option<&Path>) -> Result<String, OneKpError> {
+    let Some(path) = path else {
+        let mut text = String::new();
+        stdin().read_to_string(&mut text)?;
+        return Ok(text);
+    };
+    read_to_string(path).map_err(|err| {
+        OneKpError::InvalidOptions(format!("Cannot read {}: {}", path.display(), err))
+    })
+}
+
+/// Values of a list file: one per line, without blank lines and `#` comments
+fn parse_values(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_owned())
+        .collect()
+}
+
+/// Match the whole `value` against a `*` / `?` wildcard pattern,
+/// backtracking only to the last `*`
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    // position of the last `*` in the pattern and the value position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, v));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match star {
+                // let the `*` swallow one more character
+                Some((star_p, star_v)) => {
+                    star = Some((star_p, star_v + 1));
+                    p = star_p + 1;
+                    v = star_v + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// `n` records drawn from `records` without replacement, in their original order.
+/// The same seed draws the same records from the same table.
+fn sample(records: Vec<OneKpRecord>, n: usize, seed: u64) -> Vec<OneKpRecord> {
+    let mut rng = SplitMix64(seed);
+    let mut indices: Vec<usize> = (0..records.len()).collect();
+    let n = n.min(records.len());
+    // partial Fisher-Yates shuffle
+    for i in 0..n {
+        let j = i + (rng.next() % (indices.len() - i) as u64) as usize;
+        indices.swap(i, j);
+    }
+    let mut chosen = indices[..n].to_vec();
+    chosen.sort_unstable();
+    chosen.into_iter().map(|i| records[i].clone()).collect()
+}
+
+fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// Small PRNG whose sequence is fixed by the seed, unlike those of external crates across versions
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Up to three of `candidates` closest to `value` by edit distance, ignoring case,
+/// quoted for display. Nothing is suggested when every candidate is too far off to be a typo.
+///
+/// ```
+/// use onekp::filter::suggest;
+///
+/// let known = ["Arabidopsis thaliana", "Arabidopsis lyrata", "Poa annua"];
+/// assert_eq!(
+///     suggest("Arabidopsis thalianna", known.into_iter()),
+///     [r#""Arabidopsis thaliana""#]
+/// );
+/// ```
+pub fn suggest<'a>(value: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let value = value.to_lowercase();
+    let max_distance = (value.chars().count() / 5).max(2);
+    let mut scored: Vec<(usize, &str)> = candidates
+        .map(|c| (edit_distance(&value, &c.to_lowercase()), c))
+        .filter(|(d, _)| *d <= max_distance)
+        .collect();
+    scored.sort();
+    let best = scored.first().map(|(d, _)| *d);
+    scored
+        .into_iter()
+        .take_while(|(d, _)| Some(*d) == best)
+        .take(3)
+        .map(|(_, c)| format!("{:?}", c))
+        .collect()
+}
+
+/// Levenshtein distance between `a` and `b` in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
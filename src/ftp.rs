@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use futures_util::{stream, StreamExt};
+use suppaftp::{tokio::AsyncFtpStream, types::FileType, FtpError, Status};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    client::{Body, NotFound},
+    error::OneKpError,
+};
+
+const ANONYMOUS_USER: &str = "anonymous";
+const ANONYMOUS_PASSWORD: &str = "anonymous@";
+
+/// Log in to the server of an `ftp://` URL, returning the session and the file path
+async fn connect(url: &str, timeout: Duration) -> Result<(AsyncFtpStream, String)> {
+    let parsed = reqwest::Url::parse(url)?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| anyhow!("No host in {}", url))?;
+    let port = parsed.port().unwrap_or(21);
+
+    let mut ftp = tokio::time::timeout(timeout, AsyncFtpStream::connect((host, port)))
+        .await
+        .map_err(|_| anyhow!("Timed out connecting to {}", host))??;
+    let user = match parsed.username() {
+        "" => ANONYMOUS_USER,
+        user => user,
+    };
+    ftp.login(user, parsed.password().unwrap_or(ANONYMOUS_PASSWORD))
+        .await?;
+    ftp.transfer_type(FileType::Binary).await?;
+
+    Ok((ftp, parsed.path().to_owned()))
+}
+
+fn map_err(err: FtpError, url: &str) -> anyhow::Error {
+    match err {
+        FtpError::UnexpectedResponse(resp) if resp.status == Status::FileUnavailable => NotFound {
+            url: url.to_owned(),
+        }
+        .into(),
+        err => err.into(),
+    }
+}
+
+/// Size of the file at `url` from the `SIZE` command
+pub async fn size(url: &str, timeout: Duration) -> Result<Option<u64>> {
+    let (mut ftp, path) = connect(url, timeout).await?;
+    let size = ftp.size(&path).await.map_err(|err| map_err(err, url))?;
+    ftp.quit().await.ok();
+    Ok(Some(size as u64))
+}
+
+/// Retrieve `url` starting at byte `offset` with `REST` + `RETR`
+pub async fn open(url: &str, offset: u64, timeout: Duration) -> Result<Body> {
+    let (mut ftp, path) = connect(url, timeout).await?;
+    let size = ftp.size(&path).await.map_err(|err| map_err(err, url))? as u64;
+    // restart markers are not supported by every server, so only send them when needed
+    let offset = offset.min(size);
+    if offset > 0 {
+        ftp.resume_transfer(offset as usize).await?;
+    }
+    let transfer = ftp
+        .retr_as_stream(&path)
+        .await
+        .map_err(|err| map_err(err, url))?;
+
+    let url = url.to_owned();
+    let chunks = stream::unfold(Some((ftp, transfer)), move |state| {
+        let url = url.clone();
+        async move {
+            let (mut ftp, mut transfer) = state?;
+            let mut buf = vec![0; 1 << 16];
+            match transfer.read(&mut buf).await {
+                Ok(0) => {
+                    // read the completion reply so that truncated transfers are reported
+                    let finished = transfer.finish().await;
+                    ftp.quit().await.ok();
+                    finished
+                        .err()
+                        .map(|err| (Err(OneKpError::Network(err.into())), None))
+                }
+                Ok(n) => {
+                    buf.truncate(n);
+                    Some((Ok(Bytes::from(buf)), Some((ftp, transfer))))
+                }
+                // not a local I/O error, so that mirrors are tried
+                Err(err) => Some((
+                    Err(OneKpError::Network(anyhow!(
+                        "Error receiving {}: {}",
+                        url,
+                        err
+                    ))),
+                    None,
+                )),
+            }
+        }
+    });
+
+    Ok(Body {
+        offset,
+        len: Some(size - offset),
+        stream: chunks.boxed(),
+    })
+}
@@ -0,0 +1,710 @@
+//! Download the assemblies of the [1KP project](https://db.cngb.org/onekp/) from GigaDB.
+//!
+//! [`OneKp`] holds the sample metadata and selects samples with [`OneKp::filter`],
+//! [`Client`] talks to the servers with retries and rate limiting,
+//! and [`run_fetch`] downloads, verifies and records a selection the way `onekp fetch` does.
+//!
+//! ```no_run
+//! use onekp::{Client, ClientOptions, Mirrors, OneKp, OneKpKey};
+//!
+//! # async fn example() -> Result<(), onekp::OneKpError> {
+//! let client = Client::new(&ClientOptions::default())?;
+//! let onekp = OneKp::load(&client).await?;
+//! for rec in onekp.filter(OneKpKey::Clade, &["Mosses".to_owned()]) {
+//!     println!("{}", rec.to_gigadb_url("protein.fa.gz"));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::Result;
+use clap::ValueEnum;
+use select::{document::Document, predicate::Name};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs::{read_to_string, rename, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+pub mod alias;
+pub mod aria2;
+mod cache;
+pub mod checksum;
+pub mod client;
+pub mod config;
+mod error;
+pub mod export;
+pub mod fetch;
+pub mod filter;
+mod ftp;
+pub mod listing;
+pub mod lock;
+pub mod manifest;
+pub mod metadata_version;
+pub mod mirror;
+pub mod onekp_client;
+pub mod output;
+pub mod parquet;
+pub mod path_template;
+pub mod picker;
+mod progress;
+pub mod provenance;
+pub mod query;
+pub mod rate_limit;
+pub mod report;
+pub mod serve;
+mod sftp;
+pub mod sink;
+pub mod snapshot;
+pub mod source;
+pub mod sql;
+pub mod state;
+pub mod stats;
+pub mod strict;
+pub mod summary;
+pub mod synonym;
+pub mod taxonomy;
+pub mod transport;
+pub mod tree;
+pub mod tui;
+pub mod validate;
+pub mod verify;
+pub use cache::{
+    default_cache_dir, is_cache_update_required, use_cache, use_cache_in, Cache, CacheEntry,
+    CacheMode, CacheTtl, CACHE_TTL,
+};
+pub use client::{Client, ClientOptions};
+pub use error::OneKpError;
+pub use fetch::{fetch_stream, run_fetch, Downloader, FetchArgs, OverwritePolicy, Protocol};
+use filter::MatchMode;
+use metadata_version::{MetadataVersion, VersionStore};
+pub use mirror::Mirrors;
+pub use onekp_client::{OneKpClient, OneKpClientBuilder};
+use path_template::sanitize;
+pub use progress::DownloadEvent;
+pub use source::{DataSource, OneKpSource};
+use taxonomy::{Lineage, Taxonomy};
+
+/// Which assemblies of a sample to download
+#[derive(Debug, Clone, Copy, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SequenceType {
+    /// Translated nucleotide sequences (CDS)
+    Nucleotide,
+    /// Translated protein sequences
+    Protein,
+    /// Nucleotide and protein
+    Both,
+    /// Contigs of the SOAPdenovo-Trans assembly
+    Assembly,
+    /// Scaffolds of the SOAPdenovo-Trans assembly
+    Scaffold,
+    /// Every sequence file of the sample
+    All,
+}
+
+/// File name of the SOAPdenovo-Trans contigs
+const ASSEMBLY_FILENAME: &str = "SOAPdenovo-Trans-assembly.fa.gz";
+
+impl SequenceType {
+    /// File names of the assemblies, as used by [`OneKpRecord::to_gigadb_path`]
+    pub fn to_filenames(self) -> Vec<&'static str> {
+        let nucleotide = "nucleotides.fa.gz";
+        let protein = "protein.fa.gz";
+        let scaffold = "SOAPdenovo-Trans-scaffolds.fa.gz";
+        match self {
+            Self::Nucleotide => vec![nucleotide],
+            Self::Protein => vec![protein],
+            Self::Both => vec![nucleotide, protein],
+            Self::Assembly => vec![ASSEMBLY_FILENAME],
+            Self::Scaffold => vec![scaffold],
+            Self::All => vec![nucleotide, protein, ASSEMBLY_FILENAME, scaffold],
+        }
+    }
+
+    /// File names of the assemblies followed by those of `products`, without duplicates
+    pub fn with_products(self, products: &[Product]) -> Vec<&'static str> {
+        let mut filenames = self.to_filenames();
+        for filename in products.iter().map(|p| p.filename()) {
+            if !filenames.contains(&filename) {
+                filenames.push(filename);
+            }
+        }
+        filenames
+    }
+}
+
+/// Files of a sample besides the translated assemblies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Product {
+    /// Raw SOAPdenovo-Trans assembly the translations were made from
+    Assembly,
+    /// Assembly statistics of the sample
+    Stats,
+}
+
+impl Product {
+    /// File name of the product, as used by [`OneKpRecord::to_gigadb_path`]
+    pub fn filename(self) -> &'static str {
+        match self {
+            Self::Assembly => ASSEMBLY_FILENAME,
+            Self::Stats => "stats.txt",
+        }
+    }
+}
+
+/// How downloaded files are named
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameBy {
+    /// GigaDB directory name of the sample followed by the file name
+    #[default]
+    Prefix,
+    /// Sample ID, e.g. ABCD-protein.fa.gz
+    Id,
+    /// Species and sample ID, e.g. Arabidopsis_thaliana-ABCD-protein.fa.gz
+    Species,
+}
+
+/// Value of the columns missing from the sample table or not looked up with `onekp enrich`
+pub const NO_DATA: &str = "No data";
+
+/// Metadata of a 1KP sample
+#[derive(Debug, Clone, Serialize)]
+pub struct OneKpRecord {
+    /// Four-letter sample ID, e.g. URDJ
+    pub id: String,
+    pub clade: String,
+    pub order: String,
+    pub family: String,
+    pub species: String,
+    pub tissue_type: String,
+    /// GigaDB directory name of the sample
+    pub prefix: String,
+    /// Where the sample is published, the 1KP assemblies on GigaDB when `None`
+    #[serde(skip)]
+    pub source: Option<Arc<dyn DataSource>>,
+    /// NCBI Taxonomy of the species, once looked up with `onekp enrich`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lineage: Option<Lineage>,
+}
+
+impl OneKpRecord {
+    /// Whether the directory of the sample on the server is known. Samples of a 1KP ID missing
+    /// from the assemblies listing, or matching several directories, cannot be downloaded.
+    pub fn is_available(&self) -> bool {
+        !self.prefix.is_empty()
+    }
+
+    /// Local name of the file `filename` of the sample
+    pub fn to_filename(&self, filename: &str, name_by: NameBy) -> String {
+        match name_by {
+            NameBy::Prefix => format!("{}-{}", self.prefix, filename),
+            NameBy::Id => format!("{}-{}", self.id, filename),
+            // the ID tells apart samples of the same species
+            NameBy::Species => format!("{}-{}-{}", sanitize(&self.species), self.id, filename),
+        }
+    }
+    /// Row of the metadata table, see [`METADATA_HEADER`]
+    pub fn to_tsv(&self) -> String {
+        output::delimited_row(self.columns(), '\t')
+    }
+    /// Values of the columns of [`METADATA_HEADER`]
+    pub fn columns(&self) -> [&str; 6] {
+        [
+            &self.id,
+            &self.clade,
+            &self.order,
+            &self.family,
+            &self.species,
+            &self.tissue_type,
+        ]
+    }
+    /// Value of the metadata column `key`
+    pub fn get(&self, key: OneKpKey) -> &str {
+        match key {
+            OneKpKey::Id => &self.id,
+            OneKpKey::Clade => &self.clade,
+            OneKpKey::Order => &self.order,
+            OneKpKey::Family => &self.family,
+            OneKpKey::Species => &self.species,
+            OneKpKey::TissueType => &self.tissue_type,
+            OneKpKey::Taxid => self.lineage.as_ref().map_or(NO_DATA, |l| &l.taxid),
+            OneKpKey::Phylum => self.rank("phylum"),
+            OneKpKey::Class => self.rank("class"),
+            OneKpKey::Genus => self.rank("genus"),
+        }
+    }
+    /// Name of the ancestor of the species at the NCBI rank `rank`, e.g. `order` or `tribe`,
+    /// once looked up with `onekp enrich`
+    pub fn rank(&self, rank: &str) -> &str {
+        self.lineage
+            .as_ref()
+            .and_then(|l| l.rank(rank))
+            .unwrap_or(NO_DATA)
+    }
+    /// Where the sample is published
+    pub fn source(&self) -> &dyn DataSource {
+        self.source.as_deref().unwrap_or(&OneKpSource)
+    }
+    /// Path of the file relative to the dataset root, as listed in the checksum file.
+    /// Only the nucleotide and protein files are named as translations:
+    ///
+    /// ```
+    /// use onekp::{OneKpRecord, Product, SequenceType};
+    ///
+    /// let rec = OneKpRecord {
+    ///     id: "URDJ".to_owned(),
+    ///     clade: "Liverworts".to_owned(),
+    ///     order: "Marchantiales".to_owned(),
+    ///     family: "Marchantiaceae".to_owned(),
+    ///     species: "Marchantia polymorpha".to_owned(),
+    ///     tissue_type: "thallus".to_owned(),
+    ///     prefix: "URDJ-Marchantia_polymorpha".to_owned(),
+    ///     source: None,
+    ///     lineage: None,
+    /// };
+    /// let dir = "assemblies/URDJ-Marchantia_polymorpha";
+    /// let paths = |filenames: Vec<&str>| -> Vec<String> {
+    ///     filenames.iter().map(|filename| rec.to_gigadb_path(filename)).collect()
+    /// };
+    /// let nucleotides = format!("{}/URDJ-translated-nucleotides.fa.gz", dir);
+    /// let protein = format!("{}/URDJ-translated-protein.fa.gz", dir);
+    /// let contigs = format!("{}/URDJ-SOAPdenovo-Trans-assembly.fa.gz", dir);
+    /// let scaffolds = format!("{}/URDJ-SOAPdenovo-Trans-scaffolds.fa.gz", dir);
+    ///
+    /// assert_eq!(paths(SequenceType::Nucleotide.to_filenames()), [nucleotides.clone()]);
+    /// assert_eq!(paths(SequenceType::Protein.to_filenames()), [protein.clone()]);
+    /// assert_eq!(paths(SequenceType::Both.to_filenames()), [nucleotides.clone(), protein.clone()]);
+    /// // the SOAPdenovo-Trans files have no `translated-` prefix
+    /// assert_eq!(paths(SequenceType::Assembly.to_filenames()), [contigs.clone()]);
+    /// assert_eq!(paths(SequenceType::Scaffold.to_filenames()), [scaffolds.clone()]);
+    /// assert_eq!(
+    ///     paths(SequenceType::All.to_filenames()),
+    ///     [nucleotides, protein.clone(), contigs.clone(), scaffolds]
+    /// );
+    /// // neither have the other products, and the raw assembly is not listed twice
+    /// assert_eq!(
+    ///     paths(SequenceType::Protein.with_products(&[Product::Assembly, Product::Stats])),
+    ///     [protein, contigs.clone(), format!("{}/URDJ-stats.txt", dir)]
+    /// );
+    /// assert_eq!(
+    ///     paths(SequenceType::Assembly.with_products(&[Product::Assembly])),
+    ///     [contigs]
+    /// );
+    /// ```
+    pub fn to_gigadb_path(&self, filename: &str) -> String {
+        self.source().file_path(self, filename)
+    }
+    /// URL on the primary server, see [`Mirrors`] for the fallbacks
+    pub fn to_gigadb_url(&self, filename: &str) -> String {
+        // https://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/assemblies/
+        format!(
+            "{}/{}",
+            self.source().base_url().trim_end_matches('/'),
+            self.to_gigadb_path(filename)
+        )
+    }
+}
+
+/// Metadata of every 1KP sample
+#[derive(Debug, Clone)]
+pub struct OneKp {
+    links: Vec<String>,
+    records: Vec<OneKpRecord>,
+    source: Arc<dyn DataSource>,
+    version: Option<MetadataVersion>,
+    /// IDs of the samples dropped for sharing the ID of an earlier one
+    duplicates: Vec<String>,
+}
+
+/// Metadata column to select samples by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OneKpKey {
+    Id,
+    Clade,
+    Order,
+    Family,
+    Species,
+    TissueType,
+    /// NCBI Taxonomy ID of the species, see `onekp enrich`
+    Taxid,
+    /// Phylum of the species in the NCBI Taxonomy
+    Phylum,
+    /// Class of the species in the NCBI Taxonomy
+    Class,
+    /// Genus of the species in the NCBI Taxonomy
+    Genus,
+}
+
+impl OneKp {
+    /// Download the sample list and the assembly directory listing, cached for an hour
+    pub async fn load(client: &Client) -> Result<Self, OneKpError> {
+        Self::load_in(&default_cache_dir()?, client).await
+    }
+
+    /// Same as [`OneKp::load`], caching in `cache_dir`
+    pub async fn load_in(cache_dir: &Path, client: &Client) -> Result<Self, OneKpError> {
+        Self::load_from(Arc::new(OneKpSource), &Cache::new(cache_dir), client).await
+    }
+
+    /// Download the sample table of `source` through `cache`, storing its [version](metadata_version)
+    pub async fn load_from(
+        source: Arc<dyn DataSource>,
+        cache: &Cache,
+        client: &Client,
+    ) -> Result<Self, OneKpError> {
+        let tsv = cache.get(source.metadata_url(), client).await?;
+        let listing = match source.listing_url() {
+            Some(url) => Some(cache.get(url, client).await?),
+            None => None,
+        };
+        Self::from_tables(source, &tsv, listing.as_deref(), Some(cache))
+    }
+
+    /// The version `id` of the sample table of `source` stored in `cache`, see [`metadata_version`]
+    pub fn load_version(
+        source: Arc<dyn DataSource>,
+        cache: &Cache,
+        id: &str,
+    ) -> Result<Self, OneKpError> {
+        let (version, tsv, listing) = VersionStore::new(&cache.dir).load(source.name(), id)?;
+        let records = source.parse(&tsv, listing.as_deref())?;
+        let mut onekp = Self::from_records(source, records);
+        onekp.version = Some(version);
+        Ok(onekp)
+    }
+
+    /// Read the sample table of `source` from `metadata`, a saved copy of
+    /// [`DataSource::metadata_url`], with the listing from `listing` or, if not given, through `cache`
+    pub async fn load_local(
+        source: Arc<dyn DataSource>,
+        metadata: &Path,
+        listing: Option<&Path>,
+        cache: &Cache,
+        client: &Client,
+    ) -> Result<Self, OneKpError> {
+        let read = |path: &Path| {
+            read_to_string(path).map_err(|err| {
+                OneKpError::Io(io::Error::new(
+                    err.kind(),
+                    format!("{}: {}", path.display(), err),
+                ))
+            })
+        };
+        let tsv = read(metadata)?;
+        let listing = match (listing, source.listing_url()) {
+            (Some(path), _) => Some(read(path)?),
+            (None, Some(url)) => Some(cache.get(url, client).await?),
+            (None, None) => None,
+        };
+        Self::from_tables(source, &tsv, listing.as_deref(), Some(cache))
+    }
+
+    /// The sample table of `source` built into the binary, see [`snapshot`].
+    /// `None` if it was built with `ONEKP_NO_SNAPSHOT` or for another dataset.
+    pub fn load_embedded(source: Arc<dyn DataSource>) -> Result<Option<Self>, OneKpError> {
+        let Some((tsv, listing)) = snapshot::embedded(source.as_ref())? else {
+            return Ok(None);
+        };
+        Self::from_tables(source, &tsv, Some(&listing), None).map(Some)
+    }
+
+    /// Parse the sample table, storing its version in `cache` unless it is bypassed
+    fn from_tables(
+        source: Arc<dyn DataSource>,
+        tsv: &str,
+        listing: Option<&str>,
+        cache: Option<&Cache>,
+    ) -> Result<Self, OneKpError> {
+        let records = source.parse(tsv, listing)?;
+        let version = match cache {
+            Some(cache) if cache.mode != CacheMode::Bypass => {
+                VersionStore::new(&cache.dir).save(source.name(), tsv, listing)?
+            }
+            _ => MetadataVersion::new(source.name(), tsv, listing),
+        };
+        let mut onekp = Self::from_records(source, records);
+        onekp.version = Some(version);
+        Ok(onekp)
+    }
+
+    /// Samples of `source`, each one recording it as its source.
+    /// Only the first of the samples sharing an ID is kept, see [`OneKp::duplicates`].
+    pub fn from_records(source: Arc<dyn DataSource>, mut records: Vec<OneKpRecord>) -> Self {
+        let mut seen = HashSet::new();
+        let mut duplicates = BTreeSet::new();
+        records.retain(|rec| {
+            if seen.insert(rec.id.clone()) {
+                return true;
+            }
+            duplicates.insert(rec.id.clone());
+            false
+        });
+        for rec in records.iter_mut() {
+            rec.source = Some(source.clone());
+        }
+        Self {
+            links: vec![],
+            records,
+            source,
+            version: None,
+            duplicates: duplicates.into_iter().collect(),
+        }
+    }
+
+    /// Build from the sample list TSV and the HTML listing of the assemblies directory
+    pub fn parse(tsv: &str, table_index: &str) -> Result<Self, OneKpError> {
+        let mut onekp = Self::new(table_index);
+        for attrs in sample_list_rows(tsv) {
+            onekp.push_record(attrs)?;
+        }
+        Ok(onekp)
+    }
+
+    pub fn new(table_index: &str) -> Self {
+        Self {
+            records: vec![],
+            // Cannot infer prefix name only in tsv file...
+            links: listing_links(table_index),
+            source: Arc::new(OneKpSource),
+            version: None,
+            duplicates: vec![],
+        }
+    }
+
+    /// Add a row of the sample list. A sample whose directory is not found in the listing, or is
+    /// ambiguous, is kept [unavailable](OneKpRecord::is_available).
+    pub fn push_record(&mut self, attrs: Vec<&str>) -> Result<(), OneKpError> {
+        let id = attrs[0].to_string();
+
+        let prefix = match resolve_prefix(&id, &self.links) {
+            PrefixMatch::Found(prefix) => prefix.to_owned(),
+            PrefixMatch::Missing | PrefixMatch::Ambiguous(_) => String::new(),
+        };
+
+        self.records.push(OneKpRecord {
+            id,
+            clade: attrs[1].to_string(),
+            order: attrs[2].to_string(),
+            family: attrs[3].to_string(),
+            // clean data for gigadb
+            species: attrs[4].to_string(),
+            tissue_type: attrs[5].to_string(),
+            prefix,
+            source: None,
+            lineage: None,
+        });
+        Ok(())
+    }
+
+    pub fn records(&self) -> &[OneKpRecord] {
+        &self.records
+    }
+
+    /// Version of the sample table the samples were read from, if loaded from one
+    pub fn version(&self) -> Option<&MetadataVersion> {
+        self.version.as_ref()
+    }
+
+    /// IDs shared by several samples of the sample table, of which only the first was kept
+    pub fn duplicates(&self) -> &[String] {
+        &self.duplicates
+    }
+
+    /// Where the samples are published
+    pub fn source(&self) -> &Arc<dyn DataSource> {
+        &self.source
+    }
+
+    /// Attach the lineages of `taxonomy` to the samples of the species it knows
+    pub fn enrich(&mut self, taxonomy: &Taxonomy) {
+        for rec in self.records.iter_mut() {
+            rec.lineage = taxonomy.get(&rec.species).cloned();
+        }
+    }
+
+    /// Samples whose `key` is one of `values`
+    pub fn filter(&self, key: OneKpKey, values: &[String]) -> Vec<OneKpRecord> {
+        self.filter_matching(key, values, MatchMode::Exact, false)
+    }
+
+    /// Samples with every word of `text` in one of the columns of the sample table or NCBI lineage
+    /// names, ignoring case. Missing values, [`NO_DATA`], never match.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use onekp::{OneKp, OneKpRecord, OneKpSource};
+    ///
+    /// let rec = |id: &str, species: &str| OneKpRecord {
+    ///     id: id.to_owned(),
+    ///     clade: "Mosses".to_owned(),
+    ///     order: "Bryales".to_owned(),
+    ///     family: "Bryaceae".to_owned(),
+    ///     species: species.to_owned(),
+    ///     tissue_type: "No data".to_owned(),
+    ///     prefix: id.to_owned(),
+    ///     source: None,
+    ///     lineage: None,
+    /// };
+    /// let onekp = OneKp::from_records(
+    ///     Arc::new(OneKpSource),
+    ///     vec![rec("AAAA", "Bryum argenteum"), rec("BBBB", "Rhodobryum roseum")],
+    /// );
+    /// let ids = |text: &str| onekp.search(text).iter().map(|r| r.id.clone()).collect::<Vec<_>>();
+    /// assert_eq!(ids("bryales ROSEUM"), ["BBBB"]);
+    /// assert!(ids("data").is_empty());
+    /// ```
+    pub fn search(&self, text: &str) -> Vec<OneKpRecord> {
+        let words: Vec<String> = text.split_whitespace().map(|w| w.to_lowercase()).collect();
+        self.records
+            .iter()
+            .filter(|r| {
+                let fields: Vec<String> = [
+                    &r.id,
+                    &r.clade,
+                    &r.order,
+                    &r.family,
+                    &r.species,
+                    &r.tissue_type,
+                ]
+                .into_iter()
+                .map(String::as_str)
+                .chain(
+                    r.lineage
+                        .iter()
+                        .flat_map(|l| l.lineage.iter().map(|t| t.name.as_str())),
+                )
+                .filter(|f| *f != NO_DATA)
+                .map(|f| f.to_lowercase())
+                .collect();
+                words.iter().all(|w| fields.iter().any(|f| f.contains(w)))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Samples whose `key` matches one of `values` the way `mode` compares them
+    pub fn filter_matching(
+        &self,
+        key: OneKpKey,
+        values: &[String],
+        mode: MatchMode,
+        ignore_case: bool,
+    ) -> Vec<OneKpRecord> {
+        self.records
+            .iter()
+            .filter(|r| {
+                values
+                    .iter()
+                    .any(|v| mode.matches(v, r.get(key), ignore_case))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Directory names linked from the HTML listing of the assemblies directory
+pub(crate) fn listing_links(table_index: &str) -> Vec<String> {
+    Document::from(table_index)
+        .find(Name("a"))
+        .filter_map(|n| n.attr("href"))
+        .map(|n| n.trim_end_matches('/').to_string())
+        .collect()
+}
+
+/// Directory of a sample found by [`resolve_prefix`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrefixMatch<'a> {
+    Found(&'a str),
+    Missing,
+    /// Several directories could be the one of the sample
+    Ambiguous(Vec<&'a str>),
+}
+
+/// Directory of the sample `id` among `links`: the one named `ID-...` or `ID`, or else the only
+/// one starting with the ID
+///
+/// ```
+/// use onekp::{resolve_prefix, PrefixMatch};
+///
+/// let links = ["URDJ-Marchantia_polymorpha", "URDJX-Other", "ABCD-One", "ABCD-Two", "EFGHI"]
+///     .map(String::from);
+/// assert_eq!(resolve_prefix("URDJ", &links), PrefixMatch::Found("URDJ-Marchantia_polymorpha"));
+/// assert_eq!(resolve_prefix("EFGH", &links), PrefixMatch::Found("EFGHI"));
+/// assert_eq!(resolve_prefix("ABCD", &links), PrefixMatch::Ambiguous(vec!["ABCD-One", "ABCD-Two"]));
+/// assert_eq!(resolve_prefix("WXYZ", &links), PrefixMatch::Missing);
+/// ```
+pub fn resolve_prefix<'a>(id: &str, links: &'a [String]) -> PrefixMatch<'a> {
+    let exact: Vec<&str> = links
+        .iter()
+        .map(|l| l.as_str())
+        .filter(|l| {
+            l.strip_prefix(id)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+        })
+        .collect();
+    let candidates = if exact.is_empty() {
+        links
+            .iter()
+            .map(|l| l.as_str())
+            .filter(|l| !id.is_empty() && l.starts_with(id))
+            .collect()
+    } else {
+        exact
+    };
+    match candidates.as_slice() {
+        [] => PrefixMatch::Missing,
+        [prefix] => PrefixMatch::Found(prefix),
+        _ => PrefixMatch::Ambiguous(candidates),
+    }
+}
+
+/// Rows of the 1KP sample list, padded to its six columns:
+/// 0: sample_id, 1: clade, 2: order, 3: family, 4: species, 5: tissue_type
+pub(crate) fn sample_list_rows(tsv: &str) -> Vec<Vec<&str>> {
+    tsv.split('\n')
+        .map(|l| l.trim())
+        .skip(1)
+        .filter(|l| !l.is_empty())
+        .map(|line| {
+            let mut attrs: Vec<&str> = line.split('\t').collect();
+            while attrs.len() < 6 {
+                attrs.push(NO_DATA);
+            }
+            attrs
+        })
+        .collect()
+}
+
+pub(crate) fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Write `contents` to a temporary file next to `path` and rename it,
+/// so readers never see a partially written file.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), OneKpError> {
+    let tmp = part_path(path);
+    let f = File::create(&tmp)?;
+    let mut bw = BufWriter::new(f);
+    bw.write_all(contents)?;
+    bw.flush()?;
+    bw.get_ref().sync_all()?;
+    drop(bw);
+    rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Header of the metadata table, see [`OneKpRecord::to_tsv`]
+pub const METADATA_HEADER: &str = "1kP_ID\tClade\tOrder\tFamily\tSpecies\tTissue Type";
+/// Metadata of the downloaded samples, written into the root directory
+pub const METADATA_FILENAME: &str = "metadata.tsv";
+/// MD5 checksums of the dataset published by GigaDB
+pub const CHECKSUM_URL: &str =
+    "https://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/100627.md5";
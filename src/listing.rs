@@ -0,0 +1,64 @@
+use anyhow::anyhow;
+use select::{document::Document, predicate::Name};
+
+use crate::{client::Client, error::OneKpError, mirror::Mirrors, OneKpRecord};
+
+/// A file in the remote directory of a sample
+#[derive(Debug, Clone)]
+pub struct RemoteFile {
+    pub name: String,
+    pub url: String,
+    /// `None` when the server does not tell
+    pub size: Option<u64>,
+}
+
+/// Files in the directory listing of `rec`, with their sizes from HEAD requests.
+/// Fails with [`OneKpError::Unavailable`] for samples without a directory.
+pub async fn list_files(
+    rec: &OneKpRecord,
+    client: &Client,
+    mirrors: &Mirrors,
+) -> Result<Vec<RemoteFile>, OneKpError> {
+    if !rec.is_available() {
+        return Err(OneKpError::Unavailable(rec.id.clone()));
+    }
+    let dir = rec.source().sample_dir(rec).ok_or_else(|| {
+        OneKpError::Other(anyhow!(
+            "{} does not list the files of a sample",
+            rec.source().name()
+        ))
+    })?;
+
+    let mut last_err = None;
+    for dir_url in mirrors.urls(&dir) {
+        match client.get(&dir_url).await {
+            Ok(response) => {
+                let html = response.text().await?;
+                let mut files = vec![];
+                for name in file_links(&html) {
+                    let url = format!("{}{}", dir_url, name);
+                    let size = client.content_length(&url).await.unwrap_or(None);
+                    files.push(RemoteFile { name, url, size });
+                }
+                return Ok(files);
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| OneKpError::Other(anyhow!("No URL for {}", dir))))
+}
+
+/// File names linked from a directory listing, without subdirectories,
+/// the parent directory and sorting links
+fn file_links(html: &str) -> Vec<String> {
+    let mut names: Vec<String> = Document::from(html)
+        .find(Name("a"))
+        .filter_map(|n| n.attr("href"))
+        .filter(|href| !href.ends_with('/') && !href.starts_with('?') && !href.contains("://"))
+        .map(|href| href.rsplit('/').next().unwrap_or(href).to_owned())
+        .filter(|name| !name.is_empty())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
@@ -0,0 +1,120 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checksum::Checksums,
+    error::{OneKpError, Result},
+    metadata_version::MetadataVersion,
+    write_atomic, OneKpRecord, SequenceType,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedFile {
+    /// Path relative to the dataset root, as listed in the checksum file
+    pub path: String,
+    pub url: String,
+    pub md5: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedSample {
+    pub id: String,
+    pub species: String,
+    pub files: Vec<LockedFile>,
+}
+
+/// Exact set of samples, URLs and checksums written by `onekp lock`
+/// and fetched by `onekp install`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockFile {
+    pub onekp_version: String,
+    pub sequence_type: SequenceType,
+    /// Sample table the samples were selected from
+    #[serde(default)]
+    pub metadata: Option<MetadataVersion>,
+    pub samples: Vec<LockedSample>,
+}
+
+impl LockFile {
+    pub fn new(
+        records: &[OneKpRecord],
+        sequence_type: SequenceType,
+        checksums: Option<&Checksums>,
+    ) -> Self {
+        let samples = records
+            .iter()
+            .map(|rec| LockedSample {
+                id: rec.id.clone(),
+                species: rec.species.clone(),
+                files: sequence_type
+                    .to_filenames()
+                    .iter()
+                    .map(|filename| {
+                        let path = rec.to_gigadb_path(filename);
+                        LockedFile {
+                            url: rec.to_gigadb_url(filename),
+                            md5: checksums.and_then(|c| c.get(&path)).map(|h| h.to_owned()),
+                            path,
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            onekp_version: env!("CARGO_PKG_VERSION").to_owned(),
+            sequence_type,
+            metadata: None,
+            samples,
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        write_atomic(path, &json)
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        self.samples.iter().map(|s| s.id.clone()).collect()
+    }
+
+    /// Check that `records` resolve to exactly the locked files
+    pub fn check(&self, records: &[OneKpRecord]) -> Result<()> {
+        for sample in self.samples.iter() {
+            let rec = records
+                .iter()
+                .find(|r| r.id == sample.id)
+                .ok_or_else(|| anyhow!("{} is locked but not found in the metadata", sample.id))?;
+            for (filename, file) in self.sequence_type.to_filenames().iter().zip(&sample.files) {
+                let url = rec.to_gigadb_url(filename);
+                if url != file.url {
+                    return Err(OneKpError::Other(anyhow!(
+                        "{} is locked to {} but now resolves to {}",
+                        sample.id,
+                        file.url,
+                        url
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Locked checksums, used instead of the published list
+    pub fn checksums(&self) -> Checksums {
+        let mut checksums = Checksums::default();
+        for file in self.samples.iter().flat_map(|s| s.files.iter()) {
+            if let Some(md5) = &file.md5 {
+                checksums.insert(&file.path, md5);
+            }
+        }
+        checksums
+    }
+}
@@ -1,403 +1,995 @@
-use anyhow::{anyhow, Result};
-use clap::{Parser, Subcommand, ValueEnum};
-
-use reqwest::{Response, StatusCode};
-use select::{document::Document, predicate::Name};
+use anyhow::Result;
+use clap::{builder::RangedU64ValueParser, CommandFactory, FromArgMatches, Parser, Subcommand};
 use std::{
-    collections::BTreeSet,
-    env::current_dir,
-    fs::{create_dir, metadata, File},
-    io::{BufReader, BufWriter, Read, Write},
+    env,
+    fs::File,
+    io::{BufWriter, IsTerminal, Write},
+    net::{IpAddr, SocketAddr},
     path::{Path, PathBuf},
-    thread::sleep,
-    time::{Duration, Instant, SystemTime},
+    process::ExitCode,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
 };
 
 use colored::*;
+use console::Term;
+use indicatif::{HumanBytes, HumanDuration};
+
+use onekp::{
+    aria2,
+    checksum::Checksums,
+    client::{INTERVAL, MAX_RETRY, TIMEOUT},
+    config::{self, Config},
+    default_cache_dir,
+    export::{self, UrlFormat},
+    fetch::JOBS,
+    filter::{FilterArgs, Selection, Within},
+    listing,
+    lock::LockFile,
+    metadata_version::VersionStore,
+    output::{self, MetadataFormat, SortArgs},
+    path_template::PathTemplate,
+    picker,
+    rate_limit::parse_byte_rate,
+    report::{FetchReport, SampleStatus},
+    run_fetch, serve, snapshot,
+    source::DataSource,
+    sql::Select,
+    stats::{self, AssemblyStats, STATS_HEADER},
+    strict::StrictSource,
+    summary::{count_by, nest, Summary},
+    taxonomy::Taxonomy,
+    tree::{TaxonTree, TreeFormat},
+    tui::{Action, Browser},
+    verify, Cache, CacheMode, CacheTtl, Client, ClientOptions, FetchArgs, Mirrors, NameBy, OneKp,
+    OneKpError, OneKpKey, OneKpRecord, OneKpSource, OverwritePolicy, Product, Protocol,
+    SequenceType, METADATA_HEADER,
+};
 
-#[derive(Debug, Clone, Copy, ValueEnum)]
-pub enum SequenceType {
-    Nucleotide,
-    Protein,
-    Both,
-}
-
-impl SequenceType {
-    fn to_filenames(self) -> Vec<&'static str> {
-        let nucleotide = "nucleotides.fa.gz";
-        let protein = "protein.fa.gz";
-        match self {
-            Self::Nucleotide => vec![nucleotide],
-            Self::Protein => vec![protein],
-            Self::Both => vec![nucleotide, protein],
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct OneKpRecord {
-    id: String,
-    clade: String,
-    order: String,
-    family: String,
-    species: String,
-    tissue_type: String,
-    prefix: String,
-}
-
-impl OneKpRecord {
-    pub fn to_filename(&self, filename: &str) -> String {
-        format!("{}-{}", self.prefix, filename)
-    }
-    pub fn to_gigadb_url(&self, filename: &str) -> String {
-        // https://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/assemblies/
-        format!("https://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/assemblies/{}/{}-translated-{}", self.prefix, self.id, filename)
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct OneKp {
-    links: Vec<String>,
-    records: Vec<OneKpRecord>,
-}
-
-#[derive(Debug, Clone, ValueEnum)]
-pub enum OneKpKey {
-    Id,
-    Clade,
-    Order,
-    Family,
-    Species,
-    TissueType,
-}
-
-impl OneKp {
-    pub fn new(table_index: &str) -> Self {
-        // Cannot infer prefix name only in tsv file...
-        let links = Document::from(table_index)
-            .find(Name("a"))
-            .filter_map(|n| n.attr("href"))
-            .map(|n| n.trim_end_matches('/').to_string())
-            .collect();
-
-        Self {
-            records: vec![],
-            links,
-        }
-    }
-
-    pub fn push_record(&mut self, attrs: Vec<&str>) -> Result<()> {
-        let id = attrs[0].to_string();
-
-        let prefix = self
-            .links
-            .iter()
-            .find(|l| l.starts_with(&id))
-            .ok_or_else(|| anyhow!("{} dirname is not found", id))?
-            .to_owned();
-
-        self.records.push(OneKpRecord {
-            id,
-            clade: attrs[1].to_string(),
-            order: attrs[2].to_string(),
-            family: attrs[3].to_string(),
-            // clean data for gigadb
-            species: attrs[4].to_string(),
-            tissue_type: attrs[5].to_string(),
-            prefix,
-        });
-        Ok(())
-    }
-
-    pub fn filter(&self, key: OneKpKey, values: &[String]) -> Vec<OneKpRecord> {
-        match key {
-            OneKpKey::Id => self
-                .records
-                .iter()
-                .cloned()
-                .filter(|r| values.contains(&r.id))
-                .collect(),
-            OneKpKey::Clade => self
-                .records
-                .iter()
-                .cloned()
-                .filter(|r| values.contains(&r.clade))
-                .collect(),
-            OneKpKey::Order => self
-                .records
-                .iter()
-                .cloned()
-                .filter(|r| values.contains(&r.order))
-                .collect(),
-            OneKpKey::Family => self
-                .records
-                .iter()
-                .cloned()
-                .filter(|r| values.contains(&r.family))
-                .collect(),
-            OneKpKey::Species => self
-                .records
-                .iter()
-                .cloned()
-                .filter(|r| values.contains(&r.species))
-                .collect(),
-            OneKpKey::TissueType => self
-                .records
-                .iter()
-                .cloned()
-                .filter(|r| values.contains(&r.tissue_type))
-                .collect(),
-        }
-    }
-}
-
-#[derive(Debug)]
-struct Client {
-    interval_time: u64,
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    commands: Commands,
+    /// Seconds to wait between requests to the same host.
+    /// Only the wait between retries with `--downloader aria2c`
+    #[arg(long, global = true, env = "ONEKP_INTERVAL", default_value_t = INTERVAL)]
+    interval: u64,
+    /// Number of attempts for each request
+    #[arg(
+        long,
+        global = true,
+        env = "ONEKP_MAX_RETRY",
+        default_value_t = MAX_RETRY,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
     max_retry: usize,
-    last_fetch_time: Instant,
+    /// Seconds to wait for a connection or data before giving up
+    #[arg(long, global = true, env = "ONEKP_TIMEOUT", default_value_t = TIMEOUT)]
+    timeout: u64,
+    /// Proxy URL (http, https or socks5). HTTP_PROXY / HTTPS_PROXY are used when omitted
+    #[arg(long, global = true, env = "ONEKP_PROXY")]
+    proxy: Option<String>,
+    /// Proxy credentials as USER:PASSWORD
+    #[arg(
+        long,
+        global = true,
+        env = "ONEKP_PROXY_AUTH",
+        hide_env_values = true,
+        value_parser = parse_credentials,
+        requires = "proxy"
+    )]
+    proxy_auth: Option<(String, String)>,
+    /// User-Agent sent with every request [default: onekp/<version>]
+    #[arg(long, global = true, env = "ONEKP_USER_AGENT")]
+    user_agent: Option<String>,
+    /// Extra request header as "NAME: VALUE". Can be given multiple times
+    #[arg(long = "header", short = 'H', global = true, value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+    /// Base URL of a mirror of the dataset, tried when the primary server fails.
+    /// Can be given multiple times, ONEKP_MIRRORS is comma-separated
+    #[arg(
+        long = "mirror",
+        global = true,
+        env = "ONEKP_MIRRORS",
+        value_delimiter = ','
+    )]
+    mirrors: Vec<String>,
+    /// Directory the metadata and checksum lists are cached in, e.g. a directory shared by a group
+    /// [default: cache directory of the platform]
+    #[arg(long, global = true, env = "ONEKP_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+    /// How long the cached metadata is reused: seconds, e.g. 600, 10m, 2h or 7d, or never
+    #[arg(long, global = true, env = "ONEKP_CACHE_TTL", default_value = "1h")]
+    cache_ttl: CacheTtl,
+    /// Download the metadata and checksum lists without reading or writing the cache
+    #[arg(long, global = true)]
+    no_cache: bool,
+    /// Download the metadata and checksum lists again, replacing the cached copies
+    #[arg(long, global = true, conflicts_with = "no_cache")]
+    refresh_cache: bool,
+    /// Only use the cached metadata and checksum lists, however old, without connecting to the server
+    #[arg(long, global = true, conflicts_with_all = ["no_cache", "refresh_cache"])]
+    offline: bool,
+    /// Saved copy of the sample table to use instead of downloading it
+    #[arg(long, global = true, env = "ONEKP_METADATA_FILE")]
+    metadata_file: Option<PathBuf>,
+    /// Saved copy of the assemblies directory listing of 1KP, with --metadata-file
+    /// [default: downloaded or cached]
+    #[arg(
+        long,
+        global = true,
+        env = "ONEKP_LISTING_FILE",
+        requires = "metadata_file"
+    )]
+    listing_file: Option<PathBuf>,
+    /// Use the stored version of the sample table with this hash or start of it, as recorded in
+    /// fetch reports and lockfiles, see `onekp cache versions`
+    #[arg(
+        long,
+        global = true,
+        env = "ONEKP_METADATA_VERSION",
+        conflicts_with = "metadata_file"
+    )]
+    metadata_version: Option<String>,
+    /// Fail with a report of the rows of the sample table with missing cells, unknown clades or IDs
+    /// without a directory on the server, instead of working around them
+    #[arg(long, global = true)]
+    strict: bool,
 }
 
-impl Client {
-    pub fn new(interval_time: u64, max_retry: usize) -> Self {
-        Self {
-            interval_time,
-            max_retry,
-            last_fetch_time: Instant::now(),
-        }
-    }
-
-    async fn _get(&mut self, url: &str) -> Result<Response> {
-        let now = Instant::now();
-        let duration = now.duration_since(self.last_fetch_time).as_secs();
-
-        if duration < self.interval_time {
-            sleep(Duration::from_secs(self.interval_time));
-        }
-
-        let resp = reqwest::get(url).await?;
-
-        if resp.status() != StatusCode::OK {
-            return Err(anyhow!("Error: {}", resp.status()));
-        }
-
-        self.last_fetch_time = Instant::now();
-
-        Ok(resp)
-    }
-
-    pub async fn get(&mut self, url: &str) -> Result<Response> {
-        for _ in 0..self.max_retry {
-            match self._get(url).await {
-                Ok(data) => return Ok(data),
-                Err(err) => eprintln!("{}", err),
-            }
-        }
-
-        Err(anyhow!(
-            "Error {} times when fetching {}",
-            self.max_retry,
-            url
-        ))
-    }
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    s.split_once(':')
+        .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| "expected NAME: VALUE".to_owned())
 }
 
-async fn fetch_and_save(
-    rec: &OneKpRecord,
-    basedir: &Path,
-    sequence_type: SequenceType,
-    client: &mut Client,
-) -> Result<()> {
-    for filename in sequence_type.to_filenames().iter() {
-        let path = basedir.join(rec.to_filename(filename));
-
-        let f = File::create(path)?;
-        let mut bw = BufWriter::new(f);
-        bw.write_all(
-            &client
-                .get(&rec.to_gigadb_url(filename))
-                .await?
-                .bytes()
-                .await?,
-        )?;
-    }
-
-    Ok(())
-}
-
-#[derive(Parser)]
-struct Cli {
-    #[command(subcommand)]
-    commands: Commands,
+fn parse_credentials(s: &str) -> Result<(String, String), String> {
+    s.split_once(':')
+        .map(|(user, password)| (user.to_owned(), password.to_owned()))
+        .ok_or_else(|| "expected USER:PASSWORD".to_owned())
 }
 
 #[derive(Subcommand)]
 enum Commands {
     Fetch {
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Pick the samples to download among the selected ones in a searchable list
+        #[arg(long)]
+        interactive: bool,
+        #[command(flatten)]
+        args: FetchArgs,
+    },
+    /// Fetch again only the failed samples of a previous report, with the same options
+    RetryFailed {
+        /// JSON report written by `fetch --report`
+        #[arg(long)]
+        report: PathBuf,
+        /// Override the root directory recorded in the report
+        #[arg(long, short)]
+        rootdir: Option<PathBuf>,
+        /// Where to write the updated report [default: overwrite --report]
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Do not ask for confirmation before downloading
         #[arg(long, short)]
+        yes: bool,
+    },
+    /// Record the exact samples, URLs and checksums to fetch into a lockfile
+    Lock {
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, short, env = "ONEKP_SEQUENCE_TYPE")]
+        sequence_type: SequenceType,
+        #[arg(long, short, default_value = "onekp.lock")]
+        output: PathBuf,
+    },
+    /// Fetch exactly the samples recorded in a lockfile, verified with the locked checksums
+    Install {
+        lockfile: PathBuf,
+        #[arg(long, short, env = "ONEKP_ROOTDIR")]
         rootdir: PathBuf,
+        /// Number of samples downloaded concurrently
+        #[arg(long, short, env = "ONEKP_JOBS", default_value_t = JOBS)]
+        jobs: usize,
+        /// Destination of each file relative to the root directory, see `fetch --help`
+        #[arg(long, default_value = PathTemplate::DEFAULT, value_parser = PathTemplate::parse)]
+        path_template: PathTemplate,
+        /// How to name the downloaded files
+        #[arg(long, value_enum, default_value_t)]
+        name_by: NameBy,
+        /// Transport used for the data files. Proxies only apply to https
+        #[arg(long, value_enum, default_value_t)]
+        protocol: Protocol,
+        /// What to do when a file already exists in the root directory
+        #[arg(long, value_enum, default_value_t)]
+        overwrite: OverwritePolicy,
+        /// Cap the total download speed in bytes per second, e.g. 500K, 5M, 1G
+        #[arg(long, value_parser = parse_byte_rate)]
+        limit_rate: Option<u64>,
+        /// Stop at the first failed sample
         #[arg(long)]
-        filter_key: OneKpKey,
-        #[arg(long, value_delimiter = ',')]
-        filter_values: Vec<String>,
+        fail_fast: bool,
+        /// Skip the samples already done according to the state file of the root directory
+        #[arg(long)]
+        resume: bool,
+        /// Do not ask for confirmation before downloading
         #[arg(long, short)]
+        yes: bool,
+        /// Only warn when the root directory does not have enough free space
+        #[arg(long)]
+        ignore_space_check: bool,
+        /// Write a JSON report of the run to this path
+        #[arg(long)]
+        report: Option<PathBuf>,
+    },
+    /// Check the files of a downloaded dataset against the remote sizes and checksums
+    Verify {
+        /// Root directory of a previous fetch, with its metadata.tsv
+        #[arg(long, short, env = "ONEKP_ROOTDIR")]
+        rootdir: PathBuf,
+    },
+    /// Print the download list of the selected samples for an external downloader
+    Urls {
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, short, env = "ONEKP_SEQUENCE_TYPE")]
         sequence_type: SequenceType,
+        /// Other files of each sample to list as well, e.g. assembly,stats
+        #[arg(long, value_enum, value_delimiter = ',')]
+        products: Vec<Product>,
+        #[arg(long, value_enum, default_value = "aria2")]
+        format: UrlFormat,
+        /// Directory the output paths are relative to
+        #[arg(long, short, env = "ONEKP_ROOTDIR", default_value = ".")]
+        rootdir: PathBuf,
+        /// Destination of each file relative to the root directory, see `fetch --help`
+        #[arg(long, default_value = PathTemplate::DEFAULT, value_parser = PathTemplate::parse)]
+        path_template: PathTemplate,
+        /// How to name the files
+        #[arg(long, value_enum, default_value = "prefix")]
+        name_by: NameBy,
+    },
+    /// Download the assembly statistics of the selected samples into one table
+    AssemblyStats {
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Write the table to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// List every file in the remote directory of the samples with its size
+    ListFiles {
+        #[arg(long, value_delimiter = ',', required = true)]
+        id: Vec<String>,
     },
     MetaData {
-        #[arg(long)]
-        filter_key: Option<OneKpKey>,
-        #[arg(long, value_delimiter = ',')]
-        filter_values: Option<Vec<String>>,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value = "tsv")]
+        format: MetadataFormat,
+        /// Columns to print, in this order, e.g. id,species,tissue-type
+        #[arg(long, value_enum, value_delimiter = ',')]
+        fields: Vec<OneKpKey>,
+        #[command(flatten)]
+        sort: SortArgs,
+        /// Write the table to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
     },
     Show {
+        /// Column to list the values of. Several, e.g. clade,order, list the values of each
+        /// column under those of the previous one
+        #[arg(
+            long = "key",
+            short,
+            visible_alias = "keys",
+            value_delimiter = ',',
+            required = true
+        )]
+        keys: Vec<OneKpKey>,
+        /// Only list the values of the samples whose KEY column is VALUE, e.g. clade=Bryophytes
+        #[arg(long, value_name = "KEY=VALUE")]
+        within: Vec<Within>,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// tsv prints one value per line without a header, indented under the values of the previous keys
+        #[arg(long, value_enum, default_value = "tsv")]
+        format: MetadataFormat,
+        /// List the values in descending order
+        #[arg(long)]
+        desc: bool,
+        /// Print the number of samples of each value after it
+        #[arg(long)]
+        counts: bool,
+    },
+    /// Print the number of selected samples of each value of the --by columns
+    Count {
+        /// Columns to group the samples by, e.g. clade or clade,order
+        #[arg(long, value_enum, value_delimiter = ',', required = true)]
+        by: Vec<OneKpKey>,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value = "tsv")]
+        format: MetadataFormat,
+        /// List the largest groups first instead of sorting by value
+        #[arg(long)]
+        sort_by_count: bool,
+    },
+    /// Summarize the selected samples: numbers of samples and species,
+    /// samples per clade, order and tissue type, and samples with both sequence files
+    Stats {
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Run an SQL SELECT over the sample table, named samples, and print the result as TSV
+    Query {
+        /// e.g. "SELECT family, count(*) FROM samples WHERE clade = 'Mosses' GROUP BY family"
+        sql: Select,
+    },
+    /// Print the samples with every word of TEXT in one of their columns, ignoring case
+    Search {
+        #[arg(required = true)]
+        text: Vec<String>,
+    },
+    /// Print the clade, order, family and species of the selected samples as a tree
+    /// with the number of samples of each
+    Tree {
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Write the selected samples as a tree constrained by their clade, order, family and species,
+    /// labeled with the sample IDs
+    ExportTree {
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value = "newick")]
+        format: TreeFormat,
+        /// Write the tree to this file instead of stdout
         #[arg(long, short)]
-        key: OneKpKey,
+        output: Option<PathBuf>,
+    },
+    /// Browse the selected samples by clade, order and family in the terminal,
+    /// mark samples and download them with the fetch options
+    Tui {
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[command(flatten)]
+        args: FetchArgs,
+    },
+    /// Look up the NCBI Taxonomy ID and lineage of every species,
+    /// making the taxid, phylum, class and genus keys available
+    Enrich {
+        /// NCBI API key, raising the rate limit of Entrez from 3 to 10 requests a second
+        #[arg(long)]
+        api_key: Option<String>,
+    },
+    /// Serve the metadata as JSON for other applications
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        host: IpAddr,
+        #[arg(long, short, default_value_t = 8080)]
+        port: u16,
+    },
+    /// Show or clear the cached metadata
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
     },
 }
 
-pub fn is_cache_update_required(path: &Path) -> Result<bool> {
-    let meta = metadata(path)?;
-    Ok(SystemTime::now().duration_since(meta.modified()?)? >= Duration::from_secs(3600))
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Print the location of the cache and its files with their sizes and ages
+    Info,
+    /// Delete the cached files
+    Clear,
+    /// Print the location of the cache
+    Path,
+    /// List the stored versions of the sample table, oldest first
+    Versions,
 }
 
-async fn use_cache(url: &str, client: &mut Client) -> Result<String> {
-    let cache_path = current_dir()?.join(".onekp_cache");
-    if let Err(err) = create_dir(&cache_path) {
-        if let Some(raw_os_error) = err.raw_os_error() {
-            if raw_os_error != 17 {
-                return Err(anyhow!("{}", err));
-            }
-        }
+/// Exit status when some samples could not be fetched
+const EXIT_FETCH_FAILED: u8 = 2;
+/// Exit status when `verify` found missing or broken files
+const EXIT_VERIFY_FAILED: u8 = 2;
+/// Exit status when the run was stopped with Ctrl-C, as shells do for SIGINT
+const EXIT_INTERRUPTED: u8 = 130;
+
+/// Write the report if requested and map the result of the run to the exit status
+fn finish_fetch(report: Option<FetchReport>, path: Option<&Path>) -> Result<ExitCode> {
+    let Some(report) = report else {
+        return Ok(ExitCode::SUCCESS);
     };
-
-    let mut filename = url.split('/').last().expect("Should exist filename");
-
-    if filename.is_empty() {
-        filename = "index.html"
+    if let Some(path) = path {
+        report.write(path)?;
     }
-
-    let path = cache_path.join(filename);
-
-    if let Ok(cache_update_required) = is_cache_update_required(&path) {
-        if !cache_update_required {
-            let f = File::open(path)?;
-            let mut br = BufReader::new(f);
-            let mut buf = String::new();
-            br.read_to_string(&mut buf)?;
-            return Ok(buf);
-        }
+    Ok(if report.interrupted {
+        ExitCode::from(EXIT_INTERRUPTED)
+    } else if report.has_failures() {
+        ExitCode::from(EXIT_FETCH_FAILED)
     } else {
-        eprintln!("{:?}", is_cache_update_required(&path));
-    }
-
-    let text = client.get(url).await?.text().await?;
-    let f = File::create(path)?;
-    let mut br = BufWriter::new(f);
-    br.write_fmt(format_args!("{}", text))?;
-
-    Ok(text)
+        ExitCode::SUCCESS
+    })
 }
 
-const INTERVAL: u64 = 3;
-const MAX_RETRY: usize = 5;
+/// Samples selected by `filter`, printing how its values were resolved
+fn select(filter: &FilterArgs, onekp: &OneKp) -> Result<Vec<OneKpRecord>> {
+    Ok(print_notices(filter.select(onekp)?))
+}
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let mut client = Client::new(INTERVAL, MAX_RETRY);
+/// Same as [`select`], refusing to select every sample implicitly
+fn select_required(filter: &FilterArgs, onekp: &OneKp) -> Result<Vec<OneKpRecord>> {
+    Ok(print_notices(filter.select_required(onekp)?))
+}
 
-    let tsv = use_cache("https://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/Sample-List-with-Taxonomy.tsv.csv", &mut client).await?;
-    let table_index = use_cache(
-        "https://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/assemblies/",
-        &mut client,
-    )
-    .await?;
+fn print_notices(selection: Selection) -> Vec<OneKpRecord> {
+    for notice in selection.notices.iter() {
+        eprintln!("{}", notice);
+    }
+    selection.records
+}
 
-    let mut onekp = OneKp::new(&table_index);
-    for (i, line) in tsv.split('\n').map(|l| l.trim()).enumerate() {
-        if i == 0 {
-            continue;
+/// The published checksums of the dataset, or `None` with a warning ending with `consequence`
+async fn load_checksums(
+    onekp: &OneKp,
+    client: &Client,
+    cache: &Cache,
+    consequence: &str,
+) -> Option<Checksums> {
+    let url = onekp.source().checksum_url()?;
+    match cache.get(url, client).await {
+        Ok(text) => Some(Checksums::parse(&text)),
+        Err(err) => {
+            eprintln!(
+                "{}: checksum list is not available, {}\n{}",
+                "Warning".yellow(),
+                consequence,
+                err
+            );
+            None
         }
+    }
+}
 
-        if line.is_empty() {
-            continue;
+fn cache_command(command: &CacheCommand, cache: &Cache) -> Result<()> {
+    match command {
+        CacheCommand::Path => println!("{}", cache.dir.display()),
+        CacheCommand::Info => {
+            let entries = cache.entries()?;
+            println!("Location: {}", cache.dir.display());
+            println!(
+                "Files: {}, {}",
+                entries.len(),
+                HumanBytes(entries.iter().map(|e| e.size).sum())
+            );
+            for entry in entries.iter() {
+                println!(
+                    "{}\t{}\t{} ago{}",
+                    entry.name,
+                    HumanBytes(entry.size),
+                    HumanDuration(entry.age),
+                    if entry.expired { ", expired" } else { "" }
+                );
+            }
+        }
+        CacheCommand::Versions => {
+            for version in VersionStore::new(&cache.dir).list()? {
+                let first_seen = UNIX_EPOCH + Duration::from_secs(version.first_seen);
+                println!(
+                    "{}\t{}\t{}",
+                    version.id(),
+                    version.dataset,
+                    httpdate::fmt_http_date(first_seen)
+                );
+            }
         }
+        CacheCommand::Clear => {
+            // every URL the cache may have a copy of
+            let source = OneKpSource;
+            let urls = [
+                Some(source.metadata_url()),
+                source.listing_url(),
+                source.checksum_url(),
+            ]
+            .into_iter()
+            .flatten();
+            let entries = cache.clear(urls)?;
+            eprintln!(
+                "Removed {} files, {}, from {}",
+                entries.len(),
+                HumanBytes(entries.iter().map(|e| e.size).sum()),
+                cache.dir.display()
+            );
+        }
+    }
+    Ok(())
+}
 
-        // 0: sample_id, 1: clade, 2: order, 3: family, 4: species, 5: tissue_type
-        let mut attrs: Vec<&str> = line.split('\t').collect();
-        while attrs.len() < 6 {
-            attrs.push("No data");
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    let mut config = match Config::path() {
+        Some(path) => Config::load(&path)?,
+        None => Config::default(),
+    };
+    if let Some(name) = config::profile_name(env::args()) {
+        config = config.with_profile(&name)?;
+    }
+    let cli = Cli::from_arg_matches(&config.apply(Cli::command()).get_matches())
+        .unwrap_or_else(|err| err.exit());
+    let client = Client::new(&ClientOptions {
+        interval: Duration::from_secs(cli.interval),
+        max_retry: cli.max_retry,
+        timeout: Duration::from_secs(cli.timeout),
+        proxy: cli.proxy,
+        proxy_auth: cli.proxy_auth,
+        user_agent: cli.user_agent,
+        headers: cli.headers,
+    })?
+    .with_notices(|msg| eprintln!("{}", msg));
+    let cache_dir = match cli.cache_dir.or(config.cache_dir) {
+        Some(dir) => dir,
+        None => default_cache_dir()?,
+    };
+    let cache = Cache::new(&cache_dir)
+        .with_ttl(cli.cache_ttl.0)
+        .with_mode(if cli.offline {
+            CacheMode::Offline
+        } else if cli.no_cache {
+            CacheMode::Bypass
+        } else if cli.refresh_cache {
+            CacheMode::Refresh
+        } else {
+            CacheMode::Reuse
+        });
+    if let Commands::Cache { command } = &cli.commands {
+        // without the metadata, which would fill the cache
+        cache_command(command, &cache)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut source: Arc<dyn DataSource> = Arc::new(OneKpSource);
+    if cli.strict {
+        source = Arc::new(StrictSource(source));
+    }
+    let mut onekp = match (&cli.metadata_file, &cli.metadata_version) {
+        (Some(path), _) => {
+            OneKp::load_local(source, path, cli.listing_file.as_deref(), &cache, &client).await?
         }
-        onekp.push_record(attrs)?;
+        (None, Some(id)) => OneKp::load_version(source, &cache, id)?,
+        (None, None) => match OneKp::load_from(source.clone(), &cache, &client).await {
+            Ok(onekp) => onekp,
+            Err(err) if err.is_transient() || matches!(err, OneKpError::NotCached(_)) => {
+                match OneKp::load_embedded(source)? {
+                    Some(onekp) => {
+                        eprintln!(
+                            "{}: {}\nUsing the sample table built into onekp, downloaded on {}, which may be stale",
+                            "Warning".yellow(),
+                            err,
+                            snapshot::date().unwrap_or_default()
+                        );
+                        onekp
+                    }
+                    None => return Err(err.into()),
+                }
+            }
+            Err(err) => return Err(err.into()),
+        },
+    };
+    if !onekp.duplicates().is_empty() {
+        eprintln!(
+            "{}: several samples of the sample table share the IDs {}, keeping the first of each",
+            "Warning".yellow(),
+            onekp.duplicates().join(", ")
+        );
+    }
+    let unavailable: Vec<&str> = onekp
+        .records()
+        .iter()
+        .filter(|r| !r.is_available())
+        .map(|r| r.id.as_str())
+        .collect();
+    if !unavailable.is_empty() {
+        eprintln!(
+            "{}: no single directory on the server for {}, which cannot be downloaded",
+            "Warning".yellow(),
+            unavailable.join(", ")
+        );
     }
+    let mut taxonomy = Taxonomy::load(&cache_dir)?;
+    onekp.enrich(&taxonomy);
+    let mirrors = Mirrors::for_source(onekp.source().as_ref(), &cli.mirrors);
 
     match cli.commands {
         Commands::Fetch {
+            filter,
+            interactive,
+            args,
+        } => {
+            let records = if interactive {
+                let records = picker::pick(select(&filter, &onekp)?)?;
+                if records.is_empty() {
+                    eprintln!("No sample picked");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                records
+            } else {
+                select_required(&filter, &onekp)?
+            };
+            let report = run_fetch(records, &args, client, mirrors, &cache, None)
+                .await?
+                .map(|report| FetchReport {
+                    metadata: onekp.version().cloned(),
+                    ..report
+                });
+            return finish_fetch(report, args.report.as_deref());
+        }
+        Commands::RetryFailed {
+            report: report_path,
             rootdir,
-            filter_key,
-            filter_values,
+            output,
+            yes,
+        } => {
+            let mut report = FetchReport::read(&report_path)?;
+            let failed_ids: Vec<String> = report
+                .samples
+                .iter()
+                .filter(|s| s.status == SampleStatus::Failed)
+                .map(|s| s.id.clone())
+                .collect();
+            if failed_ids.is_empty() {
+                eprintln!("No failed samples in {}", report_path.display());
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let mut args = report.options.clone();
+            if let Some(rootdir) = rootdir {
+                args.rootdir = rootdir;
+            }
+            args.yes = yes;
+
+            let records = onekp.filter(OneKpKey::Id, &failed_ids);
+            if let Some(retried) = run_fetch(records, &args, client, mirrors, &cache, None).await? {
+                let failed = retried.has_failures();
+                report.merge(retried);
+                report.write(output.as_deref().unwrap_or(&report_path))?;
+                if report.interrupted {
+                    return Ok(ExitCode::from(EXIT_INTERRUPTED));
+                }
+                if failed {
+                    return Ok(ExitCode::from(EXIT_FETCH_FAILED));
+                }
+            }
+        }
+        Commands::Lock {
+            filter,
             sequence_type,
+            output,
         } => {
-            let mut success_ids = vec![];
-            let mut err_ids = vec![];
-            eprintln!("--- Fetching start ---");
-            for rec in onekp.filter(filter_key, filter_values.as_ref()).iter() {
-                match fetch_and_save(rec, &rootdir, sequence_type, &mut client).await {
-                    Ok(()) => {
-                        eprintln!("{}: {}", "Success".green(), rec.species);
-                        success_ids.push(rec.id.to_owned());
-                    }
+            let records = select_required(&filter, &onekp)?;
+            let checksums =
+                load_checksums(&onekp, &client, &cache, "the lockfile has no checksums").await;
+            let mut lockfile = LockFile::new(&records, sequence_type, checksums.as_ref());
+            lockfile.metadata = onekp.version().cloned();
+            lockfile.write(&output)?;
+            eprintln!("Locked {} samples into {}", records.len(), output.display());
+        }
+        Commands::Install {
+            lockfile,
+            rootdir,
+            jobs,
+            path_template,
+            name_by,
+            protocol,
+            overwrite,
+            limit_rate,
+            fail_fast,
+            resume,
+            yes,
+            ignore_space_check,
+            report,
+        } => {
+            let lock = LockFile::read(&lockfile)?;
+            let records = onekp.filter(OneKpKey::Id, &lock.ids());
+            lock.check(&records)?;
+
+            let args = FetchArgs {
+                jobs,
+                path_template,
+                name_by,
+                protocol,
+                overwrite,
+                limit_rate,
+                fail_fast,
+                resume,
+                yes,
+                ignore_space_check,
+                report,
+                ..FetchArgs::new(rootdir, lock.sequence_type)
+            };
+            let report = run_fetch(
+                records,
+                &args,
+                client,
+                mirrors,
+                &cache,
+                Some(lock.checksums()),
+            )
+            .await?;
+            return finish_fetch(report, args.report.as_deref());
+        }
+        Commands::Verify { rootdir } => {
+            let checksums =
+                load_checksums(&onekp, &client, &cache, "checksums are not verified").await;
+            let files =
+                verify::listed_files(&rootdir, onekp.records(), &mirrors, checksums.as_ref())?;
+            let verification = verify::verify(&rootdir, &files, &client).await?;
+
+            for (path, problem) in verification.problems.iter() {
+                println!("{}: {} {}", "Failed".red(), path.display(), problem);
+            }
+            for path in verification.extraneous.iter() {
+                println!("{}: {}", "Extraneous".yellow(), path.display());
+            }
+            println!(
+                "{} files OK, {} with problems, {} extraneous",
+                verification.ok.len(),
+                verification.problems.len(),
+                verification.extraneous.len()
+            );
+            if !verification.problems.is_empty() {
+                return Ok(ExitCode::from(EXIT_VERIFY_FAILED));
+            }
+        }
+        Commands::Urls {
+            filter,
+            sequence_type,
+            products,
+            format,
+            rootdir,
+            path_template,
+            name_by,
+        } => {
+            let entries: Vec<aria2::Entry> = select_required(&filter, &onekp)?
+                .iter()
+                .flat_map(|rec| {
+                    sequence_type
+                        .with_products(&products)
+                        .into_iter()
+                        .map(|filename| aria2::Entry {
+                            urls: mirrors.urls(&rec.to_gigadb_path(filename)),
+                            out: rootdir.join(path_template.render(rec, filename, name_by)),
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            print!("{}", export::render(format, &entries));
+        }
+        Commands::AssemblyStats { filter, output } => {
+            let mut lines = vec![STATS_HEADER.to_owned()];
+            for rec in select_required(&filter, &onekp)?.iter() {
+                let stats = match stats::fetch_stats(rec, &client, &mirrors).await {
+                    Ok(stats) => stats,
                     Err(err) => {
-                        eprintln!("{}: {}\n{}", "Failed".red(), rec.species, err);
-                        err_ids.push(rec.id.to_owned());
+                        eprintln!(
+                            "{}: no statistics of {}: {}",
+                            "Warning".yellow(),
+                            rec.id,
+                            err
+                        );
+                        AssemblyStats::default()
                     }
+                };
+                lines.push(stats.to_tsv(&rec.id));
+            }
+            lines.push(String::new());
+            match output {
+                Some(path) => std::fs::write(path, lines.join("\n"))?,
+                None => print!("{}", lines.join("\n")),
+            }
+        }
+        Commands::ListFiles { id } => {
+            let records = onekp.filter(OneKpKey::Id, &id);
+            for missing in id.iter().filter(|i| !records.iter().any(|r| &r.id == *i)) {
+                eprintln!("{}: no sample {}", "Warning".yellow(), missing);
+            }
+            for rec in records.iter() {
+                for file in listing::list_files(rec, &client, &mirrors).await? {
+                    println!(
+                        "{}\t{}\t{}",
+                        rec.id,
+                        file.name,
+                        file.size
+                            .map(|size| HumanBytes(size).to_string())
+                            .unwrap_or_else(|| "-".to_owned())
+                    );
                 }
             }
-            eprintln!("--- Fetching end ---");
-            eprintln!("{}: {}", "Success IDs".green(), success_ids.join(","));
-            eprintln!("{}: {}", "Failed IDs".red(), err_ids.join(","));
         }
         Commands::MetaData {
-            filter_key,
-            filter_values,
+            filter,
+            format,
+            fields,
+            sort,
+            output,
         } => {
-            let mut lines = vec!["1kP_ID\tClade\tOrder\tFamily\tSpecies\tTissue Type".to_owned()];
-            if let Some(filter_key) = filter_key {
-                if let Some(filter_values) = filter_values {
-                    for rec in onekp.filter(filter_key, &filter_values).iter() {
-                        lines.push(format!(
-                            "{}\t{}\t{}\t{}\t{}\t{}",
-                            rec.id, rec.clade, rec.order, rec.family, rec.species, rec.tissue_type
-                        ));
-                    }
+            let mut records = select(&filter, &onekp)?;
+            sort.sort(&mut records);
+            match output {
+                Some(path) => {
+                    let mut file = BufWriter::new(File::create(path)?);
+                    output::write_records(format, &records, &fields, &mut file)?;
+                    file.flush()?;
                 }
-            } else {
-                for rec in onekp.records.iter() {
-                    lines.push(format!(
-                        "{}\t{}\t{}\t{}\t{}\t{}",
-                        rec.id, rec.clade, rec.order, rec.family, rec.species, rec.tissue_type
-                    ));
+                None if format == MetadataFormat::Parquet && std::io::stdout().is_terminal() => {
+                    anyhow::bail!("--format parquet needs --output or a redirected stdout")
+                }
+                None => {
+                    output::write_records(format, &records, &fields, &mut std::io::stdout().lock())?
                 }
             }
+        }
+        Commands::Show {
+            keys,
+            within,
+            filter,
+            format,
+            desc,
+            counts,
+        } => {
+            let mut records = select(&filter, &onekp)?;
+            records.retain(|r| within.iter().all(|w| w.matches(r)));
+            let mut groups: Vec<(Vec<&str>, usize)> =
+                count_by(&records, &keys).into_iter().collect();
+            if desc {
+                groups.reverse();
+            }
 
-            println!("{}", lines.join("\n"));
+            if format == MetadataFormat::Tsv {
+                for (depth, value, n) in nest(&groups) {
+                    let n = n.to_string();
+                    let row = if counts { vec![value, &n] } else { vec![value] };
+                    println!("{}{}", "  ".repeat(depth), output::delimited_row(row, '\t'));
+                }
+            } else {
+                let mut header: Vec<String> =
+                    keys.iter().map(|k| output::column_name(*k)).collect();
+                if counts {
+                    header.push("samples".to_owned());
+                }
+                let header: Vec<&str> = header.iter().map(|h| h.as_str()).collect();
+                let numbers: Vec<String> = groups.iter().map(|(_, n)| n.to_string()).collect();
+                let rows: Vec<Vec<&str>> = groups
+                    .iter()
+                    .zip(numbers.iter())
+                    .map(|((values, _), n)| {
+                        let mut row = values.clone();
+                        if counts {
+                            row.push(n);
+                        }
+                        row
+                    })
+                    .collect();
+                output::write_table(format, &header, &rows, &mut std::io::stdout().lock())?;
+            }
         }
-        Commands::Show { key } => {
-            let keyset: BTreeSet<String> = onekp
-                .records
+        Commands::Count {
+            by,
+            filter,
+            format,
+            sort_by_count,
+        } => {
+            let records = select(&filter, &onekp)?;
+            let mut counts: Vec<(Vec<&str>, usize)> = count_by(&records, &by).into_iter().collect();
+            if sort_by_count {
+                // stable, so groups of the same size stay sorted by value
+                counts.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+            }
+            let counts: Vec<(Vec<&str>, String)> = counts
                 .into_iter()
-                .map(|r| match key {
-                    OneKpKey::Id => r.id,
-                    OneKpKey::Clade => r.clade,
-                    OneKpKey::Family => r.family,
-                    OneKpKey::Order => r.order,
-                    OneKpKey::Species => r.species,
-                    OneKpKey::TissueType => r.tissue_type,
-                })
+                .map(|(g, n)| (g, n.to_string()))
                 .collect();
-
-            println!("{}", keyset.into_iter().collect::<Vec<String>>().join("\n"));
+            let rows: Vec<Vec<&str>> = counts
+                .iter()
+                .map(|(group, n)| group.iter().copied().chain([n.as_str()]).collect())
+                .collect();
+            let names: Vec<String> = by.iter().map(|k| output::column_name(*k)).collect();
+            let header: Vec<&str> = names
+                .iter()
+                .map(|n| n.as_str())
+                .chain(["samples"])
+                .collect();
+            output::write_table(format, &header, &rows, &mut std::io::stdout().lock())?;
+        }
+        Commands::Stats { filter } => {
+            let records = select(&filter, &onekp)?;
+            let checksums = load_checksums(
+                &onekp,
+                &client,
+                &cache,
+                "the samples with both sequence files are not counted",
+            )
+            .await;
+            print!("{}", Summary::new(&records, checksums.as_ref()));
+        }
+        Commands::Query { sql } => {
+            print!("{}", sql.run(onekp.records()).to_tsv());
+        }
+        Commands::Search { text } => {
+            let records = onekp.search(&text.join(" "));
+            if records.is_empty() {
+                eprintln!("No sample matches {:?}", text.join(" "));
+                return Ok(ExitCode::FAILURE);
+            }
+            let mut lines = vec![METADATA_HEADER.to_owned()];
+            lines.extend(records.iter().map(|r| r.to_tsv()));
+            println!("{}", lines.join("\n"));
+        }
+        Commands::Tree { filter } => {
+            print!(
+                "{}",
+                TaxonTree::new(&select(&filter, &onekp)?).to_indented()
+            );
         }
+        Commands::ExportTree {
+            filter,
+            format,
+            output,
+        } => {
+            let tree = TaxonTree::new(&select(&filter, &onekp)?);
+            let text = match format {
+                TreeFormat::Newick => tree.to_newick(),
+            };
+            match output {
+                Some(path) => std::fs::write(path, text + "\n")?,
+                None => println!("{}", text),
+            }
+        }
+        Commands::Tui { filter, args } => {
+            let term = Term::stdout();
+            let mut browser = Browser::new(select(&filter, &onekp)?);
+            loop {
+                match browser.run(&term)? {
+                    Action::Quit => break,
+                    Action::ListFiles(rec) => {
+                        let files = listing::list_files(&rec, &client, &mirrors).await;
+                        browser.set_files(&rec.id, files.map_err(|err| err.to_string()));
+                    }
+                    Action::Fetch(records) => {
+                        let report =
+                            run_fetch(records, &args, client, mirrors, &cache, None).await?;
+                        return finish_fetch(report, args.report.as_deref());
+                    }
+                }
+            }
+        }
+        Commands::Enrich { api_key } => {
+            let species = onekp.records().iter().map(|r| r.species.as_str());
+            let updated = taxonomy.update(species, &client, api_key.as_deref()).await;
+            // keep what was found before a failure
+            taxonomy.save(&cache_dir)?;
+            updated?;
+            let (looked_up, found) = taxonomy.counts();
+            eprintln!(
+                "Found {} of {} species in the NCBI Taxonomy, saved to {}",
+                found,
+                looked_up,
+                Taxonomy::path(&cache_dir).display()
+            );
+        }
+        Commands::Serve { host, port } => {
+            let checksums = load_checksums(
+                &onekp,
+                &client,
+                &cache,
+                "files are served without checksums",
+            )
+            .await;
+            serve::serve(onekp, checksums, mirrors, SocketAddr::new(host, port)).await?;
+        }
+        Commands::Cache { .. } => unreachable!("handled before loading the metadata"),
     }
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
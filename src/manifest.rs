@@ -0,0 +1,61 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checksum::{md5_file, sha256_file},
+    error::Result,
+    verify::dataset_files,
+    write_atomic,
+};
+
+/// Checksum manifests in the `sha256sum`/`md5sum` format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ManifestAlgorithm {
+    /// SHA256SUMS
+    Sha256,
+    /// MD5SUMS
+    Md5,
+}
+
+impl ManifestAlgorithm {
+    pub fn filename(self) -> &'static str {
+        match self {
+            Self::Sha256 => "SHA256SUMS",
+            Self::Md5 => "MD5SUMS",
+        }
+    }
+
+    fn hash(self, path: &Path) -> Result<String> {
+        match self {
+            Self::Sha256 => Ok(sha256_file(path)?),
+            Self::Md5 => Ok(md5_file(path)?),
+        }
+    }
+}
+
+/// Write a manifest of every file under `rootdir`, checkable with `sha256sum -c` from `rootdir`.
+/// Partial downloads and the manifests themselves are left out. Returns the path of the manifest.
+pub fn write(rootdir: &Path, algorithm: ManifestAlgorithm) -> Result<PathBuf> {
+    let manifests: Vec<&str> = ManifestAlgorithm::value_variants()
+        .iter()
+        .map(|a| a.filename())
+        .collect();
+    let mut lines = String::new();
+    for relative in dataset_files(rootdir)? {
+        let is_part = relative.extension().is_some_and(|ext| ext == "part");
+        if is_part || manifests.iter().any(|m| relative == Path::new(m)) {
+            continue;
+        }
+        lines.push_str(&format!(
+            "{}  {}\n",
+            algorithm.hash(&rootdir.join(&relative))?,
+            relative.display()
+        ));
+    }
+    let path = rootdir.join(algorithm.filename());
+    write_atomic(&path, lines.as_bytes())?;
+    Ok(path)
+}
@@ -0,0 +1,194 @@
+//! Versions of the sample table, kept in the cache directory so that later runs can be pinned to one
+//! with `--metadata-version` even after GigaDB updates the table.
+
+use std::{
+    fs::{create_dir_all, read_dir, read_to_string},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{error::OneKpError, write_atomic};
+
+/// Subdirectory of the cache directory holding the versions
+pub(crate) const VERSIONS_DIR: &str = "versions";
+const VERSION_FILENAME: &str = "version.json";
+const METADATA_FILENAME: &str = "metadata.tsv";
+const LISTING_FILENAME: &str = "listing.html";
+
+/// A sample table, identified by its hash, as recorded in fetch reports and lockfiles
+///
+/// ```
+/// use onekp::metadata_version::MetadataVersion;
+///
+/// let version = MetadataVersion::new("1kp", "id\tclade\nURDJ\tLiverworts\n", None);
+/// assert_eq!(version.id().len(), 12);
+/// assert!(version.sha256.starts_with(version.id()));
+/// assert_ne!(version, MetadataVersion::new("1kp", "id\tclade\n", None));
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataVersion {
+    /// Dataset of the table, see [`DataSource::name`](crate::source::DataSource::name)
+    pub dataset: String,
+    /// Of the sample table followed by the listing, if any
+    pub sha256: String,
+    /// Unix time in seconds the table was first seen
+    pub first_seen: u64,
+}
+
+impl PartialEq for MetadataVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.dataset == other.dataset && self.sha256 == other.sha256
+    }
+}
+
+impl Eq for MetadataVersion {}
+
+impl MetadataVersion {
+    /// Version of `metadata` and `listing` seen now
+    pub fn new(dataset: &str, metadata: &str, listing: Option<&str>) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(metadata.as_bytes());
+        if let Some(listing) = listing {
+            hasher.update(listing.as_bytes());
+        }
+        Self {
+            dataset: dataset.to_owned(),
+            sha256: hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            first_seen: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Start of the hash, enough to tell the versions apart and given to `--metadata-version`
+    pub fn id(&self) -> &str {
+        &self.sha256[..12]
+    }
+}
+
+/// Versions stored in `versions/<dataset>/<sha256>/` of a cache directory
+#[derive(Debug, Clone)]
+pub struct VersionStore {
+    dir: PathBuf,
+}
+
+impl VersionStore {
+    pub fn new(cache_dir: &Path) -> Self {
+        Self {
+            dir: cache_dir.join(VERSIONS_DIR),
+        }
+    }
+
+    /// Store `metadata` and `listing` unless they are stored already, returning their version
+    pub fn save(
+        &self,
+        dataset: &str,
+        metadata: &str,
+        listing: Option<&str>,
+    ) -> Result<MetadataVersion, OneKpError> {
+        let version = MetadataVersion::new(dataset, metadata, listing);
+        let dir = self.dir.join(dataset).join(&version.sha256);
+        if let Ok(stored) = read_version(&dir) {
+            return Ok(stored);
+        }
+        create_dir_all(&dir)?;
+        write_atomic(&dir.join(METADATA_FILENAME), metadata.as_bytes())?;
+        if let Some(listing) = listing {
+            write_atomic(&dir.join(LISTING_FILENAME), listing.as_bytes())?;
+        }
+        // written last, a version without it is incomplete
+        write_atomic(
+            &dir.join(VERSION_FILENAME),
+            &serde_json::to_vec_pretty(&version)?,
+        )?;
+        Ok(version)
+    }
+
+    /// Stored versions of every dataset, oldest first
+    pub fn list(&self) -> Result<Vec<MetadataVersion>, OneKpError> {
+        let mut versions = vec![];
+        for dataset in subdirs(&self.dir)? {
+            for dir in subdirs(&dataset)? {
+                if let Ok(version) = read_version(&dir) {
+                    versions.push(version);
+                }
+            }
+        }
+        versions.sort_by_key(|v| v.first_seen);
+        Ok(versions)
+    }
+
+    /// The stored version of `dataset` whose hash starts with `id`, with its table and listing
+    pub fn load(
+        &self,
+        dataset: &str,
+        id: &str,
+    ) -> Result<(MetadataVersion, String, Option<String>), OneKpError> {
+        let id = id.trim().to_lowercase();
+        let found: Vec<MetadataVersion> = self
+            .list()?
+            .into_iter()
+            .filter(|v| v.dataset == dataset && !id.is_empty() && v.sha256.starts_with(&id))
+            .collect();
+        let version = match found.as_slice() {
+            [version] => version.clone(),
+            [] => {
+                return Err(OneKpError::InvalidOptions(format!(
+                    "metadata version {} of {} is not stored, see `onekp cache versions`",
+                    id, dataset
+                )))
+            }
+            _ => {
+                return Err(OneKpError::InvalidOptions(format!(
+                    "metadata version {} is ambiguous: {}",
+                    id,
+                    found
+                        .iter()
+                        .map(|v| v.sha256.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                )))
+            }
+        };
+        let dir = self.dir.join(dataset).join(&version.sha256);
+        let metadata = read_to_string(dir.join(METADATA_FILENAME))?;
+        let listing = match read_to_string(dir.join(LISTING_FILENAME)) {
+            Ok(listing) => Some(listing),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        Ok((version, metadata, listing))
+    }
+}
+
+fn read_version(dir: &Path) -> Result<MetadataVersion, OneKpError> {
+    Ok(serde_json::from_str(&read_to_string(
+        dir.join(VERSION_FILENAME),
+    )?)?)
+}
+
+/// Directories in `dir`, none if it does not exist
+fn subdirs(dir: &Path) -> Result<Vec<PathBuf>, OneKpError> {
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    let mut dirs = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    Ok(dirs)
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use crate::source::{DataSource, OneKpSource};
+
+/// Base URL of the 1KP dataset on the CNGB FTP server
+pub const PRIMARY: &str = "https://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/";
+
+/// The same directory served over plain FTP
+pub const PRIMARY_FTP: &str = "ftp://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/";
+
+/// Copies of the dataset tried when the primary server fails
+pub const BUILTIN_MIRRORS: &[&str] =
+    &["https://s3.ap-northeast-1.wasabisys.com/gigadb-datasets/live/pub/10.5524/100001_101000/100627/"];
+
+/// Base URLs a dataset file is downloaded from, in order of preference:
+/// the primary server, user-supplied mirrors, then the built-in ones.
+#[derive(Debug, Clone)]
+pub struct Mirrors {
+    bases: Arc<Vec<String>>,
+}
+
+impl Mirrors {
+    /// Mirrors of the 1KP dataset
+    pub fn new(extra: &[String]) -> Self {
+        Self::for_source(&OneKpSource, extra)
+    }
+
+    /// Mirrors of the dataset of `source`
+    pub fn for_source(source: &dyn DataSource, extra: &[String]) -> Self {
+        let mut bases: Vec<String> = vec![];
+        let candidates = std::iter::once(source.base_url())
+            .chain(extra.iter().map(|s| s.as_str()))
+            .chain(source.mirrors().iter().copied());
+        for base in candidates {
+            let base = format!("{}/", base.trim_end_matches('/'));
+            if !bases.contains(&base) {
+                bases.push(base);
+            }
+        }
+
+        Self {
+            bases: Arc::new(bases),
+        }
+    }
+
+    /// Try `base` before every other URL
+    pub fn with_primary(&self, base: &str) -> Self {
+        let base = format!("{}/", base.trim_end_matches('/'));
+        let mut bases = vec![base.clone()];
+        bases.extend(self.bases.iter().filter(|b| **b != base).cloned());
+        Self {
+            bases: Arc::new(bases),
+        }
+    }
+
+    /// Candidate URLs of `path`, relative to the dataset root
+    pub fn urls(&self, path: &str) -> Vec<String> {
+        self.bases
+            .iter()
+            .map(|base| format!("{}{}", base, path.trim_start_matches('/')))
+            .collect()
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures_util::Stream;
+use tokio::sync::OnceCell;
+
+use crate::{
+    checksum::Checksums,
+    client::{Client, ClientOptions},
+    default_cache_dir,
+    error::OneKpError,
+    fetch::{fetch_sample, fetch_stream},
+    mirror::Mirrors,
+    progress::DownloadEvent,
+    report::SampleReport,
+    source::{DataSource, OneKpSource},
+    transport::Transport,
+    Cache, OneKp, OneKpKey, OneKpRecord, SequenceType,
+};
+
+/// Entry point of the library: loads the metadata once and downloads samples.
+///
+/// ```no_run
+/// use std::{path::Path, time::Duration};
+/// use onekp::{OneKpClient, OneKpKey, SequenceType};
+///
+/// # async fn example() -> Result<(), onekp::OneKpError> {
+/// let onekp = OneKpClient::builder()
+///     .cache_dir("/tmp/onekp")
+///     .interval(Duration::from_secs(1))
+///     .max_retry(3)
+///     .build()?;
+/// for rec in onekp.filter(OneKpKey::Clade, &["Mosses".to_owned()]).await? {
+///     onekp.download(&rec, SequenceType::Protein, Path::new("data")).await;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct OneKpClient {
+    client: Client,
+    source: Arc<dyn DataSource>,
+    mirrors: Mirrors,
+    cache: Cache,
+    metadata: OnceCell<OneKp>,
+    checksums: OnceCell<Option<Arc<Checksums>>>,
+}
+
+impl OneKpClient {
+    pub fn builder() -> OneKpClientBuilder {
+        OneKpClientBuilder::default()
+    }
+
+    /// The underlying HTTP/FTP client
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Metadata of every sample, downloaded on first use
+    pub async fn load_metadata(&self) -> Result<&OneKp, OneKpError> {
+        self.metadata
+            .get_or_try_init(|| OneKp::load_from(self.source.clone(), &self.cache, &self.client))
+            .await
+    }
+
+    /// Samples whose `key` is one of `values`
+    pub async fn filter(
+        &self,
+        key: OneKpKey,
+        values: &[String],
+    ) -> Result<Vec<OneKpRecord>, OneKpError> {
+        Ok(self.load_metadata().await?.filter(key, values))
+    }
+
+    /// Download the `sequence_type` files of `record` into `dest`,
+    /// verified with the published checksums when they are available
+    pub async fn download(
+        &self,
+        record: &OneKpRecord,
+        sequence_type: SequenceType,
+        dest: &Path,
+    ) -> SampleReport {
+        let checksums = self.checksums().await;
+        fetch_sample(
+            record,
+            sequence_type,
+            dest,
+            &self.client,
+            &self.mirrors,
+            checksums,
+        )
+        .await
+    }
+
+    /// Download the `sequence_type` files of `records` into `dest`, `jobs` samples at a time,
+    /// as a stream of progress updates and results, see [`fetch_stream`]
+    pub async fn download_stream(
+        &self,
+        records: Vec<OneKpRecord>,
+        sequence_type: SequenceType,
+        dest: impl Into<PathBuf>,
+        jobs: usize,
+    ) -> impl Stream<Item = DownloadEvent> {
+        let checksums = self.checksums().await;
+        fetch_stream(
+            records,
+            sequence_type,
+            dest.into(),
+            self.client.clone(),
+            self.mirrors.clone(),
+            checksums,
+            jobs,
+        )
+    }
+
+    async fn checksums(&self) -> Option<Arc<Checksums>> {
+        self.checksums
+            .get_or_init(|| async {
+                let url = self.source.checksum_url()?;
+                self.cache
+                    .get(url, &self.client)
+                    .await
+                    .ok()
+                    .map(|text| Arc::new(Checksums::parse(&text)))
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Builder of [`OneKpClient`], see [`OneKpClient::builder`]
+#[derive(Debug, Clone, Default)]
+pub struct OneKpClientBuilder {
+    options: ClientOptions,
+    source: Option<Arc<dyn DataSource>>,
+    mirrors: Vec<String>,
+    cache_dir: Option<PathBuf>,
+    transport: Option<Arc<dyn Transport>>,
+}
+
+impl OneKpClientBuilder {
+    /// Directory the metadata and checksum list are cached in [default: [`default_cache_dir`]]
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Interval between requests to the same host
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.options.interval = interval;
+        self
+    }
+
+    /// Number of attempts for each request
+    pub fn max_retry(mut self, max_retry: usize) -> Self {
+        self.options.max_retry = max_retry;
+        self
+    }
+
+    /// How long to wait for a connection or data
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+
+    /// `http://`, `https://` or `socks5://` proxy for every request
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.options.proxy = Some(url.into());
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.options.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Extra header sent with every request
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Dataset to download from [default: the 1KP assemblies on GigaDB]
+    pub fn source(mut self, source: Arc<dyn DataSource>) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Base URL of a mirror tried when the primary server fails
+    pub fn mirror(mut self, base: impl Into<String>) -> Self {
+        self.mirrors.push(base.into());
+        self
+    }
+
+    /// Send the HTTP requests through `transport`, e.g. a mock server in tests
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    pub fn build(self) -> Result<OneKpClient, OneKpError> {
+        let client = Client::new(&self.options)?;
+        let source = self.source.unwrap_or_else(|| Arc::new(OneKpSource));
+        Ok(OneKpClient {
+            client: match self.transport {
+                Some(transport) => client.with_transport(transport),
+                None => client,
+            },
+            mirrors: Mirrors::for_source(source.as_ref(), &self.mirrors),
+            source,
+            cache: Cache::new(match self.cache_dir {
+                Some(dir) => dir,
+                None => default_cache_dir()?,
+            }),
+            metadata: OnceCell::new(),
+            checksums: OnceCell::new(),
+        })
+    }
+}
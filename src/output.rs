@@ -0,0 +1,385 @@
+use std::{cmp::Ordering, io::Write};
+
+use clap::{Args, ValueEnum};
+use colored::*;
+use console::{measure_text_width, pad_str, Alignment, Term};
+use csv::{QuoteStyle, Terminator, WriterBuilder};
+use serde_json::{Map, Value};
+
+use crate::{error::OneKpError, parquet::to_parquet, OneKpKey, OneKpRecord, METADATA_HEADER};
+
+/// Formats of the tables printed by `onekp meta-data` and `onekp show`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum MetadataFormat {
+    /// Tab-separated with a header, see [`METADATA_HEADER`]
+    #[default]
+    Tsv,
+    /// Comma-separated with the same header
+    Csv,
+    /// Array of record objects
+    Json,
+    /// One record object per line
+    Ndjson,
+    /// Columns aligned to the width of the terminal, TSV when the output is not a terminal
+    Table,
+    /// GitHub Flavored Markdown table
+    Markdown,
+    /// Apache Parquet file, with the NCBI Taxonomy columns of the records
+    Parquet,
+}
+
+/// Write the `fields` of `records` to `out` in `format`. Without fields, JSON has every field
+/// of the records, Parquet every [`OneKpKey`] and the other formats the columns of [`METADATA_HEADER`].
+///
+/// ```
+/// use bytes::Bytes;
+/// use parquet::{
+///     file::reader::{FileReader, SerializedFileReader},
+///     record::RowAccessor,
+/// };
+/// use onekp::{
+///     output::{write_records, MetadataFormat},
+///     taxonomy::{Lineage, Taxon},
+///     OneKpRecord,
+/// };
+///
+/// let taxon = |taxid: &str, name: &str, rank: &str| Taxon {
+///     taxid: taxid.to_owned(),
+///     name: name.to_owned(),
+///     rank: rank.to_owned(),
+/// };
+/// let rec = |id: &str, lineage: Option<Lineage>| OneKpRecord {
+///     id: id.to_owned(),
+///     clade: "Liverworts".to_owned(),
+///     order: "Marchantiales".to_owned(),
+///     family: "Marchantiaceae".to_owned(),
+///     species: "Marchantia polymorpha".to_owned(),
+///     tissue_type: "thallus".to_owned(),
+///     prefix: id.to_owned(),
+///     source: None,
+///     lineage,
+/// };
+/// let lineage = Lineage {
+///     taxid: "3197".to_owned(),
+///     lineage: vec![
+///         taxon("35493", "Streptophyta", "phylum"),
+///         taxon("3195", "Marchantiopsida", "class"),
+///         taxon("3196", "Marchantia", "genus"),
+///     ],
+/// };
+/// let mut file = vec![];
+/// write_records(
+///     MetadataFormat::Parquet,
+///     &[rec("URDJ", Some(lineage)), rec("ABCD", None)],
+///     &[],
+///     &mut file,
+/// )
+/// .unwrap();
+///
+/// let reader = SerializedFileReader::new(Bytes::from(file)).unwrap();
+/// let schema = reader.metadata().file_metadata().schema_descr();
+/// let names: Vec<&str> = schema.columns().iter().map(|c| c.name()).collect();
+/// assert_eq!(names[6..], ["taxid", "phylum", "class", "genus"]);
+/// let rows: Vec<String> = reader
+///     .get_row_iter(None)
+///     .unwrap()
+///     .map(|row| {
+///         let row = row.unwrap();
+///         (0..names.len()).map(|i| row.get_string(i).unwrap().as_str()).collect::<Vec<_>>().join("|")
+///     })
+///     .collect();
+/// assert_eq!(rows, [
+///     "URDJ|Liverworts|Marchantiales|Marchantiaceae|Marchantia polymorpha|thallus|3197|Streptophyta|Marchantiopsida|Marchantia",
+///     "ABCD|Liverworts|Marchantiales|Marchantiaceae|Marchantia polymorpha|thallus|No data|No data|No data|No data",
+/// ]);
+/// ```
+pub fn write_records(
+    format: MetadataFormat,
+    records: &[OneKpRecord],
+    fields: &[OneKpKey],
+    out: &mut impl Write,
+) -> Result<(), OneKpError> {
+    let machine_readable = matches!(
+        format,
+        MetadataFormat::Json | MetadataFormat::Ndjson | MetadataFormat::Parquet
+    );
+    let keys = match fields {
+        [] if format == MetadataFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, records)?;
+            writeln!(out)?;
+            return Ok(());
+        }
+        [] if format == MetadataFormat::Ndjson => {
+            for rec in records.iter() {
+                serde_json::to_writer(&mut *out, rec)?;
+                writeln!(out)?;
+            }
+            return Ok(());
+        }
+        [] if format == MetadataFormat::Parquet => OneKpKey::value_variants(),
+        [] => &OneKpKey::value_variants()[..METADATA_HEADER.split('\t').count()],
+        fields => fields,
+    };
+    let names: Vec<String> = keys
+        .iter()
+        .map(|k| {
+            if machine_readable {
+                column_name(*k)
+            } else {
+                column_title(*k).to_owned()
+            }
+        })
+        .collect();
+    let header: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+    let rows: Vec<Vec<&str>> = records
+        .iter()
+        .map(|r| keys.iter().map(|k| r.get(*k)).collect())
+        .collect();
+    write_table(format, &header, &rows, out)
+}
+
+/// Order of the metadata output
+#[derive(Debug, Clone, Default, Args)]
+pub struct SortArgs {
+    /// Sort by these columns, the later ones breaking ties of the earlier ones [default: order of the sample list]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub sort_by: Vec<OneKpKey>,
+    /// Sort in descending order
+    #[arg(long)]
+    pub desc: bool,
+}
+
+impl SortArgs {
+    /// Sort `records` by --sort-by, keeping the order of the ties
+    pub fn sort(&self, records: &mut [OneKpRecord]) {
+        if self.sort_by.is_empty() {
+            if self.desc {
+                records.reverse();
+            }
+            return;
+        }
+        records.sort_by(|a, b| {
+            let ordering = self
+                .sort_by
+                .iter()
+                .map(|key| compare(a.get(*key), b.get(*key)))
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal);
+            if self.desc {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+}
+
+/// Numbers by value, anything else as text
+pub(crate) fn compare(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+/// Name of `key` in JSON and Parquet, that of the field of [`OneKpRecord`]
+pub fn column_name(key: OneKpKey) -> String {
+    key.to_possible_value()
+        .map(|v| v.get_name().replace('-', "_"))
+        .unwrap_or_default()
+}
+
+/// Header of `key` in the tables, as in [`METADATA_HEADER`]
+pub fn column_title(key: OneKpKey) -> &'static str {
+    match key {
+        OneKpKey::Id => "1kP_ID",
+        OneKpKey::Clade => "Clade",
+        OneKpKey::Order => "Order",
+        OneKpKey::Family => "Family",
+        OneKpKey::Species => "Species",
+        OneKpKey::TissueType => "Tissue Type",
+        OneKpKey::Taxid => "NCBI Taxid",
+        OneKpKey::Phylum => "Phylum",
+        OneKpKey::Class => "Class",
+        OneKpKey::Genus => "Genus",
+    }
+}
+
+/// Write a table of `header` and `rows` to `out` in `format`.
+/// JSON has an object per row with the header as keys.
+pub fn write_table(
+    format: MetadataFormat,
+    header: &[&str],
+    rows: &[Vec<&str>],
+    out: &mut impl Write,
+) -> Result<(), OneKpError> {
+    let object = |row: &Vec<&str>| {
+        Value::Object(
+            header
+                .iter()
+                .zip(row.iter())
+                .map(|(h, v)| (h.to_string(), Value::String(v.to_string())))
+                .collect::<Map<String, Value>>(),
+        )
+    };
+    match format {
+        MetadataFormat::Tsv | MetadataFormat::Csv => {
+            let delimiter = if format == MetadataFormat::Tsv {
+                '\t'
+            } else {
+                ','
+            };
+            let mut writer = delimited_writer(delimiter, &mut *out);
+            writer.write_record(header)?;
+            for row in rows.iter() {
+                writer.write_record(row)?;
+            }
+            writer.flush()?;
+        }
+        MetadataFormat::Json => {
+            let objects: Vec<Value> = rows.iter().map(object).collect();
+            serde_json::to_writer_pretty(&mut *out, &objects)?;
+            writeln!(out)?;
+        }
+        MetadataFormat::Ndjson => {
+            for row in rows.iter() {
+                serde_json::to_writer(&mut *out, &object(row))?;
+                writeln!(out)?;
+            }
+        }
+        MetadataFormat::Table => {
+            let term = Term::stdout();
+            if !term.is_term() {
+                return write_table(MetadataFormat::Tsv, header, rows, out);
+            }
+            let width = term.size_checked().map(|(_, cols)| cols as usize);
+            write!(out, "{}", aligned_table(header, rows, width))?;
+        }
+        MetadataFormat::Markdown => write!(out, "{}", markdown_table(header, rows))?,
+        MetadataFormat::Parquet => out.write_all(&to_parquet(header, rows)?)?,
+    }
+    Ok(())
+}
+
+/// Narrowest a column is shrunk to when the table is wider than the terminal
+const MIN_COLUMN_WIDTH: usize = 6;
+
+/// Columns padded to the same width, separated by two spaces, with a bold header.
+/// The widest columns are shrunk and their values cut with `…` to fit in `width`.
+///
+/// ```
+/// use onekp::output::aligned_table;
+///
+/// colored::control::set_override(false);
+/// let table = aligned_table(
+///     &["ID", "Species"],
+///     &[vec!["URDJ", "Marchantia polymorpha"], vec!["WOGB", "Andreaea rupestris"]],
+///     Some(20),
+/// );
+/// assert_eq!(table, "ID    Species\nURDJ  Marchantia po…\nWOGB  Andreaea rupe…\n");
+/// ```
+pub fn aligned_table(header: &[&str], rows: &[Vec<&str>], width: Option<usize>) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| measure_text_width(h)).collect();
+    for row in rows.iter() {
+        for (w, value) in widths.iter_mut().zip(row.iter()) {
+            *w = (*w).max(measure_text_width(value));
+        }
+    }
+    if let Some(width) = width {
+        let separators = 2 * widths.len().saturating_sub(1);
+        while widths.iter().sum::<usize>() + separators > width {
+            let Some(widest) = widths
+                .iter_mut()
+                .filter(|w| **w > MIN_COLUMN_WIDTH)
+                .max_by_key(|w| **w)
+            else {
+                break;
+            };
+            *widest -= 1;
+        }
+    }
+
+    let line = |row: &[&str]| {
+        row.iter()
+            .zip(widths.iter())
+            .map(|(value, w)| pad_str(value, *w, Alignment::Left, Some("…")).into_owned())
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_owned()
+    };
+    let mut table = String::new();
+    let header_line = line(header);
+    table.push_str(&format!("{}\n", header_line.bold()));
+    for row in rows.iter() {
+        table.push_str(&line(row));
+        table.push('\n');
+    }
+    table
+}
+
+/// Markdown table, escaping the `|` and line breaks of the values
+///
+/// ```
+/// use onekp::output::markdown_table;
+///
+/// assert_eq!(
+///     markdown_table(&["ID", "Tissue Type"], &[vec!["URDJ", "leaf | stem"]]),
+///     "| ID | Tissue Type |\n| --- | --- |\n| URDJ | leaf \\| stem |\n"
+/// );
+/// ```
+pub fn markdown_table(header: &[&str], rows: &[Vec<&str>]) -> String {
+    let line = |row: &[&str]| {
+        let cells: Vec<String> = row
+            .iter()
+            .map(|v| v.replace('|', "\\|").replace(['\r', '\n'], " "))
+            .collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+    let mut table = line(header);
+    table.push_str(&format!("|{}\n", " --- |".repeat(header.len())));
+    for row in rows.iter() {
+        table.push_str(&line(row));
+    }
+    table
+}
+
+/// CSV writer separating the fields with `delimiter`. Fields are quoted the way RFC 4180 does
+/// with `,`, but never in TSV, which is read back by splitting the lines at tabs.
+pub fn delimited_writer<W: Write>(delimiter: char, out: W) -> csv::Writer<W> {
+    WriterBuilder::new()
+        .delimiter(delimiter as u8)
+        .quote_style(if delimiter == '\t' {
+            QuoteStyle::Never
+        } else {
+            QuoteStyle::Necessary
+        })
+        .terminator(Terminator::Any(b'\n'))
+        .from_writer(out)
+}
+
+/// Join `fields` with `delimiter` the way [`delimited_writer`] writes them
+///
+/// ```
+/// use onekp::output::delimited_row;
+///
+/// assert_eq!(
+///     delimited_row(["URDJ", "leaf, stem", "6\" pot"], ','),
+///     r#"URDJ,"leaf, stem","6"" pot""#
+/// );
+/// assert_eq!(delimited_row(["URDJ", "leaf, stem", "6\" pot"], '\t'), "URDJ\tleaf, stem\t6\" pot");
+/// ```
+pub fn delimited_row<'a>(fields: impl IntoIterator<Item = &'a str>, delimiter: char) -> String {
+    let mut writer = delimited_writer(delimiter, vec![]);
+    writer
+        .write_record(fields)
+        .expect("writing to memory does not fail");
+    let mut row = String::from_utf8(
+        writer
+            .into_inner()
+            .expect("writing to memory does not fail"),
+    )
+    .expect("the fields are UTF-8");
+    row.pop();
+    row
+}
@@ -0,0 +1,81 @@
+//! Parquet export of the metadata tables: one row group of required UTF-8 columns,
+//! written with the [`parquet`] crate.
+
+use std::sync::Arc;
+
+use parquet::{
+    basic::{LogicalType, Repetition, Type as PhysicalType},
+    data_type::{ByteArray, ByteArrayType},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::types::Type,
+};
+
+use crate::error::OneKpError;
+
+/// Parquet file of a table of strings, `columns` naming the values of each row
+///
+/// ```
+/// use bytes::Bytes;
+/// use parquet::{
+///     file::reader::{FileReader, SerializedFileReader},
+///     record::RowAccessor,
+/// };
+/// use onekp::parquet::to_parquet;
+///
+/// let columns = ["id", "species", "taxid", "phylum"];
+/// let rows = [
+///     vec!["URDJ", "Marchantia polymorpha", "3197", "Streptophyta"],
+///     vec!["ABCD", "Bryum argenteum", "No data", "No data"],
+/// ];
+/// let file = to_parquet(&columns, &rows).unwrap();
+///
+/// let reader = SerializedFileReader::new(Bytes::from(file)).unwrap();
+/// let schema = reader.metadata().file_metadata().schema_descr();
+/// let names: Vec<&str> = schema.columns().iter().map(|c| c.name()).collect();
+/// assert_eq!(names, columns);
+/// let read: Vec<Vec<String>> = reader
+///     .get_row_iter(None)
+///     .unwrap()
+///     .map(|row| {
+///         let row = row.unwrap();
+///         (0..columns.len()).map(|i| row.get_string(i).unwrap().clone()).collect()
+///     })
+///     .collect();
+/// assert_eq!(read, rows);
+/// ```
+pub fn to_parquet(columns: &[&str], rows: &[Vec<&str>]) -> Result<Vec<u8>, OneKpError> {
+    let fields = columns
+        .iter()
+        .map(|name| {
+            Ok(Arc::new(
+                Type::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
+                    .with_repetition(Repetition::REQUIRED)
+                    .with_logical_type(Some(LogicalType::String))
+                    .build()?,
+            ))
+        })
+        .collect::<Result<Vec<_>, OneKpError>>()?;
+    let schema = Type::group_type_builder("schema")
+        .with_fields(fields)
+        .build()?;
+    let properties = WriterProperties::builder()
+        .set_created_by(concat!("onekp version ", env!("CARGO_PKG_VERSION")).to_owned())
+        .build();
+
+    let mut writer = SerializedFileWriter::new(vec![], Arc::new(schema), Arc::new(properties))?;
+    let mut row_group = writer.next_row_group()?;
+    let mut i = 0;
+    while let Some(mut column) = row_group.next_column()? {
+        let values: Vec<ByteArray> = rows
+            .iter()
+            .map(|row| ByteArray::from(row.get(i).copied().unwrap_or_default()))
+            .collect();
+        column
+            .typed::<ByteArrayType>()
+            .write_batch(&values, None, None)?;
+        column.close()?;
+        i += 1;
+    }
+    row_group.close()?;
+    Ok(writer.into_inner()?)
+}
@@ -0,0 +1,153 @@
+use std::{fmt, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{NameBy, OneKpRecord};
+
+/// Placeholders accepted in a path template
+const PLACEHOLDERS: &[&str] = &[
+    "id",
+    "clade",
+    "order",
+    "family",
+    "species",
+    "tissue_type",
+    "prefix",
+    "file",
+];
+
+/// Destination of a downloaded file relative to the root directory,
+/// e.g. `{clade}/{order}/{species}/{file}`.
+///
+/// Every placeholder is replaced by a single sanitized path component,
+/// so metadata values can never escape the root directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PathTemplate {
+    template: String,
+}
+
+impl PathTemplate {
+    pub const DEFAULT: &'static str = "{file}";
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if s.starts_with('/') || s.starts_with('\\') {
+            return Err("path template must be relative".to_owned());
+        }
+        let mut has_file = false;
+        for component in s.split('/') {
+            if component.is_empty() || component == "." || component == ".." {
+                return Err(format!(
+                    "invalid path component in template: {:?}",
+                    component
+                ));
+            }
+            let mut rest = component;
+            while let Some(start) = rest.find('{') {
+                let end = rest[start..]
+                    .find('}')
+                    .ok_or_else(|| format!("unclosed placeholder in template: {}", s))?;
+                let name = &rest[start + 1..start + end];
+                if !PLACEHOLDERS.contains(&name) {
+                    return Err(format!(
+                        "unknown placeholder {{{}}}, expected one of {}",
+                        name,
+                        PLACEHOLDERS
+                            .iter()
+                            .map(|p| format!("{{{}}}", p))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+                has_file |= name == "file";
+                rest = &rest[start + end + 1..];
+            }
+        }
+        if !has_file {
+            return Err("path template must contain {file}".to_owned());
+        }
+
+        Ok(Self {
+            template: s.to_owned(),
+        })
+    }
+
+    /// Path of `filename` of `rec`, relative to the root directory
+    pub fn render(&self, rec: &OneKpRecord, filename: &str, name_by: NameBy) -> PathBuf {
+        self.template
+            .split('/')
+            .map(|component| {
+                let mut rendered = String::new();
+                let mut rest = component;
+                while let Some(start) = rest.find('{') {
+                    // validated in `parse`
+                    let end = start + rest[start..].find('}').expect("closed placeholder");
+                    rendered.push_str(&rest[..start]);
+                    let value = match &rest[start + 1..end] {
+                        "id" => rec.id.clone(),
+                        "clade" => rec.clade.clone(),
+                        "order" => rec.order.clone(),
+                        "family" => rec.family.clone(),
+                        "species" => rec.species.clone(),
+                        "tissue_type" => rec.tissue_type.clone(),
+                        "prefix" => rec.prefix.clone(),
+                        "file" => rec.to_filename(filename, name_by),
+                        _ => unreachable!("validated in parse"),
+                    };
+                    rendered.push_str(&sanitize(&value));
+                    rest = &rest[end + 1..];
+                }
+                rendered.push_str(rest);
+                rendered
+            })
+            .collect()
+    }
+}
+
+impl Default for PathTemplate {
+    fn default() -> Self {
+        Self {
+            template: Self::DEFAULT.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for PathTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+impl TryFrom<String> for PathTemplate {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(&s)
+    }
+}
+
+impl From<PathTemplate> for String {
+    fn from(template: PathTemplate) -> Self {
+        template.template
+    }
+}
+
+/// Make a metadata value safe to use as one path component
+pub fn sanitize(value: &str) -> String {
+    let sanitized: String = value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_whitespace() || c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // no hidden files, `.` or `..`
+    let sanitized = sanitized.trim_matches('.');
+    if sanitized.is_empty() {
+        "_".to_owned()
+    } else {
+        sanitized.to_owned()
+    }
+}
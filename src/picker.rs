@@ -0,0 +1,158 @@
+use std::collections::BTreeSet;
+
+use colored::*;
+use console::{pad_str, Alignment, Key, Term};
+
+use crate::{error::OneKpError, OneKpRecord};
+
+/// Help line at the bottom of the list
+const HELP: &str = "type to search, ↑/↓ to move, Tab to mark, Enter to download, Esc to cancel";
+
+/// Let the user mark samples of `records` in a list on the terminal, narrowed down by a fuzzy search.
+/// Enter returns the marked samples, in the order of `records`, or the highlighted one if none is marked.
+/// Esc returns no sample.
+pub fn pick(records: Vec<OneKpRecord>) -> Result<Vec<OneKpRecord>, OneKpError> {
+    let term = Term::stderr();
+    if !term.is_term() {
+        return Err(OneKpError::InvalidOptions(
+            "--interactive needs a terminal".to_owned(),
+        ));
+    }
+    let lines: Vec<String> = records
+        .iter()
+        .map(|r| format!("{}  {}  {}  {}", r.id, r.clade, r.species, r.tissue_type))
+        .collect();
+
+    let mut picker = Picker {
+        query: String::new(),
+        matches: (0..lines.len()).collect(),
+        cursor: 0,
+        marked: BTreeSet::new(),
+    };
+    term.hide_cursor()?;
+    let mut drawn = 0;
+    let picked = loop {
+        term.clear_last_lines(drawn)?;
+        let (height, width) = term.size();
+        let screen = picker.render(&lines, (height as usize).saturating_sub(3).max(1), width);
+        drawn = screen.lines().count();
+        term.write_str(&screen)?;
+
+        match term.read_key()? {
+            Key::Enter => {
+                break match (picker.marked.is_empty(), picker.matches.get(picker.cursor)) {
+                    (true, Some(i)) => BTreeSet::from([*i]),
+                    _ => picker.marked,
+                };
+            }
+            Key::Escape | Key::CtrlC => break BTreeSet::new(),
+            Key::ArrowUp => picker.cursor = picker.cursor.saturating_sub(1),
+            Key::ArrowDown => picker.cursor += 1,
+            Key::PageUp => picker.cursor = picker.cursor.saturating_sub(height as usize),
+            Key::PageDown => picker.cursor += height as usize,
+            Key::Tab => {
+                if let Some(i) = picker.matches.get(picker.cursor) {
+                    if !picker.marked.remove(i) {
+                        picker.marked.insert(*i);
+                    }
+                    picker.cursor += 1;
+                }
+            }
+            Key::Backspace => {
+                picker.query.pop();
+                picker.search(&lines);
+            }
+            Key::Char(c) if !c.is_control() => {
+                picker.query.push(c);
+                picker.search(&lines);
+            }
+            _ => {}
+        }
+        picker.cursor = picker.cursor.min(picker.matches.len().saturating_sub(1));
+    };
+    term.clear_last_lines(drawn)?;
+    term.show_cursor()?;
+
+    Ok(records
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| picked.contains(i))
+        .map(|(_, r)| r)
+        .collect())
+}
+
+/// State of [`pick`]
+struct Picker {
+    query: String,
+    /// Indexes of the lines matching the query
+    matches: Vec<usize>,
+    /// Position of the highlighted line in `matches`
+    cursor: usize,
+    marked: BTreeSet<usize>,
+}
+
+impl Picker {
+    fn search(&mut self, lines: &[String]) {
+        self.matches = (0..lines.len())
+            .filter(|i| fuzzy_match(&self.query, &lines[*i]))
+            .collect();
+        self.cursor = 0;
+    }
+
+    /// Prompt, the `height` matching lines around the cursor and the help line
+    fn render(&self, lines: &[String], height: usize, width: u16) -> String {
+        let width = width as usize;
+        let mut screen = format!(
+            "{} {}\n",
+            "Search:".bold(),
+            pad_str(
+                &self.query,
+                width.saturating_sub(8),
+                Alignment::Left,
+                Some("…")
+            )
+        );
+        let first = self.cursor.saturating_sub(height - 1);
+        for (pos, i) in self.matches.iter().enumerate().skip(first).take(height) {
+            let mark = if self.marked.contains(i) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            let line = format!("{} {}", mark, lines[*i]);
+            let line = pad_str(&line, width.saturating_sub(2), Alignment::Left, Some("…"));
+            if pos == self.cursor {
+                screen.push_str(&format!("{} {}\n", ">".cyan().bold(), line.reverse()));
+            } else {
+                screen.push_str(&format!("  {}\n", line));
+            }
+        }
+        let status = format!(
+            "{}/{} samples, {} marked: {}",
+            self.matches.len(),
+            lines.len(),
+            self.marked.len(),
+            HELP
+        );
+        let status = pad_str(&status, width.saturating_sub(1), Alignment::Left, Some("…"));
+        screen.push_str(&format!("{}\n", status.dimmed()));
+        screen
+    }
+}
+
+/// Whether the characters of every word of `query` appear in `text` in the same order, ignoring case
+///
+/// ```
+/// use onekp::picker::fuzzy_match;
+///
+/// assert!(fuzzy_match("mrch plym", "URDJ  Liverworts  Marchantia polymorpha  thallus"));
+/// assert!(fuzzy_match("", "URDJ"));
+/// assert!(!fuzzy_match("hcram", "Marchantia polymorpha"));
+/// ```
+pub fn fuzzy_match(query: &str, text: &str) -> bool {
+    let text = text.to_lowercase();
+    query.split_whitespace().all(|word| {
+        let mut chars = text.chars();
+        word.to_lowercase().chars().all(|c| chars.any(|t| t == c))
+    })
+}
@@ -0,0 +1,204 @@
+use std::{
+    io::{stderr, IsTerminal},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::report::SampleReport;
+
+/// Progress and results of a download, see [`fetch_stream`](crate::fetch::fetch_stream)
+#[derive(Debug, Clone)]
+pub enum DownloadEvent {
+    /// A file transfer started with `position` bytes already on disk
+    FileStarted {
+        name: String,
+        position: u64,
+        len: Option<u64>,
+    },
+    /// Bytes of a file were received, `position` in total
+    FileProgress { name: String, position: u64 },
+    /// A file transfer completed
+    FileFinished { name: String, position: u64 },
+    /// Warnings and notices otherwise printed on the terminal
+    Message(String),
+    /// Every file of a sample was handled
+    SampleFinished(SampleReport),
+}
+
+/// Progress reporting for `fetch`.
+/// Draws progress bars when stderr is a terminal, otherwise falls back to plain log lines.
+/// With [`Progress::with_events`], everything is sent as [`DownloadEvent`]s instead.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    bars: Option<(MultiProgress, ProgressBar)>,
+    events: Option<UnboundedSender<DownloadEvent>>,
+    total_samples: u64,
+    done_samples: Arc<AtomicU64>,
+}
+
+impl Progress {
+    pub fn new(total_samples: u64) -> Self {
+        let bars = if stderr().is_terminal() {
+            let multi = MultiProgress::new();
+            let overall = multi.add(ProgressBar::new(total_samples));
+            overall.set_style(
+                ProgressStyle::with_template("{prefix:>12.bold} [{bar:40}] {pos}/{len} samples")
+                    .expect("valid template")
+                    .progress_chars("=> "),
+            );
+            overall.set_prefix("Total");
+            Some((multi, overall))
+        } else {
+            None
+        };
+
+        Self {
+            bars,
+            events: None,
+            total_samples,
+            done_samples: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn with_events(events: UnboundedSender<DownloadEvent>) -> Self {
+        Self {
+            bars: None,
+            events: Some(events),
+            total_samples: 0,
+            done_samples: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Send `event` when reporting events. The receiver may be gone, which is not an error.
+    pub fn send(&self, event: DownloadEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    /// Print a message without breaking the progress bars.
+    pub fn println(&self, msg: &str) {
+        if self.events.is_some() {
+            return self.send(DownloadEvent::Message(msg.to_owned()));
+        }
+        match &self.bars {
+            Some((multi, _)) => multi.suspend(|| eprintln!("{}", msg)),
+            None => eprintln!("{}", msg),
+        }
+    }
+
+    /// Mark one sample as completed.
+    pub fn sample_done(&self) {
+        if self.events.is_some() {
+            return;
+        }
+        let done = self.done_samples.fetch_add(1, Ordering::Relaxed) + 1;
+        match &self.bars {
+            Some((_, overall)) => overall.inc(1),
+            None => eprintln!("Completed {}/{} samples", done, self.total_samples),
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some((_, overall)) = &self.bars {
+            overall.finish();
+        }
+    }
+
+    /// Start tracking a file. `position` is the number of bytes already on disk.
+    pub fn file(&self, name: &str, position: u64, len: Option<u64>) -> FileProgress {
+        if self.events.is_some() {
+            self.send(DownloadEvent::FileStarted {
+                name: name.to_owned(),
+                position,
+                len,
+            });
+            return FileProgress {
+                bar: None,
+                events: self.clone(),
+                name: name.to_owned(),
+                position,
+            };
+        }
+        let bar = match &self.bars {
+            Some((multi, overall)) => {
+                let bar = multi.insert_before(overall, ProgressBar::new(len.unwrap_or(0)));
+                let template = if len.is_some() {
+                    "{msg:40!} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} {eta}"
+                } else {
+                    "{msg:40!} {spinner} {bytes} {bytes_per_sec}"
+                };
+                bar.set_style(
+                    ProgressStyle::with_template(template)
+                        .expect("valid template")
+                        .progress_chars("=> "),
+                );
+                bar.set_message(name.to_owned());
+                bar.set_position(position);
+                Some(bar)
+            }
+            None => {
+                match len {
+                    Some(len) => eprintln!("Downloading {} ({} bytes)", name, len),
+                    None => eprintln!("Downloading {}", name),
+                }
+                None
+            }
+        };
+
+        FileProgress {
+            bar,
+            events: self.clone(),
+            name: name.to_owned(),
+            position,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FileProgress {
+    bar: Option<ProgressBar>,
+    events: Progress,
+    name: String,
+    position: u64,
+}
+
+impl FileProgress {
+    pub fn inc(&mut self, delta: u64) {
+        self.position += delta;
+        if let Some(bar) = &self.bar {
+            bar.inc(delta);
+        }
+        self.events.send(DownloadEvent::FileProgress {
+            name: self.name.clone(),
+            position: self.position,
+        });
+    }
+
+    pub fn finish(mut self) {
+        if self.events.events.is_some() {
+            return self.events.send(DownloadEvent::FileFinished {
+                name: self.name.clone(),
+                position: self.position,
+            });
+        }
+        match self.bar.take() {
+            Some(bar) => bar.finish_and_clear(),
+            None => eprintln!("Downloaded {} ({} bytes)", self.name, self.position),
+        }
+    }
+}
+
+impl Drop for FileProgress {
+    fn drop(&mut self) {
+        // Remove bars of failed downloads
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
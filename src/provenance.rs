@@ -0,0 +1,52 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::Serialize;
+
+use crate::{
+    error::{OneKpError, Result},
+    write_atomic, OneKpRecord,
+};
+
+/// Where a downloaded file came from, written next to it as `<file>.meta.json`
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance<'a> {
+    pub onekp_version: &'static str,
+    pub url: &'a str,
+    /// Unix time in seconds
+    pub downloaded_at: u64,
+    pub bytes: u64,
+    pub md5: &'a str,
+    pub sample: &'a OneKpRecord,
+}
+
+impl<'a> Provenance<'a> {
+    pub fn new(url: &'a str, path: &Path, md5: &'a str, sample: &'a OneKpRecord) -> Result<Self> {
+        Ok(Self {
+            onekp_version: env!("CARGO_PKG_VERSION"),
+            url,
+            downloaded_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_err(|err| OneKpError::Other(err.into()))?
+                .as_secs(),
+            bytes: path.metadata()?.len(),
+            md5,
+            sample,
+        })
+    }
+
+    /// Write the sidecar of the downloaded file `path`
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        write_atomic(&sidecar_path(path), &json)
+    }
+}
+
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".meta.json");
+    PathBuf::from(sidecar)
+}
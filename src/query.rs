@@ -0,0 +1,236 @@
+use std::{fmt, str::FromStr};
+
+use clap::ValueEnum;
+use regex::Regex;
+
+use crate::{OneKpKey, OneKpRecord};
+
+/// Boolean expression over the metadata columns, e.g.
+/// `clade == "Mosses" && tissue_type != "root" && species ~ "Sphagnum"`.
+///
+/// - `==` and `!=` compare the whole value
+/// - `~` and `!~` search the value with a regular expression
+/// - `&&`, `||`, `!` and parentheses combine comparisons, `&&` binding tighter than `||`
+///
+/// Values are quoted with `"` or `'`, or bare words without spaces.
+///
+/// ```
+/// use onekp::query::Query;
+///
+/// let query: Query = r#"clade == "Mosses" && !(species ~ '^Sphagnum')"#.parse().unwrap();
+/// assert!("clade = Mosses".parse::<Query>().is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query(Expr);
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Eq(OneKpKey, String),
+    Ne(OneKpKey, String),
+    Match(OneKpKey, Regex),
+    NotMatch(OneKpKey, Regex),
+}
+
+impl Query {
+    pub fn matches(&self, rec: &OneKpRecord) -> bool {
+        self.0.eval(rec)
+    }
+}
+
+impl Expr {
+    fn eval(&self, rec: &OneKpRecord) -> bool {
+        match self {
+            Self::And(lhs, rhs) => lhs.eval(rec) && rhs.eval(rec),
+            Self::Or(lhs, rhs) => lhs.eval(rec) || rhs.eval(rec),
+            Self::Not(expr) => !expr.eval(rec),
+            Self::Eq(key, value) => rec.get(*key) == value,
+            Self::Ne(key, value) => rec.get(*key) != value,
+            Self::Match(key, regex) => regex.is_match(rec.get(*key)),
+            Self::NotMatch(key, regex) => !regex.is_match(rec.get(*key)),
+        }
+    }
+}
+
+/// Syntax error of a query, with the byte offset it was found at
+#[derive(Debug)]
+pub struct QueryError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { src: s, pos: 0 };
+        let expr = parser.or()?;
+        parser.skip_spaces();
+        if parser.pos < s.len() {
+            return Err(parser.error("unexpected input"));
+        }
+        Ok(Self(expr))
+    }
+}
+
+/// Recursive descent parser of [`Query`]
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> QueryError {
+        QueryError {
+            position: self.pos,
+            message: message.to_owned(),
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_spaces(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.pos = self.src.len() - trimmed.len();
+    }
+
+    /// Consume `token` if the input continues with it
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_spaces();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.unary()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        if self.eat("(") {
+            let expr = self.or()?;
+            if !self.eat(")") {
+                return Err(self.error("expected )"));
+            }
+            return Ok(expr);
+        }
+        // `!=` and `!~` only follow a column name
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, QueryError> {
+        self.skip_spaces();
+        let start = self.pos;
+        let name = self.word();
+        if name.is_empty() {
+            return Err(self.error("expected a column name"));
+        }
+        let key = OneKpKey::from_str(&name.replace('_', "-"), true).map_err(|_| QueryError {
+            position: start,
+            message: format!("unknown column {:?}", name),
+        })?;
+
+        let op = ["==", "!=", "!~", "~"]
+            .into_iter()
+            .find(|op| self.eat(op))
+            .ok_or_else(|| self.error("expected ==, !=, ~ or !~"))?;
+        self.skip_spaces();
+        let value_pos = self.pos;
+        let value = self.value()?;
+        let regex = || {
+            Regex::new(&value).map_err(|err| QueryError {
+                position: value_pos,
+                message: err.to_string(),
+            })
+        };
+        Ok(match op {
+            "==" => Expr::Eq(key, value),
+            "!=" => Expr::Ne(key, value),
+            "~" => Expr::Match(key, regex()?),
+            _ => Expr::NotMatch(key, regex()?),
+        })
+    }
+
+    /// Bare word: column name or unquoted value
+    fn word(&mut self) -> String {
+        let len = self
+            .rest()
+            .find(|c: char| c.is_whitespace() || "()=!~&|\"'".contains(c))
+            .unwrap_or(self.rest().len());
+        let word = self.rest()[..len].to_owned();
+        self.pos += len;
+        word
+    }
+
+    fn value(&mut self) -> Result<String, QueryError> {
+        self.skip_spaces();
+        let Some(quote) = self
+            .rest()
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+        else {
+            let word = self.word();
+            if word.is_empty() {
+                return Err(self.error("expected a value"));
+            }
+            return Ok(word);
+        };
+
+        let start = self.pos;
+        let mut value = String::new();
+        let mut chars = self.rest().char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        // keep regex escapes such as \d intact
+                        if escaped != quote && escaped != '\\' {
+                            value.push('\\');
+                        }
+                        value.push(escaped);
+                    }
+                }
+                c if c == quote => {
+                    self.pos += i + c.len_utf8();
+                    return Ok(value);
+                }
+                c => value.push(c),
+            }
+        }
+        Err(QueryError {
+            position: start,
+            message: "unterminated string".to_owned(),
+        })
+    }
+}
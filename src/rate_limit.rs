@@ -0,0 +1,114 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::{sleep_until, Instant};
+
+/// Spaces out requests to the same host by a fixed interval.
+///
+/// Clones share one schedule, so every task holding a clone is throttled together.
+/// Waiting never blocks the runtime: a task reserves its slot under the lock
+/// and then sleeps asynchronously until the slot comes.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    interval: Duration,
+    // next time a request is allowed for each host
+    next_slots: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next_slots: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Wait until a request to `host` is allowed.
+    pub async fn acquire(&self, host: &str) {
+        let now = Instant::now();
+        let slot = {
+            let mut next_slots = self.next_slots.lock().unwrap();
+            let slot = next_slots.get(host).map_or(now, |next| (*next).max(now));
+            next_slots.insert(host.to_owned(), slot + self.interval);
+            slot
+        };
+
+        if slot > now {
+            sleep_until(slot).await;
+        }
+    }
+
+    /// Hold back every request to `host` for at least `wait`.
+    pub fn delay(&self, host: &str, wait: Duration) {
+        let until = Instant::now() + wait;
+        let mut next_slots = self.next_slots.lock().unwrap();
+        let next = next_slots.entry(host.to_owned()).or_insert(until);
+        *next = (*next).max(until);
+    }
+}
+
+/// Caps the total download speed of every task holding a clone.
+#[derive(Debug, Clone)]
+pub struct BandwidthLimiter {
+    bytes_per_sec: u64,
+    // time when the bytes consumed so far are paid off
+    paid_until: Arc<Mutex<Instant>>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            paid_until: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Account for `bytes` received and wait until the rate is back under the limit.
+    pub async fn consume(&self, bytes: u64) {
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        let now = Instant::now();
+        let until = {
+            let mut paid_until = self.paid_until.lock().unwrap();
+            // idle time does not accumulate into a burst
+            *paid_until = (*paid_until).max(now) + cost;
+            *paid_until
+        };
+
+        if until > now {
+            sleep_until(until).await;
+        }
+    }
+}
+
+/// Parse a rate like `500K`, `5M` or `1G` (bytes per second, 1024 based).
+pub fn parse_byte_rate(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let number: f64 = number.parse().map_err(|_| format!("invalid rate: {}", s))?;
+    let scale = match unit
+        .to_ascii_uppercase()
+        .trim_end_matches("/S")
+        .trim_end_matches('B')
+    {
+        "" => 1.0,
+        "K" => 1024.0,
+        "M" => 1024.0 * 1024.0,
+        "G" => 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("unknown rate unit: {}", unit)),
+    };
+    let rate = (number * scale) as u64;
+    if rate == 0 {
+        return Err("rate must be greater than 0".to_owned());
+    }
+    Ok(rate)
+}
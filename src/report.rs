@@ -0,0 +1,114 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Result, metadata_version::MetadataVersion, FetchArgs};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Downloaded,
+    Skipped,
+    Failed,
+    ChecksumMismatch,
+    /// The file failed `--validate`
+    Corrupt,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileReport {
+    pub url: String,
+    pub path: PathBuf,
+    pub status: FileStatus,
+    /// Size of the file announced by the server
+    #[serde(default)]
+    pub content_length: Option<u64>,
+    /// Number of FASTA records, with `--validate-fasta`
+    #[serde(default)]
+    pub records: Option<u64>,
+    /// Bytes received in this run
+    pub bytes: u64,
+    pub duration_secs: f64,
+    pub retries: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleStatus {
+    Success,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SampleReport {
+    pub id: String,
+    pub species: String,
+    pub status: SampleStatus,
+    pub duration_secs: f64,
+    pub error: Option<String>,
+    pub files: Vec<FileReport>,
+}
+
+impl SampleReport {
+    pub fn has_checksum_mismatch(&self) -> bool {
+        self.files
+            .iter()
+            .any(|f| f.status == FileStatus::ChecksumMismatch)
+    }
+}
+
+/// Machine-readable result of a `fetch` run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchReport {
+    pub onekp_version: String,
+    /// Unix time in seconds
+    pub started_at: u64,
+    pub duration_secs: f64,
+    /// The run was stopped with Ctrl-C before every sample was fetched
+    #[serde(default)]
+    pub interrupted: bool,
+    pub options: FetchArgs,
+    /// Sample table the samples were selected from
+    #[serde(default)]
+    pub metadata: Option<MetadataVersion>,
+    pub samples: Vec<SampleReport>,
+}
+
+impl FetchReport {
+    pub fn read(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut bw = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut bw, self)?;
+        bw.write_all(b"\n")?;
+        bw.flush()?;
+        Ok(())
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.samples
+            .iter()
+            .any(|s| s.status == SampleStatus::Failed)
+    }
+
+    /// Replace the samples fetched again in `retried`, keeping the others
+    pub fn merge(&mut self, retried: FetchReport) {
+        for sample in retried.samples {
+            match self.samples.iter_mut().find(|s| s.id == sample.id) {
+                Some(old) => *old = sample,
+                None => self.samples.push(sample),
+            }
+        }
+        self.duration_secs += retried.duration_secs;
+        self.interrupted = retried.interrupted;
+        self.options.rootdir = retried.options.rootdir;
+    }
+}
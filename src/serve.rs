@@ -0,0 +1,148 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    checksum::Checksums, error::OneKpError, mirror::Mirrors, OneKp, OneKpKey, OneKpRecord, Product,
+    SequenceType,
+};
+
+/// Data served by `onekp serve`
+#[derive(Debug)]
+struct AppState {
+    onekp: OneKp,
+    checksums: Option<Checksums>,
+    mirrors: Mirrors,
+}
+
+/// A downloadable file of a sample
+#[derive(Debug, Serialize)]
+struct SampleFile {
+    name: &'static str,
+    /// Path relative to the dataset root, as listed in the checksum file
+    path: String,
+    /// Primary URL followed by the mirrors
+    urls: Vec<String>,
+    md5: Option<String>,
+}
+
+/// Serve the metadata as JSON on `addr` until the process is stopped:
+///
+/// - `GET /samples`, optionally filtered by `id`, `clade`, `order`, `family`, `species`
+///   and `tissue_type` query parameters with comma-separated values
+/// - `GET /samples/{id}`
+/// - `GET /samples/{id}/files`, 404 for samples without a directory on the server
+pub async fn serve(
+    onekp: OneKp,
+    checksums: Option<Checksums>,
+    mirrors: Mirrors,
+    addr: SocketAddr,
+) -> Result<(), OneKpError> {
+    let state = Arc::new(AppState {
+        onekp,
+        checksums,
+        mirrors,
+    });
+    let app = Router::new()
+        .route("/samples", get(samples))
+        .route("/samples/:id", get(sample))
+        .route("/samples/:id/files", get(sample_files))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    eprintln!(
+        "Serving the 1KP metadata on http://{}",
+        listener.local_addr()?
+    );
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+fn key_of(param: &str) -> Option<OneKpKey> {
+    Some(match param {
+        "id" => OneKpKey::Id,
+        "clade" => OneKpKey::Clade,
+        "order" => OneKpKey::Order,
+        "family" => OneKpKey::Family,
+        "species" => OneKpKey::Species,
+        "tissue_type" => OneKpKey::TissueType,
+        "taxid" => OneKpKey::Taxid,
+        "phylum" => OneKpKey::Phylum,
+        "class" => OneKpKey::Class,
+        "genus" => OneKpKey::Genus,
+        _ => return None,
+    })
+}
+
+async fn samples(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<OneKpRecord>>, (StatusCode, String)> {
+    let mut records = state.onekp.records().to_vec();
+    for (param, values) in params.iter() {
+        let key = key_of(param).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Unknown filter: {}", param),
+            )
+        })?;
+        let values: Vec<String> = values.split(',').map(|v| v.to_owned()).collect();
+        let selected = state.onekp.filter(key, &values);
+        records.retain(|r| selected.iter().any(|s| s.id == r.id));
+    }
+    Ok(Json(records))
+}
+
+fn find<'a>(state: &'a AppState, id: &str) -> Result<&'a OneKpRecord, (StatusCode, String)> {
+    state
+        .onekp
+        .records()
+        .iter()
+        .find(|r| r.id == id)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("No sample {}", id)))
+}
+
+async fn sample(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<OneKpRecord>, (StatusCode, String)> {
+    Ok(Json(find(&state, &id)?.clone()))
+}
+
+async fn sample_files(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<SampleFile>>, (StatusCode, String)> {
+    let rec = find(&state, &id)?;
+    if !rec.is_available() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            OneKpError::Unavailable(id).to_string(),
+        ));
+    }
+    let files = SequenceType::All
+        .with_products(Product::value_variants())
+        .into_iter()
+        .map(|name| {
+            let path = rec.to_gigadb_path(name);
+            SampleFile {
+                name,
+                urls: state.mirrors.urls(&path),
+                md5: state
+                    .checksums
+                    .as_ref()
+                    .and_then(|c| c.get(&path))
+                    .map(|h| h.to_owned()),
+                path,
+            }
+        })
+        .collect();
+    Ok(Json(files))
+}
@@ -0,0 +1,63 @@
+//! Sample table of 1KP built into the binary, for when the server is down.
+//!
+//! It is read from `snapshot/` of the crate: the gzipped sample list and assemblies listing, and
+//! `DATE`, the day they were downloaded on. The build fails without the directory unless
+//! `ONEKP_NO_SNAPSHOT` is set, which builds a binary without the fallback. The release workflow
+//! refreshes it before building, and a checkout gets it the same way:
+//!
+//! ```bash
+//! mkdir -p snapshot
+//! curl -fsS https://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/Sample-List-with-Taxonomy.tsv.csv | gzip -n > snapshot/Sample-List-with-Taxonomy.tsv.gz
+//! curl -fsS https://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/assemblies/ | gzip -n > snapshot/assemblies.html.gz
+//! date -u +%F > snapshot/DATE
+//! ```
+
+use crate::{error::OneKpError, source::DataSource};
+
+#[cfg(snapshot)]
+mod files {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use crate::error::OneKpError;
+
+    pub const DATE: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/snapshot/DATE"));
+    pub const SAMPLE_LIST: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/snapshot/Sample-List-with-Taxonomy.tsv.gz"
+    ));
+    pub const ASSEMBLIES: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/snapshot/assemblies.html.gz"
+    ));
+
+    pub fn gunzip(bytes: &[u8]) -> Result<String, OneKpError> {
+        let mut text = String::new();
+        GzDecoder::new(bytes)
+            .read_to_string(&mut text)
+            .map_err(|err| OneKpError::Parse(format!("embedded snapshot is broken: {}", err)))?;
+        Ok(text)
+    }
+}
+
+/// Day the embedded sample table was downloaded on, `None` if built with `ONEKP_NO_SNAPSHOT`
+pub fn date() -> Option<&'static str> {
+    #[cfg(snapshot)]
+    return Some(files::DATE.trim());
+    #[cfg(not(snapshot))]
+    None
+}
+
+/// The embedded sample table and listing of `source`, if it is the one built in
+pub fn embedded(source: &dyn DataSource) -> Result<Option<(String, String)>, OneKpError> {
+    #[cfg(snapshot)]
+    if source.metadata_url() == crate::OneKpSource.metadata_url() {
+        return Ok(Some((
+            files::gunzip(files::SAMPLE_LIST)?,
+            files::gunzip(files::ASSEMBLIES)?,
+        )));
+    }
+    let _ = source;
+    Ok(None)
+}
@@ -0,0 +1,117 @@
+use std::fmt::Debug;
+
+use crate::{
+    error::OneKpError,
+    mirror,
+    strict::{check_sample_list, Issue},
+    OneKp, OneKpRecord, SequenceType,
+};
+
+/// Where a dataset is published and how its files are laid out.
+///
+/// [`OneKp::load_from`] reads the sample table of a source, and each record keeps its source,
+/// so [`OneKpRecord::to_gigadb_path`] and [`Mirrors::for_source`](crate::Mirrors::for_source)
+/// work the same for every dataset.
+pub trait DataSource: Debug + Send + Sync {
+    /// Short name of the dataset, e.g. `1kp`
+    fn name(&self) -> &str;
+    /// Directory the file paths are relative to, ending with `/`
+    fn base_url(&self) -> &str;
+    /// Copies of [`DataSource::base_url`] tried when it fails
+    fn mirrors(&self) -> &[&str] {
+        &[]
+    }
+    /// The base directory served over plain FTP, if any
+    fn ftp_base_url(&self) -> Option<&str> {
+        None
+    }
+    /// Sample table, read by [`DataSource::parse`]
+    fn metadata_url(&self) -> &str;
+    /// Page needed besides the sample table to locate the files, if any
+    fn listing_url(&self) -> Option<&str> {
+        None
+    }
+    /// `md5sum`-style list of the files, see [`Checksums::parse`](crate::checksum::Checksums::parse)
+    fn checksum_url(&self) -> Option<&str> {
+        None
+    }
+    /// Problems of the sample table that [`DataSource::parse`] works around, for `--strict`
+    fn check(&self, _metadata: &str, _listing: Option<&str>) -> Vec<Issue> {
+        vec![]
+    }
+    /// Samples of the sample table, with the listing when [`DataSource::listing_url`] is set
+    fn parse(&self, metadata: &str, listing: Option<&str>) -> Result<Vec<OneKpRecord>, OneKpError>;
+    /// Path of the file `filename` of `record` relative to [`DataSource::base_url`]
+    fn file_path(&self, record: &OneKpRecord, filename: &str) -> String;
+    /// Directory of `record` relative to [`DataSource::base_url`], ending with `/`,
+    /// when the server lists its files
+    fn sample_dir(&self, _record: &OneKpRecord) -> Option<String> {
+        None
+    }
+}
+
+/// The 1KP assemblies on GigaDB
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OneKpSource;
+
+impl DataSource for OneKpSource {
+    fn name(&self) -> &str {
+        "1kp"
+    }
+
+    fn base_url(&self) -> &str {
+        mirror::PRIMARY
+    }
+
+    fn mirrors(&self) -> &[&str] {
+        mirror::BUILTIN_MIRRORS
+    }
+
+    fn ftp_base_url(&self) -> Option<&str> {
+        Some(mirror::PRIMARY_FTP)
+    }
+
+    fn metadata_url(&self) -> &str {
+        SAMPLE_LIST_URL
+    }
+
+    fn listing_url(&self) -> Option<&str> {
+        Some(ASSEMBLIES_URL)
+    }
+
+    fn checksum_url(&self) -> Option<&str> {
+        Some(crate::CHECKSUM_URL)
+    }
+
+    fn check(&self, metadata: &str, listing: Option<&str>) -> Vec<Issue> {
+        check_sample_list(metadata, listing)
+    }
+
+    fn parse(&self, metadata: &str, listing: Option<&str>) -> Result<Vec<OneKpRecord>, OneKpError> {
+        let listing = listing
+            .ok_or_else(|| OneKpError::Parse("the assemblies listing is missing".to_owned()))?;
+        Ok(OneKp::parse(metadata, listing)?.records().to_vec())
+    }
+
+    fn file_path(&self, record: &OneKpRecord, filename: &str) -> String {
+        // only the nucleotide and protein files are translations
+        let translated = SequenceType::Both.to_filenames().contains(&filename);
+        format!(
+            "assemblies/{}/{}-{}{}",
+            record.prefix,
+            record.id,
+            if translated { "translated-" } else { "" },
+            filename
+        )
+    }
+
+    fn sample_dir(&self, record: &OneKpRecord) -> Option<String> {
+        Some(format!("assemblies/{}/", record.prefix))
+    }
+}
+
+/// Sample list with taxonomy
+const SAMPLE_LIST_URL: &str = "https://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/Sample-List-with-Taxonomy.tsv.csv";
+/// Directory listing of the assemblies, the only place the directory name of a sample appears
+const ASSEMBLIES_URL: &str =
+    "https://ftp.cngb.org/pub/gigadb/pub/10.5524/100001_101000/100627/assemblies/";
@@ -0,0 +1,576 @@
+use std::{cmp::Ordering, collections::BTreeMap, str::FromStr};
+
+use clap::ValueEnum;
+use regex::Regex;
+
+use crate::{
+    output::{column_name, compare, delimited_row},
+    query::QueryError,
+    OneKpKey, OneKpRecord,
+};
+
+/// SQL `SELECT` over the sample table, named `samples`, with a column per [`OneKpKey`]
+/// (`tissue_type` with an underscore).
+///
+/// The statement is a subset of SQLite's:
+///
+/// - `SELECT *`, columns, `count(*)`, `count(column)` and `count(DISTINCT column)`, each with an optional `AS name`
+/// - `WHERE` with `=`, `!=` / `<>`, `LIKE` (`%` and `_`, ignoring case), `IN (...)`, `NOT`, `AND`, `OR` and parentheses
+/// - `GROUP BY`, `ORDER BY` an output column by name or position with `ASC` / `DESC`, and `LIMIT`
+///
+/// Strings are quoted with `'`. A column outside `GROUP BY` takes the value of the first sample of the group.
+///
+/// ```
+/// use onekp::{sql::Select, OneKpRecord};
+///
+/// let rec = |id: &str, family: &str, species: &str| OneKpRecord {
+///     id: id.to_owned(),
+///     clade: "Mosses".to_owned(),
+///     order: "Sphagnales".to_owned(),
+///     family: family.to_owned(),
+///     species: species.to_owned(),
+///     tissue_type: "shoot".to_owned(),
+///     prefix: id.to_owned(),
+///     source: None,
+///     lineage: None,
+/// };
+/// let records = [
+///     rec("AAAA", "Sphagnaceae", "Sphagnum palustre"),
+///     rec("BBBB", "Sphagnaceae", "Sphagnum recurvum"),
+///     rec("CCCC", "Flatbergiaceae", "Flatbergium sericeum"),
+/// ];
+/// let select: Select = "SELECT family, count(*) AS n FROM samples WHERE species LIKE '%um%' GROUP BY family ORDER BY n DESC"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(
+///     select.run(&records).to_tsv(),
+///     "family\tn\nSphagnaceae\t2\nFlatbergiaceae\t1\n"
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Select {
+    items: Vec<Item>,
+    filter: Option<Cond>,
+    group_by: Vec<OneKpKey>,
+    /// Output column and whether the order is descending
+    order_by: Vec<(usize, bool)>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Column(OneKpKey),
+    /// `count(*)` without a column
+    Count {
+        column: Option<OneKpKey>,
+        distinct: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Item {
+    expr: Expr,
+    label: String,
+}
+
+#[derive(Debug, Clone)]
+enum Cond {
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
+    Not(Box<Cond>),
+    Eq(OneKpKey, String),
+    Like(OneKpKey, Regex),
+    In(OneKpKey, Vec<String>),
+}
+
+impl Cond {
+    fn eval(&self, rec: &OneKpRecord) -> bool {
+        match self {
+            Self::And(lhs, rhs) => lhs.eval(rec) && rhs.eval(rec),
+            Self::Or(lhs, rhs) => lhs.eval(rec) || rhs.eval(rec),
+            Self::Not(cond) => !cond.eval(rec),
+            Self::Eq(key, value) => rec.get(*key) == value,
+            Self::Like(key, pattern) => pattern.is_match(rec.get(*key)),
+            Self::In(key, values) => values.iter().any(|v| rec.get(*key) == v),
+        }
+    }
+}
+
+/// Result of a [`Select`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn to_tsv(&self) -> String {
+        let mut tsv = delimited_row(self.header.iter().map(|h| h.as_str()), '\t') + "\n";
+        for row in self.rows.iter() {
+            tsv.push_str(&delimited_row(row.iter().map(|v| v.as_str()), '\t'));
+            tsv.push('\n');
+        }
+        tsv
+    }
+}
+
+impl Select {
+    pub fn run(&self, records: &[OneKpRecord]) -> Table {
+        let selected: Vec<&OneKpRecord> = records
+            .iter()
+            .filter(|r| self.filter.as_ref().is_none_or(|c| c.eval(r)))
+            .collect();
+
+        let aggregate = self
+            .items
+            .iter()
+            .any(|i| matches!(i.expr, Expr::Count { .. }));
+        let groups: Vec<Vec<&OneKpRecord>> = if !self.group_by.is_empty() {
+            let mut groups: BTreeMap<Vec<&str>, Vec<&OneKpRecord>> = BTreeMap::new();
+            for rec in selected {
+                let key = self.group_by.iter().map(|k| rec.get(*k)).collect();
+                groups.entry(key).or_default().push(rec);
+            }
+            groups.into_values().collect()
+        } else if aggregate {
+            vec![selected]
+        } else {
+            selected.into_iter().map(|r| vec![r]).collect()
+        };
+
+        let mut rows: Vec<Vec<String>> = groups
+            .iter()
+            .map(|group| self.items.iter().map(|i| value(&i.expr, group)).collect())
+            .collect();
+        rows.sort_by(|a, b| {
+            self.order_by
+                .iter()
+                .map(|(i, desc)| {
+                    let ordering = compare(&a[*i], &b[*i]);
+                    if *desc {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        rows.truncate(self.limit.unwrap_or(usize::MAX));
+
+        Table {
+            header: self.items.iter().map(|i| i.label.clone()).collect(),
+            rows,
+        }
+    }
+}
+
+fn value(expr: &Expr, group: &[&OneKpRecord]) -> String {
+    match expr {
+        Expr::Column(key) => group
+            .first()
+            .map(|r| r.get(*key).to_owned())
+            .unwrap_or_default(),
+        Expr::Count { column: None, .. } => group.len().to_string(),
+        Expr::Count {
+            column: Some(key),
+            distinct,
+        } => {
+            let mut values: Vec<&str> = group.iter().map(|r| r.get(*key)).collect();
+            if *distinct {
+                values.sort_unstable();
+                values.dedup();
+            }
+            values.len().to_string()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Number(usize),
+    Symbol(&'static str),
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let mut tokens = vec![];
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(pos, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '\'' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    // '' is an escaped quote
+                    Some((_, '\'')) if chars.peek().map(|(_, c)| *c) == Some('\'') => {
+                        chars.next();
+                        value.push('\'');
+                    }
+                    Some((_, '\'')) => break,
+                    Some((_, c)) => value.push(c),
+                    None => {
+                        return Err(QueryError {
+                            position: pos,
+                            message: "unterminated string".to_owned(),
+                        })
+                    }
+                }
+            }
+            tokens.push((pos, Token::Str(value)));
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let mut word = String::new();
+            while let Some(&(_, c)) = chars
+                .peek()
+                .filter(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+            {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push((
+                pos,
+                match word.parse() {
+                    Ok(n) => Token::Number(n),
+                    Err(_) => Token::Word(word),
+                },
+            ));
+        } else {
+            let symbol = ["!=", "<>", "=", ",", "(", ")", "*", ";"]
+                .into_iter()
+                .find(|sym| s[pos..].starts_with(sym))
+                .ok_or_else(|| QueryError {
+                    position: pos,
+                    message: format!("unexpected {:?}", c),
+                })?;
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+            tokens.push((pos, Token::Symbol(symbol)));
+        }
+    }
+    Ok(tokens)
+}
+
+impl FromStr for Select {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+            end: s.len(),
+        };
+        parser.select()
+    }
+}
+
+/// Recursive descent parser of [`Select`]
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// Length of the statement, the position of errors at its end
+    end: usize,
+}
+
+impl Parser {
+    fn error(&self, message: impl Into<String>) -> QueryError {
+        QueryError {
+            position: self.tokens.get(self.pos).map_or(self.end, |(p, _)| *p),
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), QueryError> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {}", keyword)))
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), QueryError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {}", symbol)))
+        }
+    }
+
+    fn select(&mut self) -> Result<Select, QueryError> {
+        self.expect_keyword("SELECT")?;
+        let items = self.items()?;
+        self.expect_keyword("FROM")?;
+        if !self.eat_keyword("samples") {
+            return Err(self.error("the only table is samples"));
+        }
+
+        let mut select = Select {
+            items,
+            filter: None,
+            group_by: vec![],
+            order_by: vec![],
+            limit: None,
+        };
+        // the clauses are accepted in any order
+        loop {
+            if self.eat_keyword("WHERE") {
+                select.filter = Some(self.or()?);
+            } else if self.eat_keyword("GROUP") {
+                self.expect_keyword("BY")?;
+                select.group_by = self.list(|p| p.column())?;
+            } else if self.eat_keyword("ORDER") {
+                self.expect_keyword("BY")?;
+                let items = select.items.clone();
+                select.order_by = self.list(|p| p.order_item(&items))?;
+            } else if self.eat_keyword("LIMIT") {
+                match self.peek() {
+                    Some(Token::Number(n)) => {
+                        select.limit = Some(*n);
+                        self.pos += 1;
+                    }
+                    _ => return Err(self.error("expected a number")),
+                }
+            } else {
+                break;
+            }
+        }
+        self.eat_symbol(";");
+        if self.pos < self.tokens.len() {
+            return Err(self.error("unexpected input"));
+        }
+        Ok(select)
+    }
+
+    /// Comma-separated list of what `item` parses
+    fn list<T>(
+        &mut self,
+        item: impl Fn(&mut Self) -> Result<T, QueryError>,
+    ) -> Result<Vec<T>, QueryError> {
+        let mut items = vec![item(self)?];
+        while self.eat_symbol(",") {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn items(&mut self) -> Result<Vec<Item>, QueryError> {
+        if self.eat_symbol("*") {
+            return Ok([
+                OneKpKey::Id,
+                OneKpKey::Clade,
+                OneKpKey::Order,
+                OneKpKey::Family,
+                OneKpKey::Species,
+                OneKpKey::TissueType,
+            ]
+            .into_iter()
+            .map(|key| Item {
+                expr: Expr::Column(key),
+                label: column_name(key),
+            })
+            .collect());
+        }
+        self.list(|p| {
+            let expr = p.expr()?;
+            let label = if p.eat_keyword("AS") {
+                p.name()?
+            } else {
+                label(&expr)
+            };
+            Ok(Item { expr, label })
+        })
+    }
+
+    fn expr(&mut self) -> Result<Expr, QueryError> {
+        if !self.eat_keyword("count") {
+            return Ok(Expr::Column(self.column()?));
+        }
+        self.expect_symbol("(")?;
+        let expr = if self.eat_symbol("*") {
+            Expr::Count {
+                column: None,
+                distinct: false,
+            }
+        } else {
+            let distinct = self.eat_keyword("DISTINCT");
+            Expr::Count {
+                column: Some(self.column()?),
+                distinct,
+            }
+        };
+        self.expect_symbol(")")?;
+        Ok(expr)
+    }
+
+    fn name(&mut self) -> Result<String, QueryError> {
+        match self.peek().cloned() {
+            Some(Token::Word(word)) => {
+                self.pos += 1;
+                Ok(word)
+            }
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    fn column(&mut self) -> Result<OneKpKey, QueryError> {
+        let start = self.pos;
+        let name = self.name()?;
+        OneKpKey::from_str(&name.replace('_', "-"), true).map_err(|_| QueryError {
+            position: self.tokens[start].0,
+            message: format!("unknown column {:?}", name),
+        })
+    }
+
+    /// Output column by 1-based position, label or expression, and the direction
+    fn order_item(&mut self, items: &[Item]) -> Result<(usize, bool), QueryError> {
+        let start = self.pos;
+        let index = match self.peek().cloned() {
+            Some(Token::Number(n)) if (1..=items.len()).contains(&n) => {
+                self.pos += 1;
+                n - 1
+            }
+            Some(Token::Word(word)) if items.iter().any(|i| i.label == word) => {
+                self.pos += 1;
+                items
+                    .iter()
+                    .position(|i| i.label == word)
+                    .unwrap_or_default()
+            }
+            _ => {
+                let expr = self.expr()?;
+                items
+                    .iter()
+                    .position(|i| i.expr == expr)
+                    .ok_or_else(|| QueryError {
+                        position: self.tokens[start].0,
+                        message: "ORDER BY must name a selected column".to_owned(),
+                    })?
+            }
+        };
+        let desc = self.eat_keyword("DESC");
+        if !desc {
+            self.eat_keyword("ASC");
+        }
+        Ok((index, desc))
+    }
+
+    fn or(&mut self) -> Result<Cond, QueryError> {
+        let mut cond = self.and()?;
+        while self.eat_keyword("OR") {
+            cond = Cond::Or(Box::new(cond), Box::new(self.and()?));
+        }
+        Ok(cond)
+    }
+
+    fn and(&mut self) -> Result<Cond, QueryError> {
+        let mut cond = self.unary()?;
+        while self.eat_keyword("AND") {
+            cond = Cond::And(Box::new(cond), Box::new(self.unary()?));
+        }
+        Ok(cond)
+    }
+
+    fn unary(&mut self) -> Result<Cond, QueryError> {
+        if self.eat_keyword("NOT") {
+            return Ok(Cond::Not(Box::new(self.unary()?)));
+        }
+        if self.eat_symbol("(") {
+            let cond = self.or()?;
+            self.expect_symbol(")")?;
+            return Ok(cond);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Cond, QueryError> {
+        let key = self.column()?;
+        if self.eat_symbol("=") {
+            return Ok(Cond::Eq(key, self.string()?));
+        }
+        if self.eat_symbol("!=") || self.eat_symbol("<>") {
+            return Ok(Cond::Not(Box::new(Cond::Eq(key, self.string()?))));
+        }
+        let negated = self.eat_keyword("NOT");
+        let cond = if self.eat_keyword("LIKE") {
+            Cond::Like(key, like(&self.string()?))
+        } else if self.eat_keyword("IN") {
+            self.expect_symbol("(")?;
+            let values = self.list(|p| p.string())?;
+            self.expect_symbol(")")?;
+            Cond::In(key, values)
+        } else {
+            return Err(self.error("expected =, !=, LIKE or IN"));
+        };
+        Ok(if negated {
+            Cond::Not(Box::new(cond))
+        } else {
+            cond
+        })
+    }
+
+    fn string(&mut self) -> Result<String, QueryError> {
+        let value = match self.peek() {
+            Some(Token::Str(s)) => s.clone(),
+            Some(Token::Number(n)) => n.to_string(),
+            _ => return Err(self.error("expected a string in single quotes")),
+        };
+        self.pos += 1;
+        Ok(value)
+    }
+}
+
+/// Regex of a `LIKE` pattern: `%` any text, `_` any character and the rest literal, ignoring case
+fn like(pattern: &str) -> Regex {
+    let mut regex = String::from("(?is)^");
+    for c in pattern.chars() {
+        match c {
+            '%' => regex.push_str(".*"),
+            '_' => regex.push('.'),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).expect("escaped pattern")
+}
+
+/// Column name of an expression without `AS`
+fn label(expr: &Expr) -> String {
+    match expr {
+        Expr::Column(key) => column_name(*key),
+        Expr::Count { column: None, .. } => "count(*)".to_owned(),
+        Expr::Count {
+            column: Some(key),
+            distinct,
+        } => format!(
+            "count({}{})",
+            if *distinct { "DISTINCT " } else { "" },
+            column_name(*key)
+        ),
+    }
+}
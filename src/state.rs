@@ -0,0 +1,135 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    report::{FileStatus, SampleReport},
+    write_atomic,
+};
+
+/// Name of the state file kept in the root directory
+pub const STATE_FILENAME: &str = ".onekp_state.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueStatus {
+    Pending,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileState {
+    pub path: PathBuf,
+    pub status: QueueStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SampleState {
+    pub status: QueueStatus,
+    pub files: Vec<FileState>,
+}
+
+/// Download queue of a root directory, updated after every sample
+/// so that an interrupted `fetch` can be resumed with `--resume`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FetchState {
+    /// Keyed by sample ID
+    pub samples: BTreeMap<String, SampleState>,
+}
+
+impl FetchState {
+    pub fn path(rootdir: &Path) -> PathBuf {
+        rootdir.join(STATE_FILENAME)
+    }
+
+    /// Read the state of `rootdir`, or an empty one if there is none yet
+    pub fn load(rootdir: &Path) -> Result<Self> {
+        let path = Self::path(rootdir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn save(&self, rootdir: &Path) -> Result<()> {
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        write_atomic(&Self::path(rootdir), &json)
+    }
+
+    /// Whether every one of `paths` was already downloaded for the sample
+    pub fn is_done(&self, id: &str, paths: &[PathBuf]) -> bool {
+        self.samples.get(id).is_some_and(|sample| {
+            paths.iter().all(|path| {
+                sample
+                    .files
+                    .iter()
+                    .any(|f| &f.path == path && f.status == QueueStatus::Done)
+            })
+        })
+    }
+
+    pub fn is_file_done(&self, path: &Path) -> bool {
+        self.samples
+            .values()
+            .flat_map(|s| s.files.iter())
+            .any(|f| f.path == path && f.status == QueueStatus::Done)
+    }
+
+    /// Queue a sample, keeping the files already done
+    pub fn enqueue(&mut self, id: &str, paths: Vec<PathBuf>) {
+        let sample = self.samples.entry(id.to_owned()).or_insert(SampleState {
+            status: QueueStatus::Pending,
+            files: vec![],
+        });
+        for path in paths {
+            match sample.files.iter_mut().find(|f| f.path == path) {
+                Some(file) if file.status == QueueStatus::Done => {}
+                Some(file) => file.status = QueueStatus::Pending,
+                None => sample.files.push(FileState {
+                    path,
+                    status: QueueStatus::Pending,
+                }),
+            }
+        }
+        if sample.files.iter().any(|f| f.status != QueueStatus::Done) {
+            sample.status = QueueStatus::Pending;
+        }
+    }
+
+    /// Record the result of a fetched sample
+    pub fn update(&mut self, report: &SampleReport) {
+        let Some(sample) = self.samples.get_mut(&report.id) else {
+            return;
+        };
+        for file in report.files.iter() {
+            let status = match file.status {
+                FileStatus::Downloaded | FileStatus::Skipped => QueueStatus::Done,
+                FileStatus::Failed | FileStatus::ChecksumMismatch | FileStatus::Corrupt => {
+                    QueueStatus::Failed
+                }
+            };
+            match sample.files.iter_mut().find(|f| f.path == file.path) {
+                Some(state) => state.status = status,
+                None => sample.files.push(FileState {
+                    path: file.path.clone(),
+                    status,
+                }),
+            }
+        }
+        sample.status = if sample.files.iter().all(|f| f.status == QueueStatus::Done) {
+            QueueStatus::Done
+        } else if sample.files.iter().any(|f| f.status == QueueStatus::Failed) {
+            QueueStatus::Failed
+        } else {
+            QueueStatus::Pending
+        };
+    }
+}
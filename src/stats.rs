@@ -0,0 +1,92 @@
+use anyhow::anyhow;
+
+use crate::{client::Client, error::OneKpError, mirror::Mirrors, OneKpRecord, Product};
+
+/// Header of the table written by `onekp assembly-stats`
+pub const STATS_HEADER: &str = "1kP_ID\tContigs\tN50\tTotal bases";
+
+/// Assembly quality of a sample, read from its stats file
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AssemblyStats {
+    pub contigs: Option<u64>,
+    pub n50: Option<u64>,
+    pub total_bases: Option<u64>,
+}
+
+impl AssemblyStats {
+    /// Read `name<TAB>value`, `name: value` or `name = value` lines,
+    /// recognizing the usual names of the three statistics and ignoring the others
+    ///
+    /// ```
+    /// use onekp::stats::AssemblyStats;
+    ///
+    /// let stats = AssemblyStats::parse("Number of contigs: 41,237\nN50\t1024\nTotal length = 38117463 bp\n");
+    /// assert_eq!(stats.to_tsv("URDJ"), "URDJ\t41237\t1024\t38117463");
+    /// ```
+    pub fn parse(text: &str) -> Self {
+        let mut stats = Self::default();
+        for line in text.lines() {
+            let Some((name, value)) = line
+                .split_once('\t')
+                .or_else(|| line.split_once(':'))
+                .or_else(|| line.split_once('='))
+            else {
+                continue;
+            };
+            // "1,234,567 bp" -> 1234567
+            let Ok(value) = value
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .replace(',', "")
+                .parse::<u64>()
+            else {
+                continue;
+            };
+            let name = name
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_ascii_lowercase();
+            match name.as_str() {
+                "contigs" | "numcontigs" | "numberofcontigs" | "contigcount" | "ncontigs"
+                | "sequences" | "numberofsequences" => stats.contigs = Some(value),
+                "n50" | "contign50" => stats.n50 = Some(value),
+                "totalbases" | "totallength" | "totalsize" | "totalbp" | "bases" => {
+                    stats.total_bases = Some(value)
+                }
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    /// Row of the table, see [`STATS_HEADER`]
+    pub fn to_tsv(&self, id: &str) -> String {
+        let cell = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_else(|| "-".to_owned());
+        format!(
+            "{}\t{}\t{}\t{}",
+            id,
+            cell(self.contigs),
+            cell(self.n50),
+            cell(self.total_bases)
+        )
+    }
+}
+
+/// Download the stats file of `rec` into memory, trying each mirror in turn
+pub async fn fetch_stats(
+    rec: &OneKpRecord,
+    client: &Client,
+    mirrors: &Mirrors,
+) -> Result<AssemblyStats, OneKpError> {
+    let path = rec.to_gigadb_path(Product::Stats.filename());
+    let mut last_err = None;
+    for url in mirrors.urls(&path) {
+        match client.get(&url).await {
+            Ok(response) => return Ok(AssemblyStats::parse(&response.text().await?)),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| OneKpError::Other(anyhow!("No URL for {}", path))))
+}
@@ -0,0 +1,196 @@
+//! `--strict` checks of the sample table, which otherwise fills missing cells with `No data`.
+
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+use crate::{
+    alias::CLADES, error::OneKpError, listing_links, resolve_prefix, source::DataSource,
+    OneKpRecord, PrefixMatch,
+};
+
+/// Columns of the 1KP sample list, see [`sample_list_rows`](crate::sample_list_rows)
+const COLUMNS: [&str; 6] = ["ID", "clade", "order", "family", "species", "tissue type"];
+
+/// Problem of a row of the sample table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// Line number in the table, from 1 with the header, if the problem is on one line
+    pub line: Option<usize>,
+    pub id: String,
+    pub problem: String,
+}
+
+/// The sample table failed the checks of [`StrictSource`]
+#[derive(Debug, Clone)]
+pub struct InvalidMetadata {
+    pub dataset: String,
+    pub issues: Vec<Issue>,
+}
+
+impl fmt::Display for InvalidMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The sample table of {} has {} problem{}:",
+            self.dataset,
+            self.issues.len(),
+            if self.issues.len() == 1 { "" } else { "s" }
+        )?;
+        for issue in self.issues.iter() {
+            match (issue.line, issue.id.is_empty()) {
+                (Some(line), true) => write!(f, "\n  line {}", line)?,
+                (Some(line), false) => write!(f, "\n  line {} ({})", line, issue.id)?,
+                (None, _) => write!(f, "\n  {}", issue.id)?,
+            }
+            write!(f, ": {}", issue.problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidMetadata {}
+
+/// Check the rows of a sample list with the columns of the 1KP one: cells that are missing,
+/// clades that are not among [`CLADES`] and, with `listing`, IDs without a single directory in it
+///
+/// ```
+/// use onekp::strict::check_sample_list;
+///
+/// let tsv = "id\tclade\torder\tfamily\tspecies\ttissue\n\
+///            URDJ\tLiverworts\tMarchantiales\tMarchantiaceae\tMarchantia polymorpha\tthallus\n\
+///            ABCD\tMosses\tBryales\n\
+///            EFGH\tTrees\tFagales\tFagaceae\tQuercus robur\tleaf\n";
+/// let listing = r#"<a href="URDJ-Marchantia_polymorpha/">URDJ</a><a href="ABCD-Bryum/">ABCD</a>"#;
+/// let issues = check_sample_list(tsv, Some(listing));
+/// let problems: Vec<(Option<usize>, &str)> = issues.iter().map(|i| (i.line, i.problem.as_str())).collect();
+/// assert_eq!(problems, [
+///     (Some(3), "no family"),
+///     (Some(3), "no species"),
+///     (Some(3), "no tissue type"),
+///     (Some(4), "unknown clade \"Trees\""),
+///     (Some(4), "no directory in the assemblies listing"),
+/// ]);
+/// ```
+pub fn check_sample_list(tsv: &str, listing: Option<&str>) -> Vec<Issue> {
+    let links = listing.map(listing_links);
+    let mut issues = vec![];
+    for (i, line) in tsv.split('\n').enumerate().skip(1) {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let cells: Vec<&str> = line.split('\t').map(|c| c.trim()).collect();
+        let id = cells[0];
+        let mut issue = |problem: String| {
+            issues.push(Issue {
+                line: Some(i + 1),
+                id: id.to_owned(),
+                problem,
+            })
+        };
+        for (column, name) in COLUMNS.iter().enumerate() {
+            if cells.get(column).is_none_or(|c| c.is_empty()) {
+                issue(format!("no {}", name));
+            }
+        }
+        if let Some(clade) = cells.get(1).filter(|c| !c.is_empty()) {
+            if !CLADES.contains(clade) {
+                issue(format!("unknown clade {:?}", clade));
+            }
+        }
+        if let Some(links) = &links {
+            match resolve_prefix(id, links) {
+                PrefixMatch::Found(_) => {}
+                PrefixMatch::Missing => issue("no directory in the assemblies listing".to_owned()),
+                PrefixMatch::Ambiguous(dirs) => {
+                    issue(format!("several directories: {}", dirs.join(", ")))
+                }
+            }
+        }
+    }
+    issues
+}
+
+/// Samples sharing an ID, which [`OneKp`](crate::OneKp) keeps the first of
+pub fn check_duplicates(records: &[OneKpRecord]) -> Vec<Issue> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for rec in records.iter() {
+        *counts.entry(rec.id.as_str()).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .filter(|(_, n)| *n > 1)
+        .map(|(id, n)| Issue {
+            line: None,
+            id: id.to_owned(),
+            problem: format!("{} samples with this ID", n),
+        })
+        .collect()
+}
+
+/// [`DataSource`] failing to parse a sample table with problems found by [`DataSource::check`]
+/// or with duplicate IDs, instead of working around them
+#[derive(Debug)]
+pub struct StrictSource(pub Arc<dyn DataSource>);
+
+impl DataSource for StrictSource {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn base_url(&self) -> &str {
+        self.0.base_url()
+    }
+
+    fn mirrors(&self) -> &[&str] {
+        self.0.mirrors()
+    }
+
+    fn ftp_base_url(&self) -> Option<&str> {
+        self.0.ftp_base_url()
+    }
+
+    fn metadata_url(&self) -> &str {
+        self.0.metadata_url()
+    }
+
+    fn listing_url(&self) -> Option<&str> {
+        self.0.listing_url()
+    }
+
+    fn checksum_url(&self) -> Option<&str> {
+        self.0.checksum_url()
+    }
+
+    fn check(&self, metadata: &str, listing: Option<&str>) -> Vec<Issue> {
+        self.0.check(metadata, listing)
+    }
+
+    fn parse(&self, metadata: &str, listing: Option<&str>) -> Result<Vec<OneKpRecord>, OneKpError> {
+        let issues = self.0.check(metadata, listing);
+        if !issues.is_empty() {
+            return Err(InvalidMetadata {
+                dataset: self.name().to_owned(),
+                issues,
+            }
+            .into());
+        }
+        let records = self.0.parse(metadata, listing)?;
+        let issues = check_duplicates(&records);
+        if !issues.is_empty() {
+            return Err(InvalidMetadata {
+                dataset: self.name().to_owned(),
+                issues,
+            }
+            .into());
+        }
+        Ok(records)
+    }
+
+    fn file_path(&self, record: &OneKpRecord, filename: &str) -> String {
+        self.0.file_path(record, filename)
+    }
+
+    fn sample_dir(&self, record: &OneKpRecord) -> Option<String> {
+        self.0.sample_dir(record)
+    }
+}
@@ -0,0 +1,169 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{checksum::Checksums, OneKpKey, OneKpRecord, SequenceType, NO_DATA};
+
+/// Number of records of each combination of the values of `keys`
+///
+/// ```
+/// use onekp::{summary::count_by, OneKpKey, OneKpRecord};
+///
+/// let rec = |id: &str, clade: &str| OneKpRecord {
+///     id: id.to_owned(),
+///     clade: clade.to_owned(),
+///     order: "No data".to_owned(),
+///     family: "No data".to_owned(),
+///     species: "No data".to_owned(),
+///     tissue_type: "No data".to_owned(),
+///     prefix: id.to_owned(),
+///     source: None,
+///     lineage: None,
+/// };
+/// let records = [rec("AAAA", "Mosses"), rec("BBBB", "Hornworts"), rec("CCCC", "Mosses")];
+/// let counts: Vec<_> = count_by(&records, &[OneKpKey::Clade]).into_iter().collect();
+/// assert_eq!(counts, [(vec!["Hornworts"], 1), (vec!["Mosses"], 2)]);
+/// ```
+pub fn count_by<'a>(
+    records: &'a [OneKpRecord],
+    keys: &[OneKpKey],
+) -> BTreeMap<Vec<&'a str>, usize> {
+    let mut counts = BTreeMap::new();
+    for rec in records.iter() {
+        *counts
+            .entry(keys.iter().map(|k| rec.get(*k)).collect())
+            .or_default() += 1;
+    }
+    counts
+}
+
+/// Values of nested groups of samples, as `(depth, value, samples)` in the order of `groups`,
+/// each value of the first key followed by those of the second key within it, and so on.
+/// `groups` are those of [`count_by`], whose groups of the same values are next to each other.
+///
+/// ```
+/// use onekp::summary::nest;
+///
+/// let groups = [
+///     (vec!["Mosses", "Bryales"], 3),
+///     (vec!["Mosses", "Sphagnales"], 2),
+///     (vec!["Hornworts", "Anthocerotales"], 1),
+/// ];
+/// assert_eq!(
+///     nest(&groups),
+///     [
+///         (0, "Mosses", 5),
+///         (1, "Bryales", 3),
+///         (1, "Sphagnales", 2),
+///         (0, "Hornworts", 1),
+///         (1, "Anthocerotales", 1),
+///     ]
+/// );
+/// ```
+pub fn nest<'a>(groups: &[(Vec<&'a str>, usize)]) -> Vec<(usize, &'a str, usize)> {
+    let mut nodes = vec![];
+    for (i, (values, _)) in groups.iter().enumerate() {
+        let shared = match i {
+            0 => 0,
+            _ => values
+                .iter()
+                .zip(groups[i - 1].0.iter())
+                .take_while(|(a, b)| a == b)
+                .count(),
+        };
+        for depth in shared..values.len() {
+            let samples = groups[i..]
+                .iter()
+                .take_while(|(v, _)| v[..=depth] == values[..=depth])
+                .map(|(_, n)| n)
+                .sum();
+            nodes.push((depth, values[depth], samples));
+        }
+    }
+    nodes
+}
+
+/// Overview of a selection of samples, printed by `onekp stats`
+#[derive(Debug, Clone)]
+pub struct Summary<'a> {
+    pub samples: usize,
+    /// Distinct species, not counting the samples without one
+    pub species: usize,
+    /// Samples whose nucleotide and protein files are both in the checksum list,
+    /// `None` without the list
+    pub with_both_files: Option<usize>,
+    pub per_clade: BTreeMap<Vec<&'a str>, usize>,
+    pub per_order: BTreeMap<Vec<&'a str>, usize>,
+    pub per_tissue_type: BTreeMap<Vec<&'a str>, usize>,
+}
+
+impl<'a> Summary<'a> {
+    /// ```
+    /// use onekp::{summary::Summary, OneKpRecord};
+    ///
+    /// let rec = |id: &str, species: &str| OneKpRecord {
+    ///     id: id.to_owned(),
+    ///     clade: "Mosses".to_owned(),
+    ///     order: "Bryales".to_owned(),
+    ///     family: "Bryaceae".to_owned(),
+    ///     species: species.to_owned(),
+    ///     tissue_type: "No data".to_owned(),
+    ///     prefix: id.to_owned(),
+    ///     source: None,
+    ///     lineage: None,
+    /// };
+    /// let records = [
+    ///     rec("AAAA", "Bryum argenteum"),
+    ///     rec("BBBB", "Bryum argenteum"),
+    ///     rec("CCCC", "No data"),
+    /// ];
+    /// let summary = Summary::new(&records, None);
+    /// assert_eq!((summary.samples, summary.species), (3, 1));
+    /// ```
+    pub fn new(records: &'a [OneKpRecord], checksums: Option<&Checksums>) -> Self {
+        Self {
+            samples: records.len(),
+            species: count_by(records, &[OneKpKey::Species])
+                .keys()
+                .filter(|species| species[0] != NO_DATA)
+                .count(),
+            with_both_files: checksums.map(|checksums| {
+                records
+                    .iter()
+                    .filter(|r| {
+                        SequenceType::Both
+                            .to_filenames()
+                            .iter()
+                            .all(|f| checksums.get(&r.to_gigadb_path(f)).is_some())
+                    })
+                    .count()
+            }),
+            per_clade: count_by(records, &[OneKpKey::Clade]),
+            per_order: count_by(records, &[OneKpKey::Order]),
+            per_tissue_type: count_by(records, &[OneKpKey::TissueType]),
+        }
+    }
+}
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Samples: {}", self.samples)?;
+        writeln!(f, "Species: {}", self.species)?;
+        match self.with_both_files {
+            Some(n) => writeln!(f, "Samples with nucleotide and protein files: {}", n)?,
+            None => writeln!(
+                f,
+                "Samples with nucleotide and protein files: unknown without the checksum list"
+            )?,
+        }
+        for (title, counts) in [
+            ("clade", &self.per_clade),
+            ("order", &self.per_order),
+            ("tissue type", &self.per_tissue_type),
+        ] {
+            writeln!(f, "\nSamples per {}:", title)?;
+            for (group, n) in counts.iter() {
+                writeln!(f, "  {}\t{}", group.join("\t"), n)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use crate::error::OneKpError;
+
+/// Accepted names of species the 1KP sample list gives under an older binomial,
+/// `accepted name<TAB>name in the sample list`
+const BUNDLED: &str = "\
+# accepted name\tname in the 1KP sample list
+Physcomitrium patens\tPhyscomitrella patens
+Phlegmariurus squarrosus\tHuperzia squarrosa
+Botrypus virginianus\tBotrychium virginianum
+Alsophila spinulosa\tCyathea spinulosa
+Symphyotrichum novae-angliae\tAster novae-angliae
+";
+
+/// Table from currently accepted species names to the names used in the metadata.
+///
+/// ```
+/// use onekp::synonym::Synonyms;
+///
+/// let mut synonyms = Synonyms::bundled();
+/// synonyms.extend(Synonyms::parse("Oxybasis rubra\tChenopodium rubrum").unwrap());
+/// assert_eq!(synonyms.resolve("physcomitrium patens"), Some(&["Physcomitrella patens".to_owned()][..]));
+/// assert_eq!(synonyms.resolve("Oxybasis rubra"), Some(&["Chenopodium rubrum".to_owned()][..]));
+/// assert_eq!(synonyms.resolve("Poa annua"), None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Synonyms {
+    /// Lowercase accepted name to the names in the metadata
+    names: BTreeMap<String, Vec<String>>,
+}
+
+impl Synonyms {
+    /// The synonyms shipped with onekp
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED).expect("the bundled synonym table is valid")
+    }
+
+    /// Read a table of `accepted name<TAB>name in the metadata` lines.
+    /// `#` starts a comment, and an accepted name may have several lines.
+    pub fn parse(text: &str) -> Result<Self, OneKpError> {
+        let mut synonyms = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (accepted, listed) = line
+                .split_once('\t')
+                .map(|(a, l)| (a.trim(), l.trim()))
+                .filter(|(a, l)| !a.is_empty() && !l.is_empty())
+                .ok_or_else(|| {
+                    OneKpError::Parse(format!(
+                        "line {} of the synonym table is not `accepted name<TAB>name in the metadata`",
+                        i + 1
+                    ))
+                })?;
+            synonyms.insert(accepted, listed);
+        }
+        Ok(synonyms)
+    }
+
+    fn insert(&mut self, accepted: &str, listed: &str) {
+        let names = self.names.entry(accepted.to_lowercase()).or_default();
+        if !names.iter().any(|n| n == listed) {
+            names.push(listed.to_owned());
+        }
+    }
+
+    /// Add the synonyms of `other`, e.g. a user table to the bundled one
+    pub fn extend(&mut self, other: Synonyms) {
+        for (accepted, listed) in other.names {
+            for name in listed {
+                self.insert(&accepted, &name);
+            }
+        }
+    }
+
+    /// Names in the metadata of the accepted name `name`, ignoring case
+    pub fn resolve(&self, name: &str) -> Option<&[String]> {
+        self.names.get(&name.to_lowercase()).map(|n| n.as_slice())
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{create_dir_all, read_to_string},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use reqwest::Url;
+use select::{document::Document, node::Node, predicate::Name};
+use serde::{Deserialize, Serialize};
+
+use crate::{client::Client, error::OneKpError, write_atomic};
+
+/// Entrez search of the NCBI Taxonomy by name
+const ESEARCH_URL: &str = "https://eutils.ncbi.nlm.nih.gov/entrez/eutils/esearch.fcgi";
+/// Entrez records of NCBI Taxonomy IDs, with their lineage
+const EFETCH_URL: &str = "https://eutils.ncbi.nlm.nih.gov/entrez/eutils/efetch.fcgi";
+/// Species looked up in one search, keeping the URL short
+const SEARCH_BATCH: usize = 20;
+
+/// File of the cache directory the lineages are kept in. Unlike the metadata it does not expire.
+pub const TAXONOMY_FILENAME: &str = "taxonomy.json";
+
+/// A node of the NCBI Taxonomy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Taxon {
+    pub taxid: String,
+    pub name: String,
+    /// e.g. `phylum`, or `no rank`
+    pub rank: String,
+}
+
+/// NCBI Taxonomy ID of a species and its ancestors, from the root to the species
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lineage {
+    pub taxid: String,
+    pub lineage: Vec<Taxon>,
+}
+
+impl Lineage {
+    /// Name of the ancestor at `rank`
+    pub fn rank(&self, rank: &str) -> Option<&str> {
+        self.lineage
+            .iter()
+            .find(|t| t.rank == rank)
+            .map(|t| t.name.as_str())
+    }
+}
+
+/// Lineages of the species names, `None` for the names unknown to the NCBI Taxonomy
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Taxonomy {
+    species: BTreeMap<String, Option<Lineage>>,
+}
+
+impl Taxonomy {
+    /// Path of the lineages cached in `cache_dir`
+    pub fn path(cache_dir: &Path) -> PathBuf {
+        cache_dir.join(TAXONOMY_FILENAME)
+    }
+
+    /// The lineages cached in `cache_dir`, none before the first `onekp enrich`
+    pub fn load(cache_dir: &Path) -> Result<Self, OneKpError> {
+        let path = Self::path(cache_dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, cache_dir: &Path) -> Result<(), OneKpError> {
+        create_dir_all(cache_dir)?;
+        write_atomic(
+            &Self::path(cache_dir),
+            serde_json::to_string_pretty(self)?.as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// Lineage of `species`, if it was looked up and found
+    pub fn get(&self, species: &str) -> Option<&Lineage> {
+        self.species.get(species).and_then(|l| l.as_ref())
+    }
+
+    /// Number of species looked up, and of those found
+    pub fn counts(&self) -> (usize, usize) {
+        (
+            self.species.len(),
+            self.species.values().filter(|l| l.is_some()).count(),
+        )
+    }
+
+    /// Look up the species not looked up yet on NCBI Entrez.
+    /// `api_key` raises the NCBI rate limit from 3 to 10 requests a second.
+    pub async fn update<'a>(
+        &mut self,
+        species: impl Iterator<Item = &'a str>,
+        client: &Client,
+        api_key: Option<&str>,
+    ) -> Result<(), OneKpError> {
+        let missing: Vec<&str> = species
+            .filter(|s| !self.species.contains_key(*s))
+            .collect::<BTreeSet<&str>>()
+            .into_iter()
+            .collect();
+        for (i, names) in missing.chunks(SEARCH_BATCH).enumerate() {
+            client.notify(&format!(
+                "Looking up species {}-{} of {}",
+                i * SEARCH_BATCH + 1,
+                i * SEARCH_BATCH + names.len(),
+                missing.len()
+            ));
+            let term = names
+                .iter()
+                .map(|n| format!("\"{}\"[Scientific Name]", n.replace('"', "")))
+                .collect::<Vec<String>>()
+                .join(" OR ");
+            let retmax = (names.len() * 2).to_string();
+            let search = entrez_url(
+                ESEARCH_URL,
+                &[("term", &term), ("retmode", "json"), ("retmax", &retmax)],
+                api_key,
+            )?;
+            let ids = parse_search(&client.get(search.as_str()).await?.text().await?)?;
+
+            let mut found = BTreeMap::new();
+            if !ids.is_empty() {
+                let fetch = entrez_url(
+                    EFETCH_URL,
+                    &[("id", &ids.join(",")), ("retmode", "xml")],
+                    api_key,
+                )?;
+                for (name, lineage) in parse_taxa(&client.get(fetch.as_str()).await?.text().await?)
+                {
+                    found.insert(name.to_lowercase(), lineage);
+                }
+            }
+            for name in names {
+                let lineage = found.get(&name.to_lowercase()).cloned();
+                self.species.insert(name.to_string(), lineage);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn entrez_url(
+    base: &str,
+    params: &[(&str, &str)],
+    api_key: Option<&str>,
+) -> Result<Url, OneKpError> {
+    let mut url = Url::parse_with_params(base, params)
+        .map_err(|err| OneKpError::InvalidOptions(err.to_string()))?;
+    url.query_pairs_mut().append_pair("db", "taxonomy");
+    if let Some(key) = api_key {
+        url.query_pairs_mut().append_pair("api_key", key);
+    }
+    Ok(url)
+}
+
+/// Taxonomy IDs of an esearch JSON response
+fn parse_search(json: &str) -> Result<Vec<String>, OneKpError> {
+    #[derive(Deserialize)]
+    struct Response {
+        esearchresult: SearchResult,
+    }
+    #[derive(Deserialize)]
+    struct SearchResult {
+        #[serde(default)]
+        idlist: Vec<String>,
+    }
+    let response: Response = serde_json::from_str(json)
+        .map_err(|err| OneKpError::Network(anyhow!("Unexpected Entrez response: {}", err)))?;
+    Ok(response.esearchresult.idlist)
+}
+
+/// Scientific names and lineages of the taxa of an efetch XML response
+///
+/// ```text
+/// <TaxaSet><Taxon>
+///     <TaxId>3702</TaxId><ScientificName>Arabidopsis thaliana</ScientificName><Rank>species</Rank>
+///     <LineageEx><Taxon><TaxId>2759</TaxId><ScientificName>Eukaryota</ScientificName><Rank>superkingdom</Rank></Taxon>...</LineageEx>
+/// </Taxon></TaxaSet>
+/// ```
+fn parse_taxa(xml: &str) -> Vec<(String, Lineage)> {
+    let doc = Document::from(xml);
+    doc.find(Name("taxon"))
+        .filter(|n| n.parent().and_then(|p| p.name()) == Some("taxaset"))
+        .filter_map(|n| {
+            let taxon = parse_taxon(&n)?;
+            let mut lineage: Vec<Taxon> = n
+                .find(Name("lineageex"))
+                .flat_map(|l| l.children().filter_map(|c| parse_taxon(&c)))
+                .collect();
+            let name = taxon.name.clone();
+            let taxid = taxon.taxid.clone();
+            lineage.push(taxon);
+            Some((name, Lineage { taxid, lineage }))
+        })
+        .collect()
+}
+
+/// ID, name and rank of a `<Taxon>` element, ignoring its descendants
+fn parse_taxon(node: &Node) -> Option<Taxon> {
+    if node.name() != Some("taxon") {
+        return None;
+    }
+    let child = |name: &str| {
+        node.children()
+            .find(|c| c.name() == Some(name))
+            .map(|c| c.text().trim().to_owned())
+    };
+    Some(Taxon {
+        taxid: child("taxid")?,
+        name: child("scientificname")?,
+        rank: child("rank").unwrap_or_else(|| "no rank".to_owned()),
+    })
+}
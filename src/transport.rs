@@ -0,0 +1,216 @@
+use std::fmt::Debug;
+
+use bytes::Bytes;
+use futures_util::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt, TryStreamExt};
+use reqwest::{
+    header::{
+        HeaderMap, CONTENT_LENGTH, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE,
+    },
+    Method, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::OneKpError;
+
+/// Sends the HTTP requests of a [`Client`](crate::Client).
+/// The default is [`HttpTransport`]; tests and library users can plug in their own,
+/// e.g. a mock server or an instrumented or caching transport, with
+/// [`Client::with_transport`](crate::Client::with_transport).
+/// `ftp://` URLs do not go through the transport.
+///
+/// ```no_run
+/// use std::{collections::HashMap, sync::Arc};
+/// use futures_util::{future::BoxFuture, FutureExt};
+/// use onekp::{transport::{Transport, TransportResponse}, Client, ClientOptions, OneKpError};
+/// use reqwest::StatusCode;
+///
+/// /// Serves fixed bodies, 404 for anything else
+/// #[derive(Debug)]
+/// struct Mock(HashMap<String, &'static str>);
+///
+/// impl Transport for Mock {
+///     fn get<'a>(&'a self, url: &'a str, _offset: u64) -> BoxFuture<'a, Result<TransportResponse, OneKpError>> {
+///         let resp = match self.0.get(url) {
+///             Some(body) => TransportResponse::new(StatusCode::OK, *body),
+///             None => TransportResponse::new(StatusCode::NOT_FOUND, ""),
+///         };
+///         async move { Ok(resp) }.boxed()
+///     }
+///
+///     fn head<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<TransportResponse, OneKpError>> {
+///         self.get(url, 0)
+///     }
+/// }
+///
+/// # async fn example() -> Result<(), OneKpError> {
+/// let mock = Mock(HashMap::from([("https://example.org/a".to_owned(), "hello")]));
+/// let client = Client::new(&ClientOptions::default())?.with_transport(Arc::new(mock));
+/// assert_eq!(client.get("https://example.org/a").await?.text().await?, "hello");
+/// # Ok(())
+/// # }
+/// ```
+pub trait Transport: Debug + Send + Sync {
+    /// `GET url`, from byte `offset` with a `Range` header when it is not 0
+    fn get<'a>(
+        &'a self,
+        url: &'a str,
+        offset: u64,
+    ) -> BoxFuture<'a, Result<TransportResponse, OneKpError>>;
+
+    /// `HEAD url`
+    fn head<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<TransportResponse, OneKpError>>;
+
+    /// `GET url` with `If-None-Match` and `If-Modified-Since` from `validators`,
+    /// answered with `304 Not Modified` when the copy they came from is still current.
+    /// Transports without conditional requests send a plain `GET`.
+    fn get_if_modified<'a>(
+        &'a self,
+        url: &'a str,
+        validators: &'a Validators,
+    ) -> BoxFuture<'a, Result<TransportResponse, OneKpError>> {
+        let _ = validators;
+        self.get(url, 0)
+    }
+}
+
+/// `ETag` and `Last-Modified` of a response, to ask the server later whether it changed
+///
+/// ```
+/// use onekp::transport::Validators;
+/// use reqwest::header::{HeaderMap, HeaderValue, ETAG};
+///
+/// let mut headers = HeaderMap::new();
+/// assert!(Validators::from_headers(&headers).is_empty());
+/// headers.insert(ETAG, HeaderValue::from_static("\"5f2a\""));
+/// assert_eq!(Validators::from_headers(&headers).etag.as_deref(), Some("\"5f2a\""));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_owned())
+        };
+        Self {
+            etag: get(ETAG),
+            last_modified: get(LAST_MODIFIED),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Response of a [`Transport`]. Error statuses are handled by the client.
+pub struct TransportResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// Length of the body, or of the resource for `HEAD`
+    pub content_length: Option<u64>,
+    pub body: BoxStream<'static, Result<Bytes, OneKpError>>,
+}
+
+impl TransportResponse {
+    /// A complete response with `body`, convenient for mock transports
+    pub fn new(status: StatusCode, body: impl Into<Bytes>) -> Self {
+        let body = body.into();
+        Self {
+            status,
+            headers: HeaderMap::new(),
+            content_length: Some(body.len() as u64),
+            body: futures_util::stream::once(async move { Ok(body) }).boxed(),
+        }
+    }
+
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// Read the whole body as text
+    pub async fn text(self) -> Result<String, OneKpError> {
+        let chunks: Vec<Bytes> = self.body.try_collect().await?;
+        String::from_utf8(chunks.concat())
+            .map_err(|_| OneKpError::Parse("Response is not UTF-8".to_owned()))
+    }
+}
+
+/// [`Transport`] over reqwest
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    http: reqwest::Client,
+}
+
+impl HttpTransport {
+    pub fn new(http: reqwest::Client) -> Self {
+        Self { http }
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        offset: u64,
+        validators: Option<&Validators>,
+    ) -> Result<TransportResponse, OneKpError> {
+        let mut req = self.http.request(method.clone(), url);
+        if offset > 0 {
+            req = req.header(RANGE, format!("bytes={}-", offset));
+        }
+        if let Some(validators) = validators {
+            if let Some(etag) = &validators.etag {
+                req = req.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                req = req.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let resp = req.send().await?;
+        let content_length = if method == Method::HEAD {
+            // the body of a HEAD response is empty, the header tells the size
+            resp.headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+        } else {
+            resp.content_length()
+        };
+        Ok(TransportResponse {
+            status: resp.status(),
+            headers: resp.headers().clone(),
+            content_length,
+            body: resp.bytes_stream().map(|c| Ok(c?)).boxed(),
+        })
+    }
+}
+
+impl Transport for HttpTransport {
+    fn get<'a>(
+        &'a self,
+        url: &'a str,
+        offset: u64,
+    ) -> BoxFuture<'a, Result<TransportResponse, OneKpError>> {
+        self.send(Method::GET, url, offset, None).boxed()
+    }
+
+    fn head<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<TransportResponse, OneKpError>> {
+        self.send(Method::HEAD, url, 0, None).boxed()
+    }
+
+    fn get_if_modified<'a>(
+        &'a self,
+        url: &'a str,
+        validators: &'a Validators,
+    ) -> BoxFuture<'a, Result<TransportResponse, OneKpError>> {
+        self.send(Method::GET, url, 0, Some(validators)).boxed()
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+
+use crate::OneKpRecord;
+
+/// Formats of `onekp export-tree`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TreeFormat {
+    /// Newick with the sample IDs as leaves
+    #[default]
+    Newick,
+}
+
+/// Clade, order, family and species of the samples as a tree
+///
+/// ```
+/// use onekp::{tree::TaxonTree, OneKpRecord};
+///
+/// let rec = |id: &str, order: &str, species: &str| OneKpRecord {
+///     id: id.to_owned(),
+///     clade: "Mosses".to_owned(),
+///     order: order.to_owned(),
+///     family: "Sphagnaceae".to_owned(),
+///     species: species.to_owned(),
+///     tissue_type: "shoot".to_owned(),
+///     prefix: id.to_owned(),
+///     source: None,
+///     lineage: None,
+/// };
+/// let tree = TaxonTree::new(&[
+///     rec("AAAA", "Sphagnales", "Sphagnum palustre"),
+///     rec("BBBB", "Sphagnales", "Sphagnum palustre"),
+///     rec("CCCC", "Sphagnales", "Sphagnum recurvum"),
+/// ]);
+/// assert_eq!(
+///     tree.to_indented(),
+///     "\
+/// Mosses (3)
+///   Sphagnales (3)
+///     Sphagnaceae (3)
+///       Sphagnum palustre (2)
+///       Sphagnum recurvum (1)
+/// "
+/// );
+/// assert_eq!(tree.to_newick(), "((AAAA,BBBB),CCCC);");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TaxonTree {
+    /// IDs of the samples below this node
+    pub ids: Vec<String>,
+    pub children: BTreeMap<String, TaxonTree>,
+}
+
+impl TaxonTree {
+    pub fn new(records: &[OneKpRecord]) -> Self {
+        let mut root = Self::default();
+        for rec in records.iter() {
+            let mut node = &mut root;
+            node.ids.push(rec.id.clone());
+            for level in [&rec.clade, &rec.order, &rec.family, &rec.species] {
+                node = node.children.entry(level.clone()).or_default();
+                node.ids.push(rec.id.clone());
+            }
+        }
+        root
+    }
+
+    /// One line per node with its number of samples, indented by depth
+    pub fn to_indented(&self) -> String {
+        let mut out = String::new();
+        self.write_indented(0, &mut out);
+        out
+    }
+
+    /// Newick tree of the sample IDs, grouping the samples of the same species, family,
+    /// order and clade. Levels with a single group are collapsed, so every internal node
+    /// is a real split, as constraint trees of phylogenetic programs expect.
+    pub fn to_newick(&self) -> String {
+        format!("{};", self.newick_subtree())
+    }
+
+    fn newick_subtree(&self) -> String {
+        let items: Vec<String> = if self.children.is_empty() {
+            self.ids.clone()
+        } else {
+            self.children.values().map(|c| c.newick_subtree()).collect()
+        };
+        match items.as_slice() {
+            [item] => item.clone(),
+            items => format!("({})", items.join(",")),
+        }
+    }
+
+    fn write_indented(&self, depth: usize, out: &mut String) {
+        for (name, child) in self.children.iter() {
+            out.push_str(&format!(
+                "{}{} ({})\n",
+                "  ".repeat(depth),
+                name,
+                child.ids.len()
+            ));
+            child.write_indented(depth + 1, out);
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Full-screen browser of `onekp tui`: the clades, orders and families of the samples on the left,
+//! the samples of the highlighted group on the right and the files of a sample below them.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use colored::*;
+use console::{pad_str, Alignment, Key, Term};
+use indicatif::HumanBytes;
+
+use crate::{error::OneKpError, listing::RemoteFile, summary::count_by, OneKpKey, OneKpRecord};
+
+/// Levels of the group pane, from the top
+const LEVELS: [OneKpKey; 3] = [OneKpKey::Clade, OneKpKey::Order, OneKpKey::Family];
+
+/// Key bindings shown on the status line
+const HELP: &str =
+    "Tab pane  Enter/→ open  ← back  Space mark  a mark all  d files  f download  q quit";
+
+/// Lines of the file pane
+const FILE_PANE_HEIGHT: usize = 8;
+
+/// What [`Browser::run`] leaves the terminal for
+#[derive(Debug, Clone)]
+pub enum Action {
+    Quit,
+    /// List the remote files of the sample, then give them to [`Browser::set_files`] and run again
+    ListFiles(Box<OneKpRecord>),
+    /// Download the marked samples
+    Fetch(Vec<OneKpRecord>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Groups,
+    Samples,
+}
+
+/// State of the browser, kept across [`Browser::run`]s
+#[derive(Debug, Clone)]
+pub struct Browser {
+    records: Vec<OneKpRecord>,
+    /// Clade, order and family opened, from the top
+    path: Vec<String>,
+    focus: Pane,
+    group_cursor: usize,
+    sample_cursor: usize,
+    /// IDs of the marked samples
+    marked: BTreeSet<String>,
+    /// Remote files of the samples by ID, or why they could not be listed
+    files: BTreeMap<String, Result<Vec<RemoteFile>, String>>,
+    show_files: bool,
+}
+
+impl Browser {
+    pub fn new(records: Vec<OneKpRecord>) -> Self {
+        Self {
+            records,
+            path: vec![],
+            focus: Pane::Groups,
+            group_cursor: 0,
+            sample_cursor: 0,
+            marked: BTreeSet::new(),
+            files: BTreeMap::new(),
+            show_files: false,
+        }
+    }
+
+    pub fn set_files(&mut self, id: &str, files: Result<Vec<RemoteFile>, String>) {
+        self.files.insert(id.to_owned(), files);
+        self.show_files = true;
+    }
+
+    /// Browse on the alternate screen of `term` until a key asks for an [`Action`]
+    pub fn run(&mut self, term: &Term) -> Result<Action, OneKpError> {
+        if !term.is_term() {
+            return Err(OneKpError::InvalidOptions(
+                "onekp tui needs a terminal".to_owned(),
+            ));
+        }
+        term.write_str("\x1b[?1049h")?;
+        term.hide_cursor()?;
+        let action = self.event_loop(term);
+        term.show_cursor()?;
+        term.write_str("\x1b[?1049l")?;
+        action
+    }
+
+    fn event_loop(&mut self, term: &Term) -> Result<Action, OneKpError> {
+        loop {
+            let (height, width) = term.size();
+            term.clear_screen()?;
+            term.write_str(&self.render(width as usize, height as usize))?;
+
+            let groups = self.groups();
+            let samples = self.samples();
+            match term.read_key()? {
+                Key::Char('q') | Key::Escape | Key::CtrlC => return Ok(Action::Quit),
+                Key::Tab | Key::BackTab => {
+                    self.focus = match self.focus {
+                        Pane::Groups => Pane::Samples,
+                        Pane::Samples => Pane::Groups,
+                    }
+                }
+                Key::ArrowUp => self.move_cursor(-1),
+                Key::ArrowDown => self.move_cursor(1),
+                Key::PageUp => self.move_cursor(-(height as isize / 2)),
+                Key::PageDown => self.move_cursor(height as isize / 2),
+                Key::Enter | Key::ArrowRight if self.focus == Pane::Groups => {
+                    if let Some((value, _)) = groups.get(self.group_cursor) {
+                        self.path.push(value.clone());
+                        self.group_cursor = 0;
+                        self.sample_cursor = 0;
+                    }
+                }
+                Key::ArrowLeft | Key::Backspace => {
+                    if let Some(value) = self.path.pop() {
+                        self.group_cursor = self
+                            .groups()
+                            .iter()
+                            .position(|(v, _)| *v == value)
+                            .unwrap_or_default();
+                        self.sample_cursor = 0;
+                    }
+                }
+                Key::Char(' ') => {
+                    let ids: Vec<String> = match self.focus {
+                        Pane::Groups => match groups.get(self.group_cursor) {
+                            Some((value, _)) => self
+                                .within(self.path.len(), value)
+                                .iter()
+                                .map(|r| r.id.clone())
+                                .collect(),
+                            None => vec![],
+                        },
+                        Pane::Samples => samples
+                            .get(self.sample_cursor)
+                            .map(|r| vec![r.id.clone()])
+                            .unwrap_or_default(),
+                    };
+                    self.toggle(ids);
+                    if self.focus == Pane::Samples {
+                        self.move_cursor(1);
+                    }
+                }
+                Key::Char('a') => self.toggle(samples.iter().map(|r| r.id.clone()).collect()),
+                Key::Char('d') | Key::Enter => match samples.get(self.sample_cursor) {
+                    Some(rec) if !self.files.contains_key(&rec.id) => {
+                        return Ok(Action::ListFiles(Box::new((*rec).clone())));
+                    }
+                    _ => self.show_files = !self.show_files,
+                },
+                Key::Char('f') => {
+                    let records: Vec<OneKpRecord> = self
+                        .records
+                        .iter()
+                        .filter(|r| self.marked.contains(&r.id))
+                        .cloned()
+                        .collect();
+                    if !records.is_empty() {
+                        return Ok(Action::Fetch(records));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Mark the samples of `ids`, or unmark them if they are all marked already
+    fn toggle(&mut self, ids: Vec<String>) {
+        if ids.iter().all(|id| self.marked.contains(id)) {
+            for id in ids.iter() {
+                self.marked.remove(id);
+            }
+        } else {
+            self.marked.extend(ids);
+        }
+    }
+
+    fn move_cursor(&mut self, by: isize) {
+        let len = match self.focus {
+            Pane::Groups => self.groups().len(),
+            Pane::Samples => self.samples().len(),
+        };
+        let cursor = match self.focus {
+            Pane::Groups => &mut self.group_cursor,
+            Pane::Samples => &mut self.sample_cursor,
+        };
+        *cursor = cursor.saturating_add_signed(by).min(len.saturating_sub(1));
+        if self.focus == Pane::Groups {
+            self.sample_cursor = 0;
+        }
+    }
+
+    /// Samples in the opened groups whose value at `level` is `value`
+    fn within(&self, level: usize, value: &str) -> Vec<&OneKpRecord> {
+        self.records
+            .iter()
+            .filter(|r| {
+                self.path.iter().zip(LEVELS).all(|(v, key)| r.get(key) == v)
+                    && LEVELS.get(level).is_none_or(|key| r.get(*key) == value)
+            })
+            .collect()
+    }
+
+    /// Values of the level below the opened groups, with their numbers of samples
+    fn groups(&self) -> Vec<(String, usize)> {
+        let Some(key) = LEVELS.get(self.path.len()) else {
+            return vec![];
+        };
+        let records: Vec<OneKpRecord> =
+            self.within(LEVELS.len(), "").into_iter().cloned().collect();
+        count_by(&records, &[*key])
+            .into_iter()
+            .map(|(values, n)| (values[0].to_owned(), n))
+            .collect()
+    }
+
+    /// Samples of the highlighted group, or of the opened ones at the lowest level
+    fn samples(&self) -> Vec<&OneKpRecord> {
+        let groups = self.groups();
+        match groups.get(self.group_cursor) {
+            Some((value, _)) => self.within(self.path.len(), value),
+            None => self.within(LEVELS.len(), ""),
+        }
+    }
+
+    /// Screen of `width` columns and `height` lines
+    fn render(&self, width: usize, height: usize) -> String {
+        // writing to the last column wraps the line on some terminals
+        let width = width.saturating_sub(1);
+        let groups = self.groups();
+        let samples = self.samples();
+        let files_height = if self.show_files { FILE_PANE_HEIGHT } else { 0 };
+        let body_height = height.saturating_sub(3 + files_height).max(1);
+        let left_width = (width / 3).clamp(12, 40);
+        let right_width = width.saturating_sub(left_width + 3);
+
+        let mut screen = String::new();
+        let breadcrumb = std::iter::once("1KP")
+            .chain(self.path.iter().map(|v| v.as_str()))
+            .collect::<Vec<&str>>()
+            .join(" > ");
+        screen.push_str(&format!("{}\n", fit(&breadcrumb, width).bold()));
+
+        let group_lines: Vec<String> = groups
+            .iter()
+            .map(|(value, n)| format!("{} ({})", value, n))
+            .collect();
+        let sample_lines: Vec<String> = samples
+            .iter()
+            .map(|r| {
+                let mark = if self.marked.contains(&r.id) {
+                    "[x]"
+                } else {
+                    "[ ]"
+                };
+                format!("{} {}  {}  {}", mark, r.id, r.species, r.tissue_type)
+            })
+            .collect();
+        let group_first = self.group_cursor.saturating_sub(body_height - 1);
+        let sample_first = self.sample_cursor.saturating_sub(body_height - 1);
+        for row in 0..body_height {
+            let cell = |lines: &[String], first: usize, cursor: usize, pane: Pane, width: usize| {
+                let Some(line) = lines.get(first + row) else {
+                    return " ".repeat(width);
+                };
+                let text = fit(line, width);
+                match (first + row == cursor, self.focus == pane) {
+                    (true, true) => text.reverse().to_string(),
+                    (true, false) => text.bold().to_string(),
+                    _ => text,
+                }
+            };
+            screen.push_str(&format!(
+                "{} │ {}\n",
+                cell(
+                    &group_lines,
+                    group_first,
+                    self.group_cursor,
+                    Pane::Groups,
+                    left_width
+                ),
+                cell(
+                    &sample_lines,
+                    sample_first,
+                    self.sample_cursor,
+                    Pane::Samples,
+                    right_width
+                )
+            ));
+        }
+
+        if self.show_files {
+            screen.push_str(&format!("{}\n", "─".repeat(width)));
+            let mut lines = vec![];
+            if let Some(rec) = samples.get(self.sample_cursor) {
+                lines.push(format!(
+                    "{}  {} / {} / {} / {}  {}",
+                    rec.id, rec.clade, rec.order, rec.family, rec.species, rec.tissue_type
+                ));
+                match self.files.get(&rec.id) {
+                    Some(Ok(files)) => lines.extend(files.iter().map(|f| {
+                        format!(
+                            "  {}  {}",
+                            f.name,
+                            f.size
+                                .map(|size| HumanBytes(size).to_string())
+                                .unwrap_or_else(|| "-".to_owned())
+                        )
+                    })),
+                    Some(Err(err)) => lines.push(format!("  {}", err)),
+                    None => lines.push("  press d to list the files".to_owned()),
+                }
+            }
+            for row in 0..FILE_PANE_HEIGHT - 1 {
+                let line = lines.get(row).map(|l| l.as_str()).unwrap_or_default();
+                screen.push_str(&format!("{}\n", fit(line, width)));
+            }
+        }
+
+        let status = format!(
+            "{} samples, {} marked  {}",
+            samples.len(),
+            self.marked.len(),
+            HELP
+        );
+        screen.push_str(&fit(&status, width).dimmed().to_string());
+        screen
+    }
+}
+
+/// `text` padded or cut with `…` to `width` columns
+fn fit(text: &str, width: usize) -> String {
+    pad_str(text, width, Alignment::Left, Some("…")).into_owned()
+}
@@ -0,0 +1,129 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use flate2::bufread::MultiGzDecoder;
+
+use crate::error::Result;
+
+/// The content of a downloaded file is broken
+#[derive(Debug)]
+pub struct Corrupt {
+    pub path: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Corrupt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Corrupt file {}: {}", self.path, self.reason)
+    }
+}
+
+impl std::error::Error for Corrupt {}
+
+pub fn is_gzip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Decompress the whole gzip file, returning the decompressed size.
+/// Files made of several concatenated gzip members are accepted.
+pub fn check_gzip(path: &Path) -> Result<u64> {
+    let mut decoder = MultiGzDecoder::new(BufReader::new(File::open(path)?));
+    match io::copy(&mut decoder, &mut io::sink()) {
+        Ok(size) => Ok(size),
+        Err(err) if is_format_error(&err) => Err(Corrupt {
+            path: path.display().to_string(),
+            reason: err.to_string(),
+        }
+        .into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn is_format_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// Residues expected in a FASTA file, case-insensitive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alphabet {
+    Nucleotide,
+    Protein,
+}
+
+impl Alphabet {
+    /// Alphabet of a 1KP file name such as `protein.fa.gz`
+    pub fn of(filename: &str) -> Self {
+        if filename.starts_with("protein") {
+            Self::Protein
+        } else {
+            Self::Nucleotide
+        }
+    }
+
+    fn allows(self, c: u8) -> bool {
+        // gaps and stop codons are accepted in both
+        let symbols: &[u8] = match self {
+            Self::Nucleotide => b"ACGTUNRYKMSWBDHV-.*",
+            Self::Protein => b"ACDEFGHIKLMNPQRSTVWYBJOUXZ-.*",
+        };
+        symbols.contains(&c.to_ascii_uppercase())
+    }
+}
+
+/// Check that the gzip file is well-formed FASTA of `alphabet`:
+/// every record has a header and a non-empty sequence of allowed residues.
+/// Returns the number of records.
+pub fn check_fasta(path: &Path, alphabet: Alphabet) -> Result<u64> {
+    let corrupt = |reason: String| Corrupt {
+        path: path.display().to_string(),
+        reason,
+    };
+    let mut reader = BufReader::new(MultiGzDecoder::new(BufReader::new(File::open(path)?)));
+    let mut line = vec![];
+    let mut records = 0;
+    let mut residues = 0;
+    let mut header = None;
+    for number in 1.. {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(err) if is_format_error(&err) => return Err(corrupt(err.to_string()).into()),
+            Err(err) => return Err(err.into()),
+        }
+        let text = line.trim_ascii_end();
+        if let Some(name) = text.strip_prefix(b">") {
+            if let Some(previous) = header.replace(String::from_utf8_lossy(name).into_owned()) {
+                if residues == 0 {
+                    return Err(corrupt(format!("empty sequence for {}", previous)).into());
+                }
+            }
+            records += 1;
+            residues = 0;
+        } else if !text.is_empty() {
+            if header.is_none() {
+                return Err(corrupt(format!("sequence without header at line {}", number)).into());
+            }
+            if let Some(&c) = text.iter().find(|&&c| !alphabet.allows(c)) {
+                return Err(corrupt(format!(
+                    "unexpected character {:?} at line {}",
+                    c as char, number
+                ))
+                .into());
+            }
+            residues += text.len();
+        }
+    }
+    match header {
+        None => Err(corrupt("no FASTA record".to_owned()).into()),
+        Some(last) if residues == 0 => Err(corrupt(format!("empty sequence for {}", last)).into()),
+        Some(_) => Ok(records),
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::{metadata, read_dir, read_to_string},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+
+use crate::{
+    checksum::{md5_file, Checksums},
+    client::Client,
+    error::{OneKpError, Result},
+    manifest::ManifestAlgorithm,
+    mirror::Mirrors,
+    provenance::sidecar_path,
+    validate::{check_gzip, is_gzip},
+    OneKpRecord, Product, SequenceType, METADATA_FILENAME,
+};
+
+/// A file listed in the metadata table of a downloaded dataset
+#[derive(Debug, Clone)]
+pub struct ListedFile {
+    /// Path relative to the root directory
+    pub path: PathBuf,
+    /// Empty when the sample is not in the 1KP metadata anymore
+    pub urls: Vec<String>,
+    pub md5: Option<String>,
+}
+
+/// Read the files listed in `metadata.tsv` of `rootdir`, resolved against `records`
+pub fn listed_files(
+    rootdir: &Path,
+    records: &[OneKpRecord],
+    mirrors: &Mirrors,
+    checksums: Option<&Checksums>,
+) -> Result<Vec<ListedFile>> {
+    let table = rootdir.join(METADATA_FILENAME);
+    let text = read_to_string(&table)
+        .map_err(|err| anyhow!("Cannot read {}: {}", table.display(), err))?;
+    let by_id: HashMap<&str, &OneKpRecord> = records.iter().map(|r| (r.id.as_str(), r)).collect();
+
+    let mut files = vec![];
+    for line in text.lines().skip(1).filter(|l| !l.trim().is_empty()) {
+        let columns: Vec<&str> = line.split('\t').collect();
+        let (Some(id), Some(path)) = (columns.first(), columns.last()) else {
+            continue;
+        };
+        // the file name tells which product of the sample it is
+        let gigadb_path = by_id.get(id).and_then(|rec| {
+            SequenceType::All
+                .with_products(Product::value_variants())
+                .into_iter()
+                .find(|filename| path.ends_with(filename))
+                .map(|filename| rec.to_gigadb_path(filename))
+        });
+        files.push(ListedFile {
+            path: PathBuf::from(path),
+            urls: gigadb_path
+                .as_deref()
+                .map(|p| mirrors.urls(p))
+                .unwrap_or_default(),
+            md5: gigadb_path
+                .as_deref()
+                .and_then(|p| checksums.and_then(|c| c.get(p)))
+                .map(|h| h.to_owned()),
+        });
+    }
+    Ok(files)
+}
+
+/// What is wrong with a listed file
+#[derive(Debug)]
+pub enum Problem {
+    Missing,
+    Size { expected: u64, actual: u64 },
+    Checksum { expected: String, actual: String },
+    Corrupt(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "missing"),
+            Self::Size { expected, actual } => {
+                write!(f, "{} bytes instead of {}", actual, expected)
+            }
+            Self::Checksum { expected, actual } => {
+                write!(f, "MD5 mismatch: expected {}, got {}", expected, actual)
+            }
+            Self::Corrupt(reason) => write!(f, "corrupt: {}", reason),
+        }
+    }
+}
+
+/// Result of `onekp verify`
+#[derive(Debug, Default)]
+pub struct Verification {
+    pub ok: Vec<PathBuf>,
+    pub problems: Vec<(PathBuf, Problem)>,
+    /// Files of the root directory that are not part of the dataset
+    pub extraneous: Vec<PathBuf>,
+}
+
+/// Check the size, checksum and gzip stream of each listed file,
+/// stopping at the first problem of a file
+pub async fn verify(rootdir: &Path, files: &[ListedFile], client: &Client) -> Result<Verification> {
+    let mut verification = Verification::default();
+    for file in files.iter() {
+        let path = rootdir.join(&file.path);
+        match check_file(&path, file, client).await? {
+            Some(problem) => verification.problems.push((file.path.clone(), problem)),
+            None => verification.ok.push(file.path.clone()),
+        }
+    }
+    verification.extraneous = extraneous(rootdir, files)?;
+    Ok(verification)
+}
+
+async fn check_file(path: &Path, file: &ListedFile, client: &Client) -> Result<Option<Problem>> {
+    let Ok(meta) = metadata(path) else {
+        return Ok(Some(Problem::Missing));
+    };
+    if let Some(url) = file.urls.first() {
+        match client.content_length(url).await {
+            Ok(Some(expected)) if expected != meta.len() => {
+                return Ok(Some(Problem::Size {
+                    expected,
+                    actual: meta.len(),
+                }));
+            }
+            Ok(_) => {}
+            Err(err) => client.notify(&format!("Cannot get the size of {}: {}", url, err)),
+        }
+    }
+
+    if let Some(expected) = &file.md5 {
+        let owned = path.to_owned();
+        let actual = tokio::task::spawn_blocking(move || md5_file(&owned)).await??;
+        if &actual != expected {
+            return Ok(Some(Problem::Checksum {
+                expected: expected.clone(),
+                actual,
+            }));
+        }
+    }
+
+    if is_gzip(path) {
+        let owned = path.to_owned();
+        if let Err(err) = tokio::task::spawn_blocking(move || check_gzip(&owned)).await? {
+            return match err {
+                OneKpError::Corrupt(corrupt) => Ok(Some(Problem::Corrupt(corrupt.reason))),
+                err => Err(err),
+            };
+        }
+    }
+    Ok(None)
+}
+
+/// Files under `rootdir` other than the listed files, their sidecars, the metadata table and manifests.
+/// Hidden entries such as the state file are ignored.
+fn extraneous(rootdir: &Path, files: &[ListedFile]) -> Result<Vec<PathBuf>> {
+    let mut known: HashSet<PathBuf> = files
+        .iter()
+        .flat_map(|f| [f.path.clone(), sidecar_path(&f.path)])
+        .collect();
+    known.insert(PathBuf::from(METADATA_FILENAME));
+    for algorithm in ManifestAlgorithm::value_variants() {
+        known.insert(PathBuf::from(algorithm.filename()));
+    }
+
+    Ok(dataset_files(rootdir)?
+        .into_iter()
+        .filter(|path| !known.contains(path))
+        .collect())
+}
+
+/// Every file under `rootdir` relative to it, except hidden entries
+pub fn dataset_files(rootdir: &Path) -> Result<Vec<PathBuf>> {
+    let mut found = vec![];
+    let mut dirs = vec![rootdir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            found.push(path.strip_prefix(rootdir).unwrap_or(&path).to_owned());
+        }
+    }
+    found.sort();
+    Ok(found)
+}
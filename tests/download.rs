@@ -0,0 +1,222 @@
+//! Downloads against a mock [`Transport`], without network access
+
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, read, remove_dir_all, write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::{future::BoxFuture, FutureExt};
+use onekp::{
+    checksum::Checksums,
+    report::{FileStatus, SampleStatus},
+    transport::{Transport, TransportResponse},
+    Client, ClientOptions, Mirrors, OneKpError, OneKpRecord, SequenceType,
+};
+use reqwest::StatusCode;
+
+const PRIMARY: &str = "https://primary.example.org/";
+const MIRROR: &str = "https://mirror.example.org/";
+const PATH: &str = "assemblies/URDJ-Marchantia_polymorpha/URDJ-translated-protein.fa.gz";
+const FILENAME: &str = "URDJ-Marchantia_polymorpha-protein.fa.gz";
+const BODY: &[u8] = b">URDJ_1\nMSTRNQLLVAG\n>URDJ_2\nMKKLFEPRW\n";
+
+/// Serves `files` with `Range` support and the `errors` statuses, 404 for anything else,
+/// and records the requests as (URL, offset)
+#[derive(Debug, Default)]
+struct Mock {
+    files: HashMap<String, &'static [u8]>,
+    errors: HashMap<String, StatusCode>,
+    requests: Mutex<Vec<(String, u64)>>,
+}
+
+impl Mock {
+    fn serve(mut self, url: String, body: &'static [u8]) -> Self {
+        self.files.insert(url, body);
+        self
+    }
+
+    fn fail(mut self, url: String, status: StatusCode) -> Self {
+        self.errors.insert(url, status);
+        self
+    }
+
+    fn requests(&self) -> Vec<(String, u64)> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn respond(&self, url: &str, offset: u64) -> TransportResponse {
+        if let Some(status) = self.errors.get(url) {
+            return TransportResponse::new(*status, "");
+        }
+        match self.files.get(url) {
+            Some(body) if offset == 0 => TransportResponse::new(StatusCode::OK, *body),
+            Some(body) if offset < body.len() as u64 => {
+                TransportResponse::new(StatusCode::PARTIAL_CONTENT, &body[offset as usize..])
+            }
+            Some(_) => TransportResponse::new(StatusCode::RANGE_NOT_SATISFIABLE, ""),
+            None => TransportResponse::new(StatusCode::NOT_FOUND, ""),
+        }
+    }
+}
+
+impl Transport for Mock {
+    fn get<'a>(
+        &'a self,
+        url: &'a str,
+        offset: u64,
+    ) -> BoxFuture<'a, Result<TransportResponse, OneKpError>> {
+        self.requests.lock().unwrap().push((url.to_owned(), offset));
+        let resp = self.respond(url, offset);
+        async move { Ok(resp) }.boxed()
+    }
+
+    fn head<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<TransportResponse, OneKpError>> {
+        let resp = self.respond(url, 0);
+        async move { Ok(resp) }.boxed()
+    }
+}
+
+fn client(mock: &Arc<Mock>) -> Client {
+    let options = ClientOptions {
+        interval: Duration::ZERO,
+        max_retry: 2,
+        ..Default::default()
+    };
+    Client::new(&options).unwrap().with_transport(mock.clone())
+}
+
+fn mirrors() -> Mirrors {
+    // the mock answers 404 for the built-in mirrors, tried after these two
+    Mirrors::new(&[]).with_primary(MIRROR).with_primary(PRIMARY)
+}
+
+fn url(base: &str) -> String {
+    format!("{}{}", base, PATH)
+}
+
+fn record() -> OneKpRecord {
+    OneKpRecord {
+        id: "URDJ".to_owned(),
+        clade: "Liverworts".to_owned(),
+        order: "Marchantiales".to_owned(),
+        family: "Marchantiaceae".to_owned(),
+        species: "Marchantia polymorpha".to_owned(),
+        tissue_type: "thallus".to_owned(),
+        prefix: "URDJ-Marchantia_polymorpha".to_owned(),
+        source: None,
+        lineage: None,
+    }
+}
+
+/// Empty directory for the files of `test`
+fn dest(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("onekp-{}-{}", test, std::process::id()));
+    let _ = remove_dir_all(&dir);
+    create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn resumes_partial_download_with_range() {
+    let mock = Arc::new(Mock::default().serve(url(PRIMARY), BODY));
+    let dest = dest("resume");
+    write(dest.join(format!("{}.part", FILENAME)), &BODY[..10]).unwrap();
+
+    let report = onekp::fetch::fetch_sample(
+        &record(),
+        SequenceType::Protein,
+        &dest,
+        &client(&mock),
+        &mirrors(),
+        None,
+    )
+    .await;
+
+    assert_eq!(report.status, SampleStatus::Success, "{:?}", report.error);
+    assert_eq!(mock.requests(), [(url(PRIMARY), 10)]);
+    assert_eq!(report.files[0].bytes, BODY.len() as u64 - 10);
+    assert_eq!(read(dest.join(FILENAME)).unwrap(), BODY);
+    remove_dir_all(dest).unwrap();
+}
+
+#[tokio::test]
+async fn rejects_checksum_mismatch() {
+    let mock = Arc::new(Mock::default().serve(url(PRIMARY), BODY));
+    let dest = dest("checksum");
+    let checksums = Checksums::parse(&format!("{}  {}\n", "0".repeat(32), PATH));
+
+    let report = onekp::fetch::fetch_sample(
+        &record(),
+        SequenceType::Protein,
+        &dest,
+        &client(&mock),
+        &mirrors(),
+        Some(Arc::new(checksums)),
+    )
+    .await;
+
+    assert_eq!(report.status, SampleStatus::Failed);
+    assert_eq!(report.files[0].status, FileStatus::ChecksumMismatch);
+    assert!(report.has_checksum_mismatch());
+    // neither the bad file nor a partial one to resume from is kept
+    assert!(!dest.join(FILENAME).exists());
+    assert!(!dest.join(format!("{}.part", FILENAME)).exists());
+    remove_dir_all(dest).unwrap();
+}
+
+#[tokio::test]
+async fn maps_404_to_not_found_without_retrying() {
+    let mock = Arc::new(Mock::default());
+    let missing = url(PRIMARY);
+
+    let result = client(&mock).get(&missing).await;
+
+    assert!(matches!(result, Err(OneKpError::NotFound(_))));
+    assert_eq!(mock.requests(), [(missing, 0)]);
+}
+
+#[tokio::test]
+async fn falls_back_to_mirror() {
+    let mock = Arc::new(
+        Mock::default()
+            .fail(url(PRIMARY), StatusCode::INTERNAL_SERVER_ERROR)
+            .serve(url(MIRROR), BODY),
+    );
+    let dest = dest("mirror");
+
+    let report = onekp::fetch::fetch_sample(
+        &record(),
+        SequenceType::Protein,
+        &dest,
+        &client(&mock),
+        &mirrors(),
+        None,
+    )
+    .await;
+
+    assert_eq!(report.status, SampleStatus::Success, "{:?}", report.error);
+    // the primary is retried before giving up on it
+    assert_eq!(
+        mock.requests(),
+        [(url(PRIMARY), 0), (url(PRIMARY), 0), (url(MIRROR), 0)]
+    );
+    assert_eq!(read(dest.join(FILENAME)).unwrap(), BODY);
+    remove_dir_all(dest).unwrap();
+}
+
+#[tokio::test]
+async fn does_not_list_unavailable_sample() {
+    let mock = Arc::new(Mock::default());
+    let rec = OneKpRecord {
+        prefix: String::new(),
+        ..record()
+    };
+
+    let result = onekp::listing::list_files(&rec, &client(&mock), &mirrors()).await;
+
+    assert!(matches!(result, Err(OneKpError::Unavailable(_))));
+    assert!(mock.requests().is_empty());
+}
@@ -0,0 +1,122 @@
+//! Parsing and evaluation of `--query` expressions
+
+use onekp::{query::Query, OneKpRecord};
+
+fn record() -> OneKpRecord {
+    OneKpRecord {
+        id: "ABCD".to_owned(),
+        clade: "Mosses".to_owned(),
+        order: "Bryales".to_owned(),
+        family: "Bryaceae".to_owned(),
+        species: "Bryum argenteum".to_owned(),
+        tissue_type: "leaf".to_owned(),
+        prefix: "ABCD-Bryum_argenteum".to_owned(),
+        source: None,
+        lineage: None,
+    }
+}
+
+fn matches(query: &str) -> bool {
+    query.parse::<Query>().unwrap().matches(&record())
+}
+
+/// Position and message of the error of parsing `query`
+fn error(query: &str) -> (usize, String) {
+    let err = query.parse::<Query>().unwrap_err();
+    (err.position, err.message)
+}
+
+#[test]
+fn and_binds_tighter_than_or() {
+    // true || (false && false), not (true || false) && false
+    assert!(matches(
+        "clade == Mosses || clade == Ferns && tissue_type == root"
+    ));
+    assert!(!matches(
+        "clade == Ferns && tissue_type == leaf || clade == Ferns"
+    ));
+    // chains of the same operator
+    assert!(matches(
+        "clade == Mosses && order == Bryales && family == Bryaceae"
+    ));
+    assert!(matches("clade == A || clade == B || clade == Mosses"));
+}
+
+#[test]
+fn not_binds_tighter_than_and() {
+    // (!true) && false, not !(true && false)
+    assert!(!matches("!clade == Mosses && tissue_type == root"));
+    assert!(matches("!clade == Ferns && tissue_type == leaf"));
+    assert!(matches("!!clade == Mosses"));
+}
+
+#[test]
+fn parentheses_group() {
+    assert!(!matches(
+        "(clade == Mosses || clade == Ferns) && tissue_type == root"
+    ));
+    assert!(matches("!(clade == Mosses && tissue_type == root)"));
+    assert!(matches("((clade == Mosses))"));
+    assert!(matches("(clade==Mosses)&&(order!=Poales)"));
+}
+
+#[test]
+fn compares_whole_values_and_searches_regexes() {
+    assert!(!matches("species == Bryum"));
+    assert!(matches("species ~ Bryum"));
+    assert!(matches("species !~ '^Sphagnum'"));
+    assert!(matches("species != 'Sphagnum palustre'"));
+    // `-` and `_` are the same in column names
+    assert!(matches("tissue-type == leaf"));
+}
+
+#[test]
+fn quoted_values() {
+    assert!(matches(r#"species == "Bryum argenteum""#));
+    assert!(matches("species == 'Bryum argenteum'"));
+    // the other quote and operators are plain characters inside quotes
+    assert!(!matches(r#"species == "it's && (x)""#));
+    assert!(matches(r#"species != "a \"quoted\" value""#));
+    // regex escapes are kept
+    assert!(matches(r#"species ~ "^\w+ arg""#));
+    assert!(matches(r#"species ~ '^Bryum\sargenteum$'"#));
+}
+
+#[test]
+fn unknown_column() {
+    assert_eq!(
+        error("colour == red"),
+        (0, r#"unknown column "colour""#.to_owned())
+    );
+    assert_eq!(
+        error("clade == Mosses && colour == red"),
+        (19, r#"unknown column "colour""#.to_owned())
+    );
+}
+
+#[test]
+fn error_positions() {
+    assert_eq!(
+        error("clade = Mosses"),
+        (6, "expected ==, !=, ~ or !~".to_owned())
+    );
+    assert_eq!(error("clade == "), (9, "expected a value".to_owned()));
+    assert_eq!(
+        error(r#"species == "Bryum"#),
+        (11, "unterminated string".to_owned())
+    );
+    assert_eq!(error("(clade == Mosses"), (16, "expected )".to_owned()));
+    assert_eq!(
+        error("clade == Mosses)"),
+        (15, "unexpected input".to_owned())
+    );
+    assert_eq!(
+        error("clade == Mosses &&"),
+        (18, "expected a column name".to_owned())
+    );
+    // invalid regexes are reported at the value
+    assert_eq!(error("species ~ '('").0, 10);
+    // the message names the position
+    let err = "clade = Mosses".parse::<Query>().unwrap_err();
+    assert_eq!(err.to_string(), "expected ==, !=, ~ or !~ at position 6");
+}
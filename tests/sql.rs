@@ -0,0 +1,227 @@
+//! Parsing and running `onekp sql` statements
+
+use onekp::{
+    sql::{Select, Table},
+    OneKpRecord,
+};
+
+fn rec(id: &str, clade: &str, family: &str, species: &str, tissue_type: &str) -> OneKpRecord {
+    OneKpRecord {
+        id: id.to_owned(),
+        clade: clade.to_owned(),
+        order: "No data".to_owned(),
+        family: family.to_owned(),
+        species: species.to_owned(),
+        tissue_type: tissue_type.to_owned(),
+        prefix: id.to_owned(),
+        source: None,
+        lineage: None,
+    }
+}
+
+fn records() -> Vec<OneKpRecord> {
+    vec![
+        rec(
+            "AAAA",
+            "Mosses",
+            "Sphagnaceae",
+            "Sphagnum palustre",
+            "shoot",
+        ),
+        rec("BBBB", "Mosses", "Sphagnaceae", "Sphagnum recurvum", "leaf"),
+        rec("CCCC", "Mosses", "Bryaceae", "Bryum argenteum", "shoot"),
+        rec(
+            "DDDD",
+            "Liverworts",
+            "Marchantiaceae",
+            "Marchantia polymorpha",
+            "thallus",
+        ),
+        rec(
+            "EEEE",
+            "Liverworts",
+            "Ricciaceae",
+            "Riccia sp. [1]*",
+            "thallus",
+        ),
+        rec(
+            "FFFF",
+            "Hornworts",
+            "Anthocerotaceae",
+            "Anthoceros 50% agrestis",
+            "thallus",
+        ),
+    ]
+}
+
+fn run(sql: &str) -> Table {
+    sql.parse::<Select>()
+        .unwrap_or_else(|err| panic!("{}: {}", sql, err))
+        .run(&records())
+}
+
+/// IDs selected by `condition`
+fn ids(condition: &str) -> Vec<String> {
+    run(&format!("SELECT id FROM samples WHERE {}", condition))
+        .rows
+        .into_iter()
+        .map(|row| row[0].clone())
+        .collect()
+}
+
+/// Position and message of the error of parsing `sql`
+fn error(sql: &str) -> (usize, String) {
+    let err = sql.parse::<Select>().unwrap_err();
+    (err.position, err.message)
+}
+
+#[test]
+fn and_binds_tighter_than_or() {
+    // Hornworts OR (Mosses AND leaf), not (Hornworts OR Mosses) AND leaf
+    assert_eq!(
+        ids("clade = 'Hornworts' OR clade = 'Mosses' AND tissue_type = 'leaf'"),
+        ["BBBB", "FFFF"]
+    );
+    assert_eq!(
+        ids("(clade = 'Hornworts' OR clade = 'Mosses') AND tissue_type = 'leaf'"),
+        ["BBBB"]
+    );
+}
+
+#[test]
+fn not_binds_tighter_than_and() {
+    // (NOT Mosses) AND thallus, not NOT (Mosses AND thallus)
+    assert_eq!(
+        ids("NOT clade = 'Liverworts' AND tissue_type = 'thallus'"),
+        ["FFFF"]
+    );
+    assert_eq!(
+        ids("NOT (clade = 'Mosses' OR clade = 'Liverworts')"),
+        ["FFFF"]
+    );
+    assert_eq!(ids("clade != 'Mosses' AND clade <> 'Liverworts'"), ["FFFF"]);
+}
+
+#[test]
+fn in_lists() {
+    assert_eq!(
+        ids("family IN ('Bryaceae', 'Ricciaceae', 'Pottiaceae')"),
+        ["CCCC", "EEEE"]
+    );
+    assert_eq!(ids("clade NOT IN ('Mosses', 'Liverworts')"), ["FFFF"]);
+    assert_eq!(ids("id IN ('AAAA')"), ["AAAA"]);
+}
+
+#[test]
+fn like_patterns() {
+    assert_eq!(ids("species LIKE 'sphagnum%'"), ["AAAA", "BBBB"]);
+    assert_eq!(ids("species LIKE '%um'"), ["BBBB", "CCCC"]);
+    assert_eq!(ids("id LIKE '_A_A'"), ["AAAA"]);
+    assert_eq!(ids("species NOT LIKE '%a%'"), Vec::<String>::new());
+    // glob and regex characters are literal
+    assert_eq!(ids("species LIKE '%[1]*'"), ["EEEE"]);
+    assert_eq!(ids("species LIKE 'Riccia sp. [1]?'"), Vec::<String>::new());
+    assert_eq!(ids("species LIKE 'Riccia sp_ [1]_'"), ["EEEE"]);
+    assert_eq!(ids("species LIKE '%.%'"), ["EEEE"]);
+    // % in the value is matched by % or _
+    assert_eq!(ids("species LIKE '%50_ %'"), ["FFFF"]);
+}
+
+#[test]
+fn quoting() {
+    let records = [rec(
+        "GGGG",
+        "Ferns",
+        "Aspleniaceae",
+        "O'Brien's fern",
+        "leaf",
+    )];
+    let select: Select = "SELECT id FROM samples WHERE species = 'O''Brien''s fern'"
+        .parse()
+        .unwrap();
+    assert_eq!(select.run(&records).rows, [["GGGG"]]);
+    // keywords are strings in quotes, and case does not matter outside of them
+    assert_eq!(ids("clade = 'OR'"), Vec::<String>::new());
+    assert_eq!(
+        run("select ID from SAMPLES where CLADE = 'Hornworts';").rows,
+        [["FFFF"]]
+    );
+    assert_eq!(ids("clade = 'hornworts'"), Vec::<String>::new());
+}
+
+#[test]
+fn group_by_order_by_and_limit() {
+    assert_eq!(
+        run("SELECT clade, count(*) AS n, count(DISTINCT family) FROM samples GROUP BY clade ORDER BY n DESC, clade").to_tsv(),
+        "clade\tn\tcount(DISTINCT family)\n\
+         Mosses\t3\t2\n\
+         Liverworts\t2\t2\n\
+         Hornworts\t1\t1\n"
+    );
+    // by position and by expression, ascending by default
+    assert_eq!(
+        run("SELECT clade, count(*) FROM samples GROUP BY clade ORDER BY 2, 1").rows,
+        [["Hornworts", "1"], ["Liverworts", "2"], ["Mosses", "3"]]
+    );
+    assert_eq!(
+        run("SELECT clade, count(*) FROM samples GROUP BY clade ORDER BY count(*) DESC LIMIT 1")
+            .rows,
+        [["Mosses", "3"]]
+    );
+    // the clauses are accepted in any order
+    assert_eq!(
+        run("SELECT id FROM samples LIMIT 2 ORDER BY id DESC WHERE clade = 'Mosses'").rows,
+        [["CCCC"], ["BBBB"]]
+    );
+    assert_eq!(run("SELECT count(*) FROM samples").rows, [["6"]]);
+    assert_eq!(run("SELECT * FROM samples LIMIT 0").header.len(), 6);
+}
+
+#[test]
+fn error_messages() {
+    assert_eq!(
+        error("SELEC id FROM samples"),
+        (0, "expected SELECT".to_owned())
+    );
+    assert_eq!(error("SELECT id samples"), (10, "expected FROM".to_owned()));
+    assert_eq!(
+        error("SELECT id FROM species"),
+        (15, "the only table is samples".to_owned())
+    );
+    assert_eq!(
+        error("SELECT colour FROM samples"),
+        (7, r#"unknown column "colour""#.to_owned())
+    );
+    assert_eq!(
+        error("SELECT id FROM samples WHERE clade = 'Mosses"),
+        (37, "unterminated string".to_owned())
+    );
+    assert_eq!(
+        error("SELECT id FROM samples WHERE clade = Mosses"),
+        (37, "expected a string in single quotes".to_owned())
+    );
+    assert_eq!(
+        error("SELECT id FROM samples WHERE clade > 'A'"),
+        (35, "unexpected '>'".to_owned())
+    );
+    assert_eq!(
+        error("SELECT id FROM samples WHERE clade IN ('A', 'B'"),
+        (47, "expected )".to_owned())
+    );
+    assert_eq!(
+        error("SELECT id FROM samples WHERE (clade = 'A'"),
+        (41, "expected )".to_owned())
+    );
+    assert_eq!(
+        error("SELECT id FROM samples ORDER BY family"),
+        (32, "ORDER BY must name a selected column".to_owned())
+    );
+    assert_eq!(
+        error("SELECT id FROM samples LIMIT ten"),
+        (29, "expected a number".to_owned())
+    );
+    assert_eq!(
+        error("SELECT id FROM samples; DROP"),
+        (24, "unexpected input".to_owned())
+    );
+}